@@ -0,0 +1,299 @@
+use std::time::{Duration, Instant};
+
+use actix_web::{web, Error, HttpResponse};
+use log::error;
+use serde::{Deserialize, Serialize};
+use shared::database::{db_interface::DatabaseConnection, program_repository::ProgramRepository};
+use shared::models::program_version::ProgramVersion;
+use shared::storage::blob_store::{BlobStore, Storage};
+use similar::{ChangeTag, TextDiff};
+use utoipa::ToSchema;
+
+use super::version::parse_program_id;
+use crate::utils::error::database_error;
+
+/// Lines of unchanged context kept around each hunk.
+const CONTEXT_LINES: usize = 3;
+
+/// Largest version diffed, in bytes.
+const MAX_DIFF_SIZE: i64 = 1024 * 1024;
+
+/// Time spent looking for the smallest diff; past it the diff found so far,
+/// larger but still correct, is returned.
+const DIFF_DEADLINE: Duration = Duration::from_secs(2);
+
+const TEXT_CONTENT_TYPES: [&str; 10] = [
+    "application/json",
+    "application/javascript",
+    "application/x-javascript",
+    "application/x-python",
+    "application/x-python-code",
+    "application/x-sh",
+    "application/x-lua",
+    "application/x-rust",
+    "application/xml",
+    "application/x-yaml",
+];
+
+const TEXT_EXTENSIONS: [&str; 12] = [
+    "py", "rs", "js", "ts", "txt", "lua", "sh", "json", "md", "toml", "yaml", "yml",
+];
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    from: i32,
+    to: i32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct VersionDiff {
+    #[schema(example = 1)]
+    pub from: i32,
+    #[schema(example = 2)]
+    pub to: i32,
+    /// The whole diff in unified format, as `diff -u` would print it.
+    #[schema(example = "--- a/example.py\n+++ b/example.py\n@@ -1 +1 @@\n-print(1)\n+print(2)\n")]
+    pub unified: String,
+    pub hunks: Vec<DiffHunk>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DiffHunk {
+    #[schema(example = 1)]
+    pub old_start: usize,
+    #[schema(example = 1)]
+    pub old_lines: usize,
+    #[schema(example = 1)]
+    pub new_start: usize,
+    #[schema(example = 1)]
+    pub new_lines: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DiffLine {
+    /// One of `equal`, `insert` or `delete`.
+    #[schema(example = "insert")]
+    pub tag: String,
+    #[schema(example = "print(2)")]
+    pub content: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/content/{id}/diff",
+    tag = "content",
+    params(
+        ("id"=String, Path, description = "Content id"),
+        ("from"=i32, Query, description = "Version to diff from"),
+        ("to"=i32, Query, description = "Version to diff to"),
+    ),
+    responses(
+        (status = 200, description = "Diff between the two versions", body = VersionDiff),
+        (status = 404, description = "Version not found"),
+        (status = 415, description = "Content is not text"),
+        (status = 422, description = "A version is too large to diff"),
+    )
+)]
+pub async fn diff_versions(
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<Storage>,
+    id: web::Path<String>,
+    query: web::Query<DiffQuery>,
+) -> Result<HttpResponse, Error> {
+    let program_id = parse_program_id(&id)?;
+
+    let (from, to) = match (
+        db.find_version(&program_id, query.from)
+            .await
+            .map_err(database_error)?,
+        db.find_version(&program_id, query.to)
+            .await
+            .map_err(database_error)?,
+    ) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Ok(HttpResponse::NotFound().body("Version not found")),
+    };
+
+    if !is_text_like(&from) || !is_text_like(&to) {
+        return Ok(
+            HttpResponse::UnsupportedMediaType().body("Diffs are only available for text content")
+        );
+    }
+
+    if from.file_size > MAX_DIFF_SIZE || to.file_size > MAX_DIFF_SIZE {
+        return Ok(HttpResponse::UnprocessableEntity().body(format!(
+            "Diffs are only available for versions up to {} bytes",
+            MAX_DIFF_SIZE
+        )));
+    }
+
+    let old_text = read_text(&storage, &from).await?;
+    let new_text = read_text(&storage, &to).await?;
+    let (old_text, new_text) = match (old_text, new_text) {
+        (Some(old_text), Some(new_text)) => (old_text, new_text),
+        _ => {
+            return Ok(HttpResponse::UnsupportedMediaType()
+                .body("Diffs are only available for UTF-8 text content"))
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(build_diff(&from, &to, &old_text, &new_text)))
+}
+
+fn is_text_like(version: &ProgramVersion) -> bool {
+    let content_type = version
+        .content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    if content_type.starts_with("text/") || TEXT_CONTENT_TYPES.contains(&content_type.as_str()) {
+        return true;
+    }
+
+    // Browsers often send `application/octet-stream` for source files, so
+    // fall back on the extension.
+    version
+        .filename
+        .rsplit_once('.')
+        .map(|(_, extension)| TEXT_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+async fn read_text(storage: &Storage, version: &ProgramVersion) -> Result<Option<String>, Error> {
+    let data = storage.get(&version.file_path).await.map_err(|e| {
+        error!("Error reading {} from storage: {:?}", version.file_path, e);
+        actix_web::error::ErrorNotFound("File not found in storage")
+    })?;
+    Ok(String::from_utf8(data).ok())
+}
+
+fn build_diff(
+    from: &ProgramVersion,
+    to: &ProgramVersion,
+    old_text: &str,
+    new_text: &str,
+) -> VersionDiff {
+    let diff = TextDiff::configure()
+        .deadline(Instant::now() + DIFF_DEADLINE)
+        .diff_lines(old_text, new_text);
+
+    let unified = diff
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header(
+            &format!("a/{}", from.filename),
+            &format!("b/{}", to.filename),
+        )
+        .to_string();
+
+    let hunks = diff
+        .grouped_ops(CONTEXT_LINES)
+        .iter()
+        .filter_map(|group| {
+            let (first, last) = (group.first()?, group.last()?);
+            let old_range = first.old_range().start..last.old_range().end;
+            let new_range = first.new_range().start..last.new_range().end;
+
+            let lines = group
+                .iter()
+                .flat_map(|op| diff.iter_changes(op))
+                .map(|change| DiffLine {
+                    tag: match change.tag() {
+                        ChangeTag::Equal => "equal",
+                        ChangeTag::Insert => "insert",
+                        ChangeTag::Delete => "delete",
+                    }
+                    .to_string(),
+                    content: change.value().trim_end_matches(['\r', '\n']).to_string(),
+                })
+                .collect();
+
+            Some(DiffHunk {
+                old_start: old_range.start + 1,
+                old_lines: old_range.len(),
+                new_start: new_range.start + 1,
+                new_lines: new_range.len(),
+                lines,
+            })
+        })
+        .collect();
+
+    VersionDiff {
+        from: from.version,
+        to: to.version,
+        unified,
+        hunks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::oid::ObjectId;
+    use chrono::Utc;
+
+    fn version(version: i32) -> ProgramVersion {
+        let program_id = ObjectId::new();
+        ProgramVersion {
+            id: ObjectId::new(),
+            program_id,
+            version,
+            filename: "example.py".to_string(),
+            content_type: "text/x-python".to_string(),
+            file_hash: String::new(),
+            file_size: 0,
+            file_path: String::new(),
+            code_url: ProgramVersion::code_url_for(&program_id, version),
+            uploaded_by: 1,
+            upload_time: Utc::now(),
+            restorations: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_build_diff_numbers_hunks_from_one() {
+        let old_text = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let new_text = "1\n3\n4\n5\n6\n7\n8\n9\n9.5\n10\n";
+        let diff = build_diff(&version(1), &version(2), old_text, new_text);
+
+        assert_eq!(
+            diff.unified,
+            "--- a/example.py\n+++ b/example.py\n\
+             @@ -1,5 +1,4 @@\n 1\n-2\n 3\n 4\n 5\n\
+             @@ -7,4 +6,5 @@\n 7\n 8\n 9\n+9.5\n 10\n"
+        );
+        let ranges: Vec<_> = diff
+            .hunks
+            .iter()
+            .map(|hunk| {
+                (
+                    hunk.old_start,
+                    hunk.old_lines,
+                    hunk.new_start,
+                    hunk.new_lines,
+                )
+            })
+            .collect();
+        assert_eq!(ranges, vec![(1, 5, 1, 4), (7, 4, 6, 5)]);
+        let tags: Vec<_> = diff.hunks[0]
+            .lines
+            .iter()
+            .map(|line| (line.tag.as_str(), line.content.as_str()))
+            .collect();
+        assert_eq!(
+            tags,
+            vec![
+                ("equal", "1"),
+                ("delete", "2"),
+                ("equal", "3"),
+                ("equal", "4"),
+                ("equal", "5")
+            ]
+        );
+        assert_eq!(diff.hunks[1].lines[3].tag, "insert");
+        assert_eq!(diff.hunks[1].lines[3].content, "9.5");
+    }
+}
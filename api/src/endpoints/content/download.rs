@@ -0,0 +1,92 @@
+use actix_web::{web, Error, HttpResponse};
+use bson::oid::ObjectId;
+use log::warn;
+use shared::database::{db_interface::DatabaseConnection, program_repository::ProgramRepository};
+use shared::storage::blob_store::Storage;
+
+use crate::utils::download::serve_blob;
+use crate::utils::error::database_error;
+
+#[utoipa::path(
+    get,
+    path = "/content/{id}/raw",
+    tag = "content",
+    params(("id"=String, Path, description = "Download the file of a Content by id")),
+    responses(
+        (status = 200, description = "Raw file content, from the local disk", content_type = "application/octet-stream"),
+        (status = 307, description = "Redirect to a short-lived signed URL of the storage backend"),
+        (status = 404, description = "Content not found"),
+    )
+)]
+pub async fn download_raw(
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<Storage>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let object_id = match ObjectId::parse_str(id.as_ref().trim()) {
+        Ok(oid) => oid,
+        Err(e) => {
+            warn!("Invalid ID format: {}", e);
+            return Err(actix_web::error::ErrorBadRequest("Invalid ID format"));
+        }
+    };
+
+    let program = match db.find_program(&object_id).await.map_err(database_error)? {
+        Some(program) => program,
+        None => return Ok(HttpResponse::NotFound().body("Content not found")),
+    };
+
+    serve_blob(
+        &storage,
+        &program.file_path,
+        &program.content_type,
+        program.filename,
+    )
+    .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/content/{id}/versions/{version}/raw",
+    tag = "content",
+    params(
+        ("id"=String, Path, description = "Content id"),
+        ("version"=i32, Path, description = "Version number, starting at 1"),
+    ),
+    responses(
+        (status = 200, description = "Raw file of the version, from the local disk", content_type = "application/octet-stream"),
+        (status = 307, description = "Redirect to a short-lived signed URL of the storage backend"),
+        (status = 404, description = "Version not found"),
+    )
+)]
+pub async fn download_version_raw(
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<Storage>,
+    path: web::Path<(String, i32)>,
+) -> Result<HttpResponse, Error> {
+    let (id, version_number) = path.into_inner();
+    let object_id = match ObjectId::parse_str(id.trim()) {
+        Ok(oid) => oid,
+        Err(e) => {
+            warn!("Invalid ID format: {}", e);
+            return Err(actix_web::error::ErrorBadRequest("Invalid ID format"));
+        }
+    };
+
+    let version = match db
+        .find_version(&object_id, version_number)
+        .await
+        .map_err(database_error)?
+    {
+        Some(version) => version,
+        None => return Ok(HttpResponse::NotFound().body("Version not found")),
+    };
+
+    serve_blob(
+        &storage,
+        &version.file_path,
+        &version.content_type,
+        version.filename,
+    )
+    .await
+}
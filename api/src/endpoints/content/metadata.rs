@@ -1,14 +1,12 @@
-use crate::{
-    endpoints::content::update_program_dto::UpdateProgramDto,
-    utils::firebase::delete_file_from_firebase,
-};
 use actix_web::{web, Error, HttpResponse};
-use bson::{oid::ObjectId, Document};
-use futures::StreamExt;
+use bson::oid::ObjectId;
 use log::{info, warn};
-use mongodb::{bson::doc, Collection, Database};
-use reqwest::Client;
-use shared::models::program::Program;
+use serde::Deserialize;
+use shared::database::{db_interface::DatabaseConnection, program_repository::ProgramRepository};
+use shared::models::program::UpdateProgramDto;
+use shared::storage::blob_store::{BlobStore, Storage};
+
+use crate::utils::error::database_error;
 
 #[utoipa::path(
     get,
@@ -21,11 +19,9 @@ use shared::models::program::Program;
     )
 )]
 pub async fn get_details(
-    db: web::Data<Database>,
+    db: web::Data<DatabaseConnection>,
     id: web::Path<String>,
 ) -> Result<HttpResponse, Error> {
-    let collection: Collection<Program> = db.collection("programs");
-
     let id_str = id.as_ref().trim();
     info!("Raw ID string: {}", id_str);
 
@@ -39,14 +35,9 @@ pub async fn get_details(
 
     info!("Parsed ObjectId: {}", object_id);
 
-    let result = collection.find_one(doc! {"_id": object_id}, None).await;
-    match result {
-        Ok(Some(program)) => Ok(HttpResponse::Ok().json(program)),
-        Ok(None) => Ok(HttpResponse::NotFound().finish()),
-        Err(e) => Err(actix_web::error::ErrorInternalServerError(format!(
-            "Database query failed: {}",
-            e
-        ))),
+    match db.find_program(&object_id).await.map_err(database_error)? {
+        Some(program) => Ok(HttpResponse::Ok().json(program)),
+        None => Ok(HttpResponse::NotFound().finish()),
     }
 }
 
@@ -61,48 +52,60 @@ pub async fn get_details(
     )
 )]
 pub async fn get_contents_by_owner(
-    db: web::Data<Database>,
+    db: web::Data<DatabaseConnection>,
     owner_id: web::Path<i32>,
 ) -> Result<HttpResponse, Error> {
-    let collection: Collection<Program> = db.collection("programs");
-
     let owner_id_value = owner_id.into_inner();
     log::info!("Searching for programs with owner_id: {}", owner_id_value);
 
-    let filter = doc! {"owner_id": owner_id_value};
-    let result = collection.find(filter, None).await;
-
-    match result {
-        Ok(cursor) => {
-            let programs: Vec<Program> = cursor
-                .filter_map(|item| async move {
-                    match item {
-                        Ok(program) => {
-                            log::info!("Found program: {:?}", program);
-                            Some(program)
-                        }
-                        Err(e) => {
-                            log::error!("Error reading program: {}", e);
-                            None
-                        }
-                    }
-                })
-                .collect()
-                .await;
-
-            if programs.is_empty() {
-                log::warn!("No programs found for owner_id: {}", owner_id_value);
-            }
-            Ok(HttpResponse::Ok().json(programs))
-        }
-        Err(e) => {
-            log::error!("Database query failed: {}", e);
-            Err(actix_web::error::ErrorInternalServerError(format!(
-                "Database query failed: {}",
-                e
-            )))
-        }
+    let programs = db
+        .find_programs_by_owner(owner_id_value)
+        .await
+        .map_err(database_error)?;
+
+    if programs.is_empty() {
+        log::warn!("No programs found for owner_id: {}", owner_id_value);
     }
+    Ok(HttpResponse::Ok().json(programs))
+}
+
+#[derive(Deserialize)]
+pub struct HashQuery {
+    owner_id: Option<i32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/content/by-hash/{sha256}",
+    tag = "content",
+    params(
+        ("sha256"=String, Path, description = "Hex encoded SHA-256 of the file content"),
+        ("owner_id"=Option<i32>, Query, description = "Only return contents of this owner"),
+    ),
+    responses(
+        (status = 200, description = "Contents with this hash", body = Vec<Program>),
+        (status = 400, description = "Invalid hash format"),
+    )
+)]
+pub async fn get_contents_by_hash(
+    db: web::Data<DatabaseConnection>,
+    sha256: web::Path<String>,
+    query: web::Query<HashQuery>,
+) -> Result<HttpResponse, Error> {
+    let file_hash = sha256.trim().to_lowercase();
+    if file_hash.len() != 64 || !file_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid SHA-256 format"));
+    }
+
+    let programs = db
+        .find_programs_by_hash(&file_hash, query.owner_id)
+        .await
+        .map_err(database_error)?;
+
+    if programs.is_empty() {
+        log::info!("No programs found with hash: {}", file_hash);
+    }
+    Ok(HttpResponse::Ok().json(programs))
 }
 
 #[utoipa::path(
@@ -120,34 +123,24 @@ pub async fn get_contents_by_owner(
     )
 )]
 pub async fn update_metadata(
-    db: web::Data<Database>,
+    db: web::Data<DatabaseConnection>,
     id: web::Path<String>,
     update_dto: web::Json<UpdateProgramDto>,
 ) -> Result<HttpResponse, Error> {
-    let collection = db.collection::<Program>("programs");
-    let object_id = match ObjectId::parse_str(&id.as_ref()) {
+    let object_id = match ObjectId::parse_str(id.as_ref()) {
         Ok(oid) => oid,
         Err(_) => return Err(actix_web::error::ErrorBadRequest("Invalid ID format")),
     };
-    let update_doc = update_dto.build_update_document();
-    let update_command = doc! {
-        "$set": update_doc,
-        "$currentDate": {"update_time": true}
-    };
 
-    let update_result = collection
-        .update_one(doc! {"_id": object_id}, update_command, None)
-        .await;
+    let updated = db
+        .update_program_metadata(&object_id, &update_dto)
+        .await
+        .map_err(database_error)?;
 
-    match update_result {
-        Ok(update) if update.matched_count == 1 => {
-            Ok(HttpResponse::Ok().body("Content metadata updated"))
-        }
-        Ok(_) => Ok(HttpResponse::NotFound().body("Content not found")),
-        Err(e) => Err(actix_web::error::ErrorInternalServerError(format!(
-            "Database operation failed: {}",
-            e
-        ))),
+    if updated {
+        Ok(HttpResponse::Ok().body("Content metadata updated"))
+    } else {
+        Ok(HttpResponse::NotFound().body("Content not found"))
     }
 }
 
@@ -161,44 +154,55 @@ pub async fn update_metadata(
         (status = 404, description = "Content not found"),
     )
 )]
-pub async fn delete(db: web::Data<Database>, id: web::Path<String>) -> Result<HttpResponse, Error> {
-    let collection = db.collection::<Document>("programs");
-    let object_id = match ObjectId::parse_str(&id.as_ref()) {
+pub async fn delete(
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<Storage>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let object_id = match ObjectId::parse_str(id.as_ref()) {
         Ok(oid) => oid,
         Err(_) => return Err(actix_web::error::ErrorBadRequest("Invalid ID format")),
     };
-    let firebase_bucket =
-        std::env::var("FIREBASE_STORAGE_BUCKET").expect("FIREBASE_STORAGE_BUCKET must be set");
-    let client = Client::new();
 
-    let existing_file = collection
-        .find_one(doc! { "_id": object_id }, None)
+    let program = match db.find_program(&object_id).await.map_err(database_error)? {
+        Some(program) => program,
+        None => return Ok(HttpResponse::NotFound().body("Content not found")),
+    };
+
+    let mut file_paths = vec![program.file_path];
+    let versions = db.find_versions(&object_id).await.map_err(database_error)?;
+    for version in versions {
+        if !file_paths.contains(&version.file_path) {
+            file_paths.push(version.file_path);
+        }
+    }
+
+    // The records go first: once they are gone nothing can reach the blobs,
+    // so a failure below only leaves garbage in storage behind.
+    if !db
+        .delete_program(&object_id)
         .await
-        .map_err(|e| {
-            log::error!("Database error: {:?}", e);
-            actix_web::error::ErrorInternalServerError("Database error")
-        })?;
-
-    if let Some(file) = existing_file {
-        let file_path = file.get_str("file_path").map_err(|e| {
-            log::error!("Error getting file_path: {:?}", e);
-            actix_web::error::ErrorInternalServerError("Error getting file_path")
-        })?;
-
-        delete_file_from_firebase(&client, &firebase_bucket, file_path).await?;
-
-        let delete_result = collection.delete_one(doc! { "_id": object_id }, None).await;
-        match delete_result {
-            Ok(delete) if delete.deleted_count == 1 => {
-                Ok(HttpResponse::Ok().body("Content deleted"))
+        .map_err(database_error)?
+    {
+        return Ok(HttpResponse::NotFound().body("Content not found"));
+    }
+    db.delete_versions(&object_id)
+        .await
+        .map_err(database_error)?;
+
+    // Deduplicated uploads let several programs point at the same blob; it
+    // may only be removed from storage once nothing else references it.
+    for file_path in &file_paths {
+        match db.is_blob_shared(file_path, &object_id).await {
+            Ok(true) => {}
+            Ok(false) => {
+                if let Err(e) = storage.delete(file_path).await {
+                    warn!("Error deleting {} from storage: {:?}", file_path, e);
+                }
             }
-            Ok(_) => Ok(HttpResponse::NotFound().body("Content not found")),
-            Err(e) => Err(actix_web::error::ErrorInternalServerError(format!(
-                "Database operation failed: {}",
-                e
-            ))),
+            Err(e) => warn!("Keeping {} in storage: {:?}", file_path, e),
         }
-    } else {
-        Ok(HttpResponse::NotFound().body("Content not found"))
     }
+
+    Ok(HttpResponse::Ok().body("Content deleted"))
 }
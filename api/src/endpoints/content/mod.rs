@@ -1,6 +1,9 @@
+pub mod diff;
+pub mod download;
 pub mod metadata;
+pub mod resumable;
+pub mod run;
 pub mod upload;
 pub mod version;
 
 pub mod routes;
-pub mod update_program_dto;
@@ -0,0 +1,335 @@
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use bson::oid::ObjectId;
+use bytes::Bytes;
+use futures::StreamExt;
+use log::{error, info, warn};
+use shared::database::{
+    db_interface::DatabaseConnection, upload_session_repository::UploadSessionRepository,
+};
+use shared::models::upload_session::{CreateUploadSession, UploadPart, UploadSession};
+use shared::storage::blob_store::{BlobStore, Storage};
+use shared::storage::upload_expiry::delete_parts;
+
+use super::upload::{normalize_output_extension, update};
+use crate::utils::error::{database_error, UploadError};
+use crate::utils::spool::{max_upload_size, spool};
+
+const UPLOAD_OFFSET: &str = "Upload-Offset";
+const UPLOAD_LENGTH: &str = "Upload-Length";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+#[utoipa::path(
+    post,
+    path = "/content/uploads",
+    tag = "content",
+    request_body(
+        content_type = "application/json",
+        content = CreateUploadSession
+    ),
+    responses(
+        (status = 201, description = "Upload session created", body = UploadSession),
+        (status = 400, description = "Bad Request"),
+        (status = 413, description = "Announced length exceeds the maximum upload size"),
+    )
+)]
+pub async fn create_upload(
+    db: web::Data<DatabaseConnection>,
+    create: web::Json<CreateUploadSession>,
+) -> Result<HttpResponse, Error> {
+    let mut create = create.into_inner();
+
+    if create.filename.trim().is_empty() {
+        return Err(UploadError::BadRequest("No filename provided".into()).into());
+    }
+    if create.upload_length < 0 {
+        return Err(UploadError::BadRequest("Invalid upload_length".into()).into());
+    }
+    let max_size = max_upload_size();
+    if create.upload_length as u64 > max_size {
+        return Err(UploadError::PayloadTooLarge(max_size).into());
+    }
+    create.output_extension = Some(normalize_output_extension(
+        create.output_extension.as_deref().unwrap_or_default(),
+    ));
+
+    let session: UploadSession = create.into();
+    db.insert_upload_session(&session).await.map_err(|e| {
+        error!("Insert error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Insert error")
+    })?;
+    info!(
+        "Created upload session {} for {} ({} bytes)",
+        session.id, session.filename, session.upload_length
+    );
+
+    Ok(HttpResponse::Created()
+        .insert_header((
+            header::LOCATION,
+            format!("/v1/content/uploads/{}", session.id),
+        ))
+        .insert_header((UPLOAD_OFFSET, session.offset))
+        .insert_header((UPLOAD_LENGTH, session.upload_length))
+        .json(session))
+}
+
+#[utoipa::path(
+    head,
+    path = "/content/uploads/{id}",
+    tag = "content",
+    params(("id"=String, Path, description = "Upload session id")),
+    responses(
+        (status = 200, description = "Current offset in the Upload-Offset header"),
+        (status = 404, description = "Upload session not found"),
+    )
+)]
+pub async fn get_upload_offset(
+    db: web::Data<DatabaseConnection>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let session = match find_session(&db, &id).await? {
+        Some(session) => session,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header((UPLOAD_OFFSET, session.offset))
+        .insert_header((UPLOAD_LENGTH, session.upload_length))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .finish())
+}
+
+#[utoipa::path(
+    patch,
+    path = "/content/uploads/{id}",
+    tag = "content",
+    params(
+        ("id"=String, Path, description = "Upload session id"),
+        ("Upload-Offset"=i64, Header, description = "Offset of the first byte of the chunk"),
+    ),
+    request_body(
+        content_type = "application/offset+octet-stream",
+        content = Vec<u8>
+    ),
+    responses(
+        (status = 204, description = "Chunk stored, new offset in the Upload-Offset header"),
+        (status = 404, description = "Upload session not found"),
+        (status = 409, description = "Upload-Offset does not match the current offset"),
+        (status = 413, description = "Chunk goes past the announced length"),
+        (status = 415, description = "Content-Type is not application/offset+octet-stream"),
+    )
+)]
+pub async fn append_chunk(
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<Storage>,
+    id: web::Path<String>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> Result<HttpResponse, Error> {
+    let is_offset_stream = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value == OFFSET_CONTENT_TYPE);
+    if !is_offset_stream {
+        return Ok(HttpResponse::UnsupportedMediaType()
+            .body(format!("Content-Type must be {}", OFFSET_CONTENT_TYPE)));
+    }
+
+    let offset = req
+        .headers()
+        .get(UPLOAD_OFFSET)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i64>().ok())
+        .ok_or_else(|| UploadError::BadRequest("Missing or invalid Upload-Offset".into()))?;
+
+    let session = match find_session(&db, &id).await? {
+        Some(session) if session.status == "open" => session,
+        Some(_) => return Ok(HttpResponse::Gone().body("Upload session already finalized")),
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if offset != session.offset {
+        return Ok(HttpResponse::Conflict()
+            .insert_header((UPLOAD_OFFSET, session.offset))
+            .finish());
+    }
+
+    let remaining = (session.upload_length - session.offset) as u64;
+    let chunk = spool(payload, remaining).await?;
+    if chunk.size == 0 {
+        return Ok(no_content_with_offset(session.offset));
+    }
+
+    let part = UploadPart {
+        key: format!("uploads/{}/{:020}", session.id, offset),
+        offset,
+        size: chunk.size as i64,
+    };
+    storage
+        .put_stream(
+            &part.key,
+            "application/octet-stream",
+            chunk.size,
+            chunk.stream().await?,
+        )
+        .await
+        .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))?;
+
+    let new_offset = offset + part.size;
+    let appended = db
+        .append_upload_part(&session.id, offset, &part)
+        .await
+        .map_err(|e| {
+            error!("Update error: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Update error")
+        })?;
+
+    if !appended {
+        warn!(
+            "Concurrent chunk for upload {} at offset {}",
+            session.id, offset
+        );
+        if let Err(e) = storage.delete(&part.key).await {
+            warn!("Error deleting orphaned part {}: {:?}", part.key, e);
+        }
+        return Ok(HttpResponse::Conflict().finish());
+    }
+
+    Ok(no_content_with_offset(new_offset))
+}
+
+#[utoipa::path(
+    post,
+    path = "/content/uploads/{id}/finalize",
+    tag = "content",
+    params(("id"=String, Path, description = "Upload session id")),
+    responses(
+        (status = 201, description = "Content uploaded successfully", body = String),
+        (status = 404, description = "Upload session not found"),
+        (status = 409, description = "Upload is not complete yet, or is being finalized by another request"),
+    )
+)]
+pub async fn finalize_upload(
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<Storage>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let session = match find_session(&db, &id).await? {
+        Some(session) if session.status == "open" => session,
+        Some(session) if session.status == "finalizing" => {
+            return Ok(HttpResponse::Conflict().body("Upload session is already being finalized"))
+        }
+        Some(_) => return Ok(HttpResponse::Gone().body("Upload session already finalized")),
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if session.offset != session.upload_length {
+        return Ok(HttpResponse::Conflict()
+            .insert_header((UPLOAD_OFFSET, session.offset))
+            .body("Upload is not complete yet"));
+    }
+
+    // Only one request turns the session into a program; chunks are no
+    // longer accepted from here on.
+    let claimed = db
+        .set_upload_session_status(&session.id, "open", "finalizing")
+        .await
+        .map_err(database_error)?;
+    if !claimed {
+        return Ok(HttpResponse::Conflict().body("Upload session is already being finalized"));
+    }
+    let result = finalize(&db, &storage, &session).await;
+    let (from, to) = match &result {
+        Ok(response) if response.status().is_success() => ("finalizing", "completed"),
+        // Let the client try again.
+        _ => ("finalizing", "open"),
+    };
+    db.set_upload_session_status(&session.id, from, to)
+        .await
+        .map_err(database_error)?;
+    if to == "completed" {
+        delete_parts(&storage, &session).await;
+        info!("Upload session {} finalized", session.id);
+    }
+    result
+}
+
+#[utoipa::path(
+    delete,
+    path = "/content/uploads/{id}",
+    tag = "content",
+    params(("id"=String, Path, description = "Upload session id")),
+    responses(
+        (status = 204, description = "Upload session discarded"),
+        (status = 404, description = "Upload session not found"),
+    )
+)]
+pub async fn cancel_upload(
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<Storage>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let session = match find_session(&db, &id).await? {
+        Some(session) => session,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    delete_parts(&storage, &session).await;
+    db.delete_upload_session(&session.id)
+        .await
+        .map_err(database_error)?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/* Private helper functions */
+async fn find_session(db: &DatabaseConnection, id: &str) -> Result<Option<UploadSession>, Error> {
+    let object_id = match ObjectId::parse_str(id.trim()) {
+        Ok(oid) => oid,
+        Err(e) => {
+            warn!("Invalid ID format: {}", e);
+            return Err(actix_web::error::ErrorBadRequest("Invalid ID format"));
+        }
+    };
+
+    db.find_upload_session(&object_id)
+        .await
+        .map_err(database_error)
+}
+
+/// Reassembles the parts on disk, which also computes the hash of the whole
+/// file, then hands it over to the regular upload path.
+async fn finalize(
+    db: &web::Data<DatabaseConnection>,
+    storage: &Storage,
+    session: &UploadSession,
+) -> Result<HttpResponse, Error> {
+    let part_storage = storage.clone();
+    let parts = futures::stream::iter(session.parts.clone()).then(move |part| {
+        let storage = part_storage.clone();
+        async move {
+            storage
+                .get(&part.key)
+                .await
+                .map(Bytes::from)
+                .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))
+        }
+    });
+    let file = spool(Box::pin(parts), session.upload_length as u64).await?;
+
+    update(
+        session.owner_id,
+        file,
+        &session.filename,
+        &session.content_type,
+        &session.output_extension,
+        db.clone(),
+        storage,
+    )
+    .await
+}
+
+fn no_content_with_offset(offset: i64) -> HttpResponse {
+    HttpResponse::NoContent()
+        .insert_header((UPLOAD_OFFSET, HeaderValue::from(offset)))
+        .finish()
+}
@@ -1,21 +1,49 @@
 use actix_web::web;
 
-use super::{metadata, upload, version};
+use super::{diff, download, metadata, resumable, run, upload, version};
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/content")
             .route("/upload", web::post().to(upload::upload))
+            .route("/uploads", web::post().to(resumable::create_upload))
+            .route(
+                "/uploads/{id}",
+                web::head().to(resumable::get_upload_offset),
+            )
+            .route("/uploads/{id}", web::patch().to(resumable::append_chunk))
+            .route("/uploads/{id}", web::delete().to(resumable::cancel_upload))
+            .route(
+                "/uploads/{id}/finalize",
+                web::post().to(resumable::finalize_upload),
+            )
             .route(
                 "/owner/{id}",
                 web::get().to(metadata::get_contents_by_owner),
             )
+            .route(
+                "/by-hash/{sha256}",
+                web::get().to(metadata::get_contents_by_hash),
+            )
             .route("/{id}", web::delete().to(metadata::delete))
+            .route("/{id}/raw", web::get().to(download::download_raw))
+            .route("/{id}/run", web::post().to(run::run_program))
             .route("/{id}", web::get().to(metadata::get_details))
             .route("/{id}", web::put().to(metadata::update_metadata))
             .route("/{id}", web::delete().to(metadata::delete))
-            .route("/versions", web::get().to(version::list_versions))
-            .route("/versions", web::post().to(version::create_version))
-            .route("/versions/{id}", web::get().to(version::get_version)),
+            .route("/{id}/diff", web::get().to(diff::diff_versions))
+            .route("/{id}/versions", web::get().to(version::list_versions))
+            .route(
+                "/{id}/versions/{version}",
+                web::get().to(version::get_version),
+            )
+            .route(
+                "/{id}/versions/{version}/raw",
+                web::get().to(download::download_version_raw),
+            )
+            .route(
+                "/{id}/versions/{version}/restore",
+                web::post().to(version::restore_version),
+            ),
     );
 }
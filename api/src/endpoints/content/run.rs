@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use actix_multipart::Multipart;
+use actix_web::http::header::{self, ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use bson::oid::ObjectId;
+use futures::StreamExt;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use shared::database::{db_interface::DatabaseConnection, program_repository::ProgramRepository};
+use shared::execution::runner::{self, Limits, RunOutput};
+use shared::execution::{media_type, sandbox::Sandbox};
+use shared::storage::blob_store::{BlobStore, Storage};
+use utoipa::ToSchema;
+
+use crate::utils::error::{database_error, sandbox_error};
+use crate::utils::spool::{max_upload_size, process_file_field, spool};
+
+/// How a program run on its own went.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProgramRun {
+    #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
+    pub program_id: String,
+
+    /// `success` when the program exited with 0, `failed` otherwise.
+    #[schema(example = "success")]
+    pub status: String,
+
+    /// `None` when the program was killed.
+    #[schema(example = 0)]
+    pub exit_code: Option<i32>,
+
+    pub timed_out: bool,
+
+    /// Killed for writing more than `EXECUTION_OUTPUT_LIMIT`.
+    pub output_exceeded: bool,
+
+    #[schema(example = 42)]
+    pub duration_ms: u64,
+
+    /// Media type of the stdout, from the program's `output_type`.
+    #[schema(example = "text/plain")]
+    pub content_type: String,
+
+    #[schema(example = 12)]
+    pub stdout_size: u64,
+
+    /// `None` when the stdout is not text; ask for `format=file` to get it.
+    #[schema(example = "hello world\n")]
+    pub stdout: Option<String>,
+
+    #[schema(example = "")]
+    pub stderr: String,
+}
+
+#[derive(Deserialize)]
+pub struct RunQuery {
+    /// `json` (the default) or `file`.
+    format: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/content/{id}/run",
+    tag = "content",
+    params(
+        ("id"=String, Path, description = "Run a Content by id"),
+        ("format"=Option<String>, Query, description = "`json` (the default) for the whole result, `file` for the stdout alone, typed by the program's output_type"),
+    ),
+    request_body(
+        content_type = "text/plain",
+        content = String,
+        description = "Given to the program on stdin; send multipart/form-data with an `input` file instead to upload a file"
+    ),
+    responses(
+        (status = 200, description = "The program ran; with format=file, its stdout with the exit code in X-Exit-Code", body = ProgramRun),
+        (status = 400, description = "Invalid ID format or format"),
+        (status = 404, description = "Content not found"),
+        (status = 413, description = "Input larger than MAX_UPLOAD_SIZE"),
+        (status = 422, description = "With format=file, the program failed", body = ProgramRun),
+        (status = 503, description = "Programs cannot run on this server, see EXECUTION_SANDBOX, or EXECUTION_CONCURRENCY programs are already running"),
+    )
+)]
+pub async fn run_program(
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<Storage>,
+    id: web::Path<String>,
+    query: web::Query<RunQuery>,
+    request: HttpRequest,
+    payload: web::Payload,
+) -> Result<HttpResponse, Error> {
+    let object_id = match ObjectId::parse_str(id.as_ref().trim()) {
+        Ok(oid) => oid,
+        Err(e) => {
+            warn!("Invalid ID format: {}", e);
+            return Err(actix_web::error::ErrorBadRequest("Invalid ID format"));
+        }
+    };
+    let as_file = match query.format.as_deref() {
+        None | Some("json") => false,
+        Some("file") => true,
+        Some(other) => {
+            return Err(actix_web::error::ErrorBadRequest(format!(
+                "Unknown format {:?}, expected json or file",
+                other
+            )))
+        }
+    };
+    let sandbox = Sandbox::shared().await.map_err(sandbox_error)?;
+
+    let input = read_input(&request, payload).await?;
+
+    let program = match db.find_program(&object_id).await.map_err(database_error)? {
+        Some(program) => program,
+        None => return Ok(HttpResponse::NotFound().body("Content not found")),
+    };
+    let code = storage.get(&program.file_path).await.map_err(|e| {
+        error!("Error reading {} from storage: {:?}", program.file_path, e);
+        actix_web::error::ErrorNotFound("File not found in storage")
+    })?;
+
+    // Taken once the input is in, so that a slow upload holds no slot.
+    let Ok(_permit) = runner::slots().try_acquire() else {
+        warn!(
+            "Refusing to run {}: too many programs running",
+            program.filename
+        );
+        return Ok(HttpResponse::ServiceUnavailable()
+            .insert_header((header::RETRY_AFTER, "1"))
+            .body("Too many programs running, try again later"));
+    };
+
+    info!("Running {} on its own", program.filename);
+    let started = Instant::now();
+    let output = runner::run(
+        sandbox,
+        &program.filename,
+        &code,
+        &input,
+        &BTreeMap::new(),
+        &Limits::from_env(),
+        |_| {},
+        // A client going away drops this future, which kills the program
+        // and everything it started.
+        std::future::pending(),
+    )
+    .await
+    .map_err(|e| actix_web::error::ErrorInternalServerError(e.to_string()))?;
+
+    let content_type = media_type::content_type(&program.output_type);
+    let result = program_run(
+        program.id.to_hex(),
+        &output,
+        started.elapsed().as_millis() as u64,
+        content_type.clone(),
+    );
+
+    if !as_file {
+        return Ok(HttpResponse::Ok().json(result));
+    }
+    if !output.success() {
+        return Ok(HttpResponse::UnprocessableEntity().json(result));
+    }
+    let stem = program
+        .filename
+        .rsplit_once('.')
+        .map_or(program.filename.as_str(), |(stem, _)| stem);
+    let filename = match media_type::extension(&content_type) {
+        Some(extension) => format!("{}-output.{}", stem, extension),
+        None => format!("{}-output", stem),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("X-Exit-Code", "0"))
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename)],
+        })
+        .body(output.stdout))
+}
+
+/* Private helper functions */
+
+/// The stdin of the program: the `input` file of a multipart form, or the
+/// request body as is.
+async fn read_input(request: &HttpRequest, payload: web::Payload) -> Result<Vec<u8>, Error> {
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !content_type.starts_with("multipart/form-data") {
+        return spool(payload, max_upload_size()).await?.read().await;
+    }
+
+    let mut form = Multipart::new(request.headers(), payload);
+    while let Some(item) = form.next().await {
+        let field = item?;
+        if field.name() == "input" {
+            let (_, _, data) = process_file_field(field).await?;
+            return data.read().await;
+        }
+    }
+    Ok(Vec::new())
+}
+
+fn program_run(
+    program_id: String,
+    output: &RunOutput,
+    duration_ms: u64,
+    content_type: String,
+) -> ProgramRun {
+    ProgramRun {
+        program_id,
+        status: if output.success() {
+            "success"
+        } else {
+            "failed"
+        }
+        .to_string(),
+        exit_code: output.exit_code,
+        timed_out: output.timed_out,
+        output_exceeded: output.output_exceeded,
+        duration_ms,
+        content_type,
+        stdout_size: output.stdout.len() as u64,
+        stdout: String::from_utf8(output.stdout.clone()).ok(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    }
+}
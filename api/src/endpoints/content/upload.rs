@@ -1,21 +1,26 @@
 use actix_multipart::Multipart;
 use actix_web::{web, Error, HttpResponse};
-use bson::{oid::ObjectId, Document};
+use bson::oid::ObjectId;
 use chrono::{DateTime, Utc};
 use futures::{StreamExt, TryStreamExt};
 use log::info;
-use mongodb::{
-    bson::{doc, DateTime as BsonDateTime},
-    Database,
-};
-use reqwest::Client;
 use serde_json::json;
-use shared::database::api_response::ApiResponse;
-use std::time::SystemTime;
-
-use utils::error::UploadError;
+use shared::database::{
+    api_response::ApiResponse, db_interface::DatabaseConnection,
+    program_repository::ProgramRepository,
+};
+use shared::models::{
+    program::{any_media_type, Program, UpdateProgramDto},
+    program_version::ProgramVersion,
+};
+use shared::storage::blob_store::{BlobStore, Storage};
 
-use crate::utils::{self, field_parser::parse_id, firebase::delete_file_from_firebase};
+use super::version::{record_next_version, record_version};
+use crate::utils::{
+    error::{database_error, UploadError},
+    field_parser::parse_id,
+    spool::{process_file_field, SpooledFile},
+};
 
 #[utoipa::path(
     post,
@@ -31,15 +36,12 @@ use crate::utils::{self, field_parser::parse_id, firebase::delete_file_from_fire
     ),
 )]
 pub async fn upload(
-    db: web::Data<Database>,
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<Storage>,
     mut payload: Multipart,
 ) -> Result<HttpResponse, Error> {
-    let firebase_bucket =
-        std::env::var("FIREBASE_STORAGE_BUCKET").expect("FIREBASE_STORAGE_BUCKET must be set");
-    let client = Client::new();
-
     let mut owner_id: Option<i32> = None;
-    let mut file_data: Option<Vec<u8>> = None;
+    let mut file_data: Option<SpooledFile> = None;
     let mut filename: Option<String> = None;
     let mut content_type: Option<String> = None;
     let mut output_extension: Option<String> = Some(".txt".to_string());
@@ -58,26 +60,17 @@ pub async fn upload(
             "output_extension" => {
                 info!("Received output extension");
                 let mut data = Vec::new();
-                while let Some(chunk) = field.try_next().await.unwrap() {
-                    data.extend_from_slice(&chunk);
-                }
-                output_extension = Some(String::from_utf8(data).unwrap());
-                if output_extension == Some("".to_string())
-                    || output_extension == Some("null".to_string())
+                while let Some(chunk) = field
+                    .try_next()
+                    .await
+                    .map_err(|e| UploadError::BadRequest(e.to_string()))?
                 {
-                    output_extension = Some(".txt".to_string());
-                }
-
-                match output_extension {
-                    Some(ref ext) => {
-                        if !ext.starts_with('.') {
-                            output_extension = Some(format!(".{}", ext));
-                        }
-                    }
-                    None => {
-                        output_extension = Some(".txt".to_string());
-                    }
+                    data.extend_from_slice(&chunk);
                 }
+                let extension = String::from_utf8(data).map_err(|_| {
+                    UploadError::BadRequest("output_extension is not valid UTF-8".into())
+                })?;
+                output_extension = Some(normalize_output_extension(&extension));
             }
 
             _ => {}
@@ -104,8 +97,7 @@ pub async fn upload(
             &content_type,
             &output_extension,
             db,
-            &client,
-            &firebase_bucket,
+            &storage,
         )
         .await;
     }
@@ -113,34 +105,26 @@ pub async fn upload(
     Ok(HttpResponse::BadRequest().json(json!({"message": "No files or owner_id were provided."})))
 }
 
-async fn update(
+pub(super) async fn update(
     owner_id: i32,
-    file_data: Vec<u8>,
+    file_data: SpooledFile,
     filename: &str,
     content_type: &str,
     output_extension: &str,
 
-    db: web::Data<Database>,
-    client: &Client,
-    firebase_bucket: &str,
+    db: web::Data<DatabaseConnection>,
+    storage: &Storage,
 ) -> Result<HttpResponse, Error> {
-    let collection: mongodb::Collection<Document> = db.collection::<Document>("programs");
+    let file_hash = file_data.sha256.as_str();
 
     let base_filename = match filename.rsplit_once('.') {
         Some((base, _)) => base,
         None => filename,
     };
-    let regex_pattern = format!("^{}", regex::escape(base_filename));
-    info!("Regex pattern: {:?}", regex_pattern);
+    info!("Filename prefix: {:?}", base_filename);
 
-    let existing_file: Option<bson::Document> = collection
-        .find_one(
-            doc! {
-                "owner_id": owner_id,
-                "filename": { "$regex": regex_pattern, "$options": "i" }
-            },
-            None,
-        )
+    let existing_file = db
+        .find_program_by_filename_prefix(owner_id, base_filename)
         .await
         .map_err(|e| {
             log::error!("Database error: {:?}", e);
@@ -148,28 +132,58 @@ async fn update(
         })?;
 
     info!("Existing file: {:?}", existing_file);
-    let file_size = file_data.len();
+    if let Some(existing_file) = &existing_file {
+        if existing_file.file_hash == file_hash {
+            let message = if existing_file.output_type == output_extension {
+                info!("Uploaded file is identical to the stored one, nothing to do");
+                "File unchanged"
+            } else {
+                info!("Uploaded file is identical to the stored one, updating its output type");
+                let update = UpdateProgramDto {
+                    output_type: Some(output_extension.to_string()),
+                    ..Default::default()
+                };
+                db.update_program_metadata(&existing_file.id, &update)
+                    .await
+                    .map_err(database_error)?;
+                "Output extension updated"
+            };
+            let response_data = ApiResponse::new(
+                message,
+                Some(existing_file.id.to_hex()),
+                Some(existing_file.code_url.clone()),
+            );
+            return Ok(HttpResponse::Ok().json(response_data));
+        }
+    }
+
+    // Identical bytes already uploaded by the same owner: point at that blob
+    // instead of storing a second copy.
+    let duplicate_path = db
+        .find_blob_by_hash(owner_id, file_hash)
+        .await
+        .map_err(|e| {
+            log::error!("Database error: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Database error")
+        })?;
+
+    let file_size = file_data.size;
     let mut file_id = ObjectId::new();
     let upload_time: DateTime<Utc> = Utc::now();
-    let bson_upload_time = BsonDateTime::from(SystemTime::from(upload_time));
     let timestamp = upload_time.timestamp_millis();
     let (base_filename, extension) = match filename.rsplit_once('.') {
         Some((base, ext)) => (base, ext),
         None => (filename, ""),
     };
-    if let Some(existing_file) = existing_file {
-        let existing_file_path = existing_file.get_str("file_path").map_err(|e| {
-            log::error!("Error getting file_path: {:?}", e);
-            actix_web::error::ErrorInternalServerError("Error getting file_path")
-        })?;
-        file_id = existing_file
-            .get_object_id("_id")
-            .map_err(|e| {
-                log::error!("Error getting _id: {:?}", e);
-                actix_web::error::ErrorInternalServerError("Error getting _id")
-            })?
-            .clone();
-        delete_file_from_firebase(client, firebase_bucket, existing_file_path).await?;
+    if let Some(existing_file) = &existing_file {
+        file_id = existing_file.id;
+
+        // Programs uploaded before version history existed get their current
+        // file recorded as version 1 so that it is not lost; a concurrent
+        // upload may have done it already.
+        if existing_file.current_version == 0 {
+            record_version(&db, &ProgramVersion::from_program(existing_file, 1)).await?;
+        }
     }
 
     let filename_with_timestamp = if extension.is_empty() {
@@ -178,168 +192,115 @@ async fn update(
         format!("{}-{}-{}.{}", base_filename, file_id, timestamp, extension)
     };
 
-    let file_path: String = format!("content%2F{}%2F{}", owner_id, filename_with_timestamp);
-    let upload_url = format!(
-        "https://firebasestorage.googleapis.com/v0/b/{}/o?name={}",
-        firebase_bucket, file_path
-    );
-
-    info!("Uploading file: {:?}", filename_with_timestamp);
+    let file_path = match duplicate_path {
+        Some(file_path) => {
+            info!("Reusing stored blob for hash {}", file_hash);
+            Ok(file_path)
+        }
+        None => {
+            let file_path: String = format!("content/{}/{}", owner_id, filename_with_timestamp);
+            info!("Uploading file: {:?}", filename_with_timestamp);
 
-    let response = client
-        .post(&upload_url)
-        .header("Content-Type", content_type)
-        .body(file_data)
-        .send()
-        .await;
+            storage
+                .put_stream(
+                    &file_path,
+                    content_type,
+                    file_data.size,
+                    file_data.stream().await?,
+                )
+                .await
+                .map(|()| file_path)
+        }
+    };
+    match file_path {
+        Ok(file_path) => {
+            let code_url = Program::code_url_for(&file_id);
+            let version = record_next_version(&db, &file_id, |version| ProgramVersion {
+                id: ObjectId::new(),
+                program_id: file_id,
+                version,
+                filename: filename_with_timestamp.clone(),
+                content_type: content_type.to_string(),
+                file_hash: file_hash.to_string(),
+                file_size: file_size as i64,
+                file_path: file_path.clone(),
+                code_url: ProgramVersion::code_url_for(&file_id, version),
+                uploaded_by: owner_id,
+                upload_time,
+                restorations: Vec::new(),
+            })
+            .await?;
 
-    match response {
-        Ok(res) if res.status().is_success() => {
-            save_metadata_to_db(
-                db,
+            let program = Program {
+                id: file_id,
                 owner_id,
-                filename_with_timestamp.to_string(),
-                firebase_bucket,
+                filename: filename_with_timestamp,
+                code_url,
+                content_type: content_type.to_string(),
+                file_size: file_size as i64,
+                input_type: existing_file
+                    .as_ref()
+                    .map_or_else(any_media_type, |existing_file| {
+                        existing_file.input_type.clone()
+                    }),
+                output_type: output_extension.to_string(),
+                upload_time: existing_file
+                    .as_ref()
+                    .map_or(upload_time, |existing_file| existing_file.upload_time),
+                update_time: upload_time,
                 file_path,
-                content_type.to_string(),
-                output_extension.to_string(),
-                file_size as i64,
-                bson_upload_time,
-                file_id,
-            )
-            .await
-        }
-        Ok(res) => {
-            let error_message = res
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            Ok(HttpResponse::BadRequest().json(json!({
-                "message": format!("Error uploading to Firebase: {}", error_message)
-            })))
+                file_hash: file_hash.to_string(),
+                current_version: version,
+            };
+            save_metadata_to_db(&db, program, existing_file.is_some()).await
         }
         Err(e) => Ok(HttpResponse::BadRequest().json(json!({
-            "message": format!("Error uploading to Firebase: {}", e)
+            "message": format!("Error uploading to storage: {}", e)
         }))),
     }
 }
 
+/// Output extensions default to `.txt` and always start with a dot.
+pub(super) fn normalize_output_extension(extension: &str) -> String {
+    match extension {
+        "" | "null" => ".txt".to_string(),
+        ext if ext.starts_with('.') => ext.to_string(),
+        ext => format!(".{}", ext),
+    }
+}
+
 async fn save_metadata_to_db(
-    db: web::Data<Database>,
-    owner_id: i32,
-    filename: String,
-    firebase_bucket: &str,
-    file_path: String,
-    content_type: String,
-    output_extension: String,
-    file_size: i64,
-    bson_upload_time: BsonDateTime,
-    file_id: ObjectId,
+    db: &DatabaseConnection,
+    program: Program,
+    exists: bool,
 ) -> Result<HttpResponse, Error> {
-    let code_url = format!(
-        "https://firebasestorage.googleapis.com/v0/b/{}/o/{}?alt=media",
-        firebase_bucket, file_path
-    );
-
-    let collection = db.collection("programs");
-
-    // Vérifier si le document existe déjà
-    let existing_file = collection
-        .find_one(doc! { "_id": file_id }, None)
-        .await
-        .map_err(|e| {
-            log::error!("Database error: {:?}", e);
-            actix_web::error::ErrorInternalServerError("Database error")
+    if exists {
+        let updated = db.update_program(&program).await.map_err(|e| {
+            log::error!("Update error: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Update error")
         })?;
 
-    if let Some(_) = existing_file {
-        let metadata = doc! {
-            "owner_id": owner_id,
-            "filename": filename,
-            "code_url": &code_url,
-            "content_type": content_type,
-            "file_size": file_size,
-            "output_type": output_extension,
-            "update_time": bson_upload_time,
-            "file_path": file_path,
-            "file_hash": "example_hash", // TODO: get an algorithm to calculate the file hash (MD5, SHA256, etc.)
-        };
-
-        let update_result = collection
-            .update_one(doc! { "_id": file_id }, doc! { "$set": metadata }, None)
-            .await
-            .map_err(|e| {
-                log::error!("Update error: {:?}", e);
-                actix_web::error::ErrorInternalServerError("Update error")
-            })?;
-
-        if update_result.matched_count > 0 {
+        if updated {
             let response_data = ApiResponse::new(
                 "File metadata updated",
-                Some(file_id.to_hex()),
-                Some(code_url),
+                Some(program.id.to_hex()),
+                Some(program.code_url),
             );
             Ok(HttpResponse::Ok().json(response_data))
         } else {
             Ok(HttpResponse::BadRequest().json(json!({"message": "Failed to update metadata"})))
         }
     } else {
-        let metadata = doc! {
-            "_id": file_id,
-            "owner_id": owner_id,
-            "filename": filename,
-            "code_url": &code_url,
-            "content_type": content_type,
-            "file_size": file_size,
-            "output_type": output_extension,
-            "upload_time": bson_upload_time,
-            "update_time": bson_upload_time,
-            "file_path": file_path,
-            "file_hash": "example_hash", // TODO: get an algorithm to calculate the file hash (MD5, SHA256, etc.)
-        };
-
-        let insert_result = collection.insert_one(metadata, None).await.map_err(|e| {
+        db.insert_program(&program).await.map_err(|e| {
             log::error!("Insert error: {:?}", e);
             actix_web::error::ErrorInternalServerError("Insert error")
         })?;
 
-        if insert_result.inserted_id.as_object_id() == Some(file_id) {
-            let response_data = ApiResponse::new(
-                "File uploaded and metadata saved",
-                Some(file_id.to_hex()),
-                Some(code_url),
-            );
-            Ok(HttpResponse::Created().json(response_data))
-        } else {
-            Ok(HttpResponse::BadRequest().json(json!({"message": "Failed to save metadata"})))
-        }
-    }
-}
-
-async fn process_file_field(
-    field: actix_multipart::Field,
-) -> Result<(String, String, Vec<u8>), Error> {
-    let content_disposition = field.content_disposition().clone();
-    let filename = match content_disposition
-        .get_filename()
-        .map(|name| name.to_string())
-    {
-        Some(name) if !name.is_empty() => name,
-        _ => {
-            return Err(UploadError::BadRequest("No filename provided".into()).into());
-        }
-    };
-
-    let content_type = field
-        .content_type()
-        .map(|mime| mime.to_string())
-        .unwrap_or_else(|| "application/octet-stream".to_string());
-
-    let mut data = Vec::new();
-    let mut field = field;
-    while let Some(chunk) = field.next().await {
-        data.extend_from_slice(&chunk?);
+        let response_data = ApiResponse::new(
+            "File uploaded and metadata saved",
+            Some(program.id.to_hex()),
+            Some(program.code_url),
+        );
+        Ok(HttpResponse::Created().json(response_data))
     }
-
-    Ok((filename, content_type, data))
 }
@@ -1,13 +1,263 @@
-use actix_web::{HttpResponse, Responder};
+use actix_web::{web, Error, HttpResponse};
+use bson::oid::ObjectId;
+use chrono::Utc;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use shared::database::{db_interface::DatabaseConnection, program_repository::ProgramRepository};
+use shared::models::program::Program;
+use shared::models::program_version::{ProgramVersion, Restoration};
+use utoipa::ToSchema;
 
-pub(super) async fn list_versions() -> impl Responder {
-    HttpResponse::Ok().body("list_versions")
+use crate::utils::error::database_error;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RestoreVersionDto {
+    #[schema(example = 121)]
+    pub restored_by: i32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/content/{id}/versions",
+    tag = "content",
+    params(("id"=String, Path, description = "List the versions of a Content by id")),
+    responses(
+        (status = 200, description = "Versions of the content, oldest first", body = Vec<ProgramVersion>),
+        (status = 400, description = "Invalid ID format"),
+    )
+)]
+pub async fn list_versions(
+    db: web::Data<DatabaseConnection>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let program_id = parse_program_id(&id)?;
+
+    let versions = db
+        .find_versions(&program_id)
+        .await
+        .map_err(database_error)?;
+    if versions.is_empty() {
+        log::warn!("No versions found for program: {}", program_id);
+    }
+    Ok(HttpResponse::Ok().json(versions))
+}
+
+#[utoipa::path(
+    get,
+    path = "/content/{id}/versions/{version}",
+    tag = "content",
+    params(
+        ("id"=String, Path, description = "Content id"),
+        ("version"=i32, Path, description = "Version number"),
+    ),
+    responses(
+        (status = 200, description = "Version details", body = ProgramVersion),
+        (status = 404, description = "Version not found"),
+    )
+)]
+pub async fn get_version(
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<(String, i32)>,
+) -> Result<HttpResponse, Error> {
+    let (id, version) = path.into_inner();
+    let program_id = parse_program_id(&id)?;
+
+    match db
+        .find_version(&program_id, version)
+        .await
+        .map_err(database_error)?
+    {
+        Some(version) => Ok(HttpResponse::Ok().json(version)),
+        None => Ok(HttpResponse::NotFound().body("Version not found")),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/content/{id}/versions/{version}/restore",
+    tag = "content",
+    params(
+        ("id"=String, Path, description = "Content id"),
+        ("version"=i32, Path, description = "Version number to make current again"),
+    ),
+    request_body(
+        content_type = "application/json",
+        content = RestoreVersionDto
+    ),
+    responses(
+        (status = 200, description = "Version restored", body = Program),
+        (status = 404, description = "Content or version not found"),
+    )
+)]
+pub async fn restore_version(
+    db: web::Data<DatabaseConnection>,
+    path: web::Path<(String, i32)>,
+    restore_dto: web::Json<RestoreVersionDto>,
+) -> Result<HttpResponse, Error> {
+    let (id, version_number) = path.into_inner();
+    let program_id = parse_program_id(&id)?;
+
+    let version = match db
+        .find_version(&program_id, version_number)
+        .await
+        .map_err(database_error)?
+    {
+        Some(version) => version,
+        None => return Ok(HttpResponse::NotFound().body("Version not found")),
+    };
+    let mut program = match db.find_program(&program_id).await.map_err(database_error)? {
+        Some(program) => program,
+        None => return Ok(HttpResponse::NotFound().body("Content not found")),
+    };
+
+    let restoration = Restoration {
+        restored_by: restore_dto.restored_by,
+        restored_time: Utc::now(),
+    };
+
+    program.filename = version.filename.clone();
+    program.content_type = version.content_type.clone();
+    program.file_size = version.file_size;
+    program.code_url = Program::code_url_for(&program_id);
+    program.file_path = version.file_path.clone();
+    program.file_hash = version.file_hash.clone();
+    program.current_version = version.version;
+    program.update_time = restoration.restored_time;
+
+    // The restoration is written first: if the update then fails, the
+    // version history tells of a restore that did not happen rather than
+    // the program silently running a restored version.
+    db.add_restoration(&version.id, &restoration)
+        .await
+        .map_err(database_error)?;
+    if !db.update_program(&program).await.map_err(database_error)? {
+        return Ok(HttpResponse::NotFound().body("Content not found"));
+    }
+
+    info!(
+        "Program {} restored to version {} by {}",
+        program_id, version.version, restoration.restored_by
+    );
+
+    Ok(HttpResponse::Ok().json(program))
 }
 
-pub(super) async fn create_version() -> impl Responder {
-    HttpResponse::Ok().body("create_version")
+/* Helpers shared with the upload endpoint */
+
+/// How many numbers recording a version tries when concurrent uploads of the
+/// same program keep taking them.
+const VERSION_ATTEMPTS: usize = 5;
+
+/// Records the version `version_for` builds for the next free number of
+/// `program_id` and returns that number. Concurrent uploads that read the
+/// same number are told apart by the unique `(program_id, version)` index;
+/// the losers move on to the next number.
+pub(super) async fn record_next_version(
+    db: &DatabaseConnection,
+    program_id: &ObjectId,
+    version_for: impl Fn(i32) -> ProgramVersion,
+) -> Result<i32, Error> {
+    for _ in 0..VERSION_ATTEMPTS {
+        let number = next_version_number(db, program_id).await?;
+        if record_version(db, &version_for(number)).await? {
+            return Ok(number);
+        }
+        info!(
+            "Version {} of program {} was taken by a concurrent upload",
+            number, program_id
+        );
+    }
+    Err(actix_web::error::ErrorConflict(
+        "Too many concurrent uploads of this content, try again",
+    ))
 }
 
-pub(super) async fn get_version() -> impl Responder {
-    HttpResponse::Ok().body("get_version")
+/// The number the next upload of `program_id` should get, one past the
+/// highest existing version so that restoring an old version never reuses a
+/// number.
+async fn next_version_number(db: &DatabaseConnection, program_id: &ObjectId) -> Result<i32, Error> {
+    let latest = db
+        .latest_version_number(program_id)
+        .await
+        .map_err(database_error)?;
+    Ok(latest.map_or(1, |version| version + 1))
+}
+
+/// `false` when the program already has a version with that number.
+pub(crate) async fn record_version(
+    db: &DatabaseConnection,
+    version: &ProgramVersion,
+) -> Result<bool, Error> {
+    let recorded = db.insert_version(version).await.map_err(|e| {
+        log::error!("Insert error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Insert error")
+    })?;
+    if recorded {
+        info!(
+            "Recorded version {} of program {}",
+            version.version, version.program_id
+        );
+    }
+    Ok(recorded)
+}
+
+pub(super) fn parse_program_id(id: &str) -> Result<ObjectId, Error> {
+    ObjectId::parse_str(id.trim()).map_err(|e| {
+        warn!("Invalid ID format: {}", e);
+        actix_web::error::ErrorBadRequest("Invalid ID format")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::database::mock_db::MockDb;
+
+    fn version(program_id: ObjectId, version: i32) -> ProgramVersion {
+        ProgramVersion {
+            id: ObjectId::new(),
+            program_id,
+            version,
+            filename: format!("example-{}.py", version),
+            content_type: "text/x-python".to_string(),
+            file_hash: String::new(),
+            file_size: 0,
+            file_path: format!("content/1/example-{}.py", version),
+            code_url: ProgramVersion::code_url_for(&program_id, version),
+            uploaded_by: 1,
+            upload_time: Utc::now(),
+            restorations: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_reuploads_get_the_next_version_number() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let db = DatabaseConnection::Mock(MockDb::default());
+            let program_id = ObjectId::new();
+            let other_id = ObjectId::new();
+            assert!(record_version(&db, &version(program_id, 1)).await.unwrap());
+            assert!(record_version(&db, &version(other_id, 1)).await.unwrap());
+            assert!(!record_version(&db, &version(program_id, 1)).await.unwrap());
+
+            let next = |number| version(program_id, number);
+            assert_eq!(
+                record_next_version(&db, &program_id, next).await.unwrap(),
+                2
+            );
+            assert_eq!(
+                record_next_version(&db, &program_id, next).await.unwrap(),
+                3
+            );
+            let numbers: Vec<i32> = db
+                .find_versions(&program_id)
+                .await
+                .unwrap()
+                .iter()
+                .map(|version| version.version)
+                .collect();
+            assert_eq!(numbers, vec![1, 2, 3]);
+        });
+    }
 }
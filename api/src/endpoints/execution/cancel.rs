@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use actix_web::{web, Error, HttpResponse};
+use log::{info, warn};
+use shared::database::{
+    db_interface::DatabaseConnection, execution_repository::ExecutionRepository,
+};
+use shared::execution::{cancel::CancelRegistry, engine};
+use shared::models::pipeline::ExecutionRecord;
+
+use super::history::parse_object_id;
+use crate::utils::error::database_error;
+
+/// How long a cancel request waits for the running step to be killed.
+const CANCEL_WAIT: Duration = Duration::from_secs(10);
+
+#[utoipa::path(
+    post,
+    path = "/executions/{id}/cancel",
+    tag = "execution",
+    params(("id"=String, Path, description = "Execution id")),
+    responses(
+        (status = 200, description = "Execution cancelled", body = ExecutionRecord),
+        (status = 202, description = "Cancellation requested, the execution is still stopping or runs on another server", body = ExecutionRecord),
+        (status = 400, description = "Invalid ID format"),
+        (status = 404, description = "Execution not found"),
+        (status = 409, description = "Execution is not running"),
+    )
+)]
+pub async fn cancel_execution(
+    db: web::Data<DatabaseConnection>,
+    cancels: web::Data<CancelRegistry>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let execution_id = parse_object_id(&id)?;
+    let execution = match db
+        .find_execution(&execution_id)
+        .await
+        .map_err(database_error)?
+    {
+        Some(execution) => execution,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    if execution.status != "running" {
+        return Err(actix_web::error::ErrorConflict(format!(
+            "Execution is {}",
+            execution.status
+        )));
+    }
+
+    let Some(stopped) = cancels.cancel(&execution_id) else {
+        return cancel_elsewhere(&db, execution).await;
+    };
+
+    info!("Cancelling execution {}", execution_id);
+    let stopped = tokio::time::timeout(CANCEL_WAIT, stopped).await.is_ok();
+    if !stopped {
+        warn!("Execution {} did not stop in time", execution_id);
+    }
+    let execution = db
+        .find_execution(&execution_id)
+        .await
+        .map_err(database_error)?
+        .unwrap_or(execution);
+    if stopped {
+        Ok(HttpResponse::Ok().json(execution))
+    } else {
+        Ok(HttpResponse::Accepted().json(execution))
+    }
+}
+
+/* Private helper functions */
+
+/// Cancels an execution this server does not run: another server that is
+/// still alive is asked to, otherwise nothing runs it any more, e.g. its
+/// server stopped mid-run, and only the record is left to update.
+async fn cancel_elsewhere(
+    db: &DatabaseConnection,
+    mut execution: ExecutionRecord,
+) -> Result<HttpResponse, Error> {
+    if engine::owner_alive(&execution) {
+        info!(
+            "Asking {} to cancel execution {}",
+            execution.instance, execution.id
+        );
+        if !db
+            .request_execution_cancel(&execution.id)
+            .await
+            .map_err(database_error)?
+        {
+            return Err(actix_web::error::ErrorConflict("Execution is not running"));
+        }
+        execution.cancel_requested = true;
+        return Ok(HttpResponse::Accepted().json(execution));
+    }
+
+    info!("Cancelling orphaned execution {}", execution.id);
+    engine::cancel_record(&mut execution);
+    db.update_execution(&execution)
+        .await
+        .map_err(database_error)?;
+    Ok(HttpResponse::Ok().json(execution))
+}
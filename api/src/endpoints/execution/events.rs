@@ -0,0 +1,74 @@
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use futures::StreamExt;
+use shared::database::{
+    db_interface::DatabaseConnection, execution_repository::ExecutionRepository,
+};
+use shared::execution::events::{EventHub, ExecutionEvent};
+
+use super::history::parse_object_id;
+use crate::utils::error::database_error;
+
+const LAST_EVENT_ID: &str = "Last-Event-ID";
+
+fn to_sse(event: &ExecutionEvent) -> Bytes {
+    Bytes::from(format!(
+        "id: {}\nevent: {}\ndata: {}\n\n",
+        event.id, event.event, event.data
+    ))
+}
+
+fn event_stream_response() -> actix_web::HttpResponseBuilder {
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"));
+    response
+}
+
+#[utoipa::path(
+    get,
+    path = "/executions/{id}/events",
+    tag = "execution",
+    params(
+        ("id"=String, Path, description = "Execution id"),
+        ("Last-Event-ID"=Option<u64>, Header, description = "Only send the events after this one"),
+    ),
+    responses(
+        (status = 200, description = "Server-Sent Events: step-started, stdout, step-finished and execution-finished", content_type = "text/event-stream"),
+        (status = 400, description = "Invalid ID format"),
+        (status = 404, description = "Execution not found"),
+    )
+)]
+pub async fn stream_events(
+    req: HttpRequest,
+    db: web::Data<DatabaseConnection>,
+    hub: web::Data<EventHub>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let execution_id = parse_object_id(&id)?;
+    let last_event_id = req
+        .headers()
+        .get(LAST_EVENT_ID)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(0);
+
+    if db
+        .find_execution(&execution_id)
+        .await
+        .map_err(database_error)?
+        .is_none()
+    {
+        return Ok(HttpResponse::NotFound().body("Execution not found"));
+    }
+
+    // Ends after the execution finished. Events come from whichever server
+    // has them, so a client can reconnect through any of them.
+    let body = hub
+        .follow(execution_id, last_event_id)
+        .map(|event| Ok::<_, Error>(to_sse(&event)));
+
+    Ok(event_stream_response().streaming(body))
+}
@@ -0,0 +1,195 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, Error, HttpResponse};
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use serde::Deserialize;
+use shared::database::{
+    db_interface::DatabaseConnection,
+    execution_repository::{ExecutionFilter, ExecutionRepository},
+    pipeline_repository::PipelineRepository,
+};
+use shared::execution::media_type;
+use shared::models::pipeline::StepRecord;
+use shared::storage::blob_store::{BlobStore, Storage};
+
+use crate::utils::error::database_error;
+
+pub(super) fn parse_object_id(id: &str) -> Result<ObjectId, Error> {
+    ObjectId::parse_str(id.trim()).map_err(|e| {
+        warn!("Invalid ID format: {}", e);
+        actix_web::error::ErrorBadRequest("Invalid ID format")
+    })
+}
+
+#[derive(Deserialize)]
+pub struct ExecutionQuery {
+    status: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/pipeline/{id}/executions",
+    tag = "pipeline",
+    params(
+        ("id"=String, Path, description = "Pipeline id"),
+        ("status"=Option<String>, Query, description = "Only executions with this status"),
+        ("from"=Option<String>, Query, description = "Only executions started at or after this RFC 3339 time"),
+        ("to"=Option<String>, Query, description = "Only executions started at or before this RFC 3339 time"),
+    ),
+    responses(
+        (status = 200, description = "Executions of the pipeline, most recent first", body = Vec<ExecutionRecord>),
+        (status = 400, description = "Invalid ID format or query"),
+        (status = 404, description = "Pipeline not found"),
+    )
+)]
+pub async fn list_pipeline_executions(
+    db: web::Data<DatabaseConnection>,
+    id: web::Path<String>,
+    query: web::Query<ExecutionQuery>,
+) -> Result<HttpResponse, Error> {
+    let pipeline_id = parse_object_id(&id)?;
+    if db
+        .find_pipeline(&pipeline_id)
+        .await
+        .map_err(database_error)?
+        .is_none()
+    {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    let query = query.into_inner();
+    let filter = ExecutionFilter {
+        status: query.status,
+        from: query.from,
+        to: query.to,
+    };
+    let executions = db
+        .find_executions_by_pipeline(&pipeline_id, &filter)
+        .await
+        .map_err(database_error)?;
+    Ok(HttpResponse::Ok().json(executions))
+}
+
+#[utoipa::path(
+    get,
+    path = "/executions/{id}",
+    tag = "execution",
+    params(("id"=String, Path, description = "Execution id")),
+    responses(
+        (status = 200, description = "Execution with its steps", body = ExecutionRecord),
+        (status = 400, description = "Invalid ID format"),
+        (status = 404, description = "Execution not found"),
+    )
+)]
+pub async fn get_execution(
+    db: web::Data<DatabaseConnection>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let execution_id = parse_object_id(&id)?;
+    match db
+        .find_execution(&execution_id)
+        .await
+        .map_err(database_error)?
+    {
+        Some(execution) => Ok(HttpResponse::Ok().json(execution)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/executions/{id}/steps/{step}/output",
+    tag = "execution",
+    params(
+        ("id"=String, Path, description = "Execution id"),
+        ("step"=i32, Path, description = "Step number, starting at 1"),
+    ),
+    responses(
+        (status = 200, description = "Stdout of the step", content_type = "application/octet-stream"),
+        (status = 400, description = "Invalid ID format"),
+        (status = 404, description = "Execution, step or output not found"),
+    )
+)]
+pub async fn get_step_output(
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<Storage>,
+    path: web::Path<(String, i32)>,
+) -> Result<HttpResponse, Error> {
+    let (id, step) = path.into_inner();
+    let (_, data) = read_step_output(&db, &storage, &id, step).await?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .body(data))
+}
+
+#[utoipa::path(
+    get,
+    path = "/executions/{id}/steps/{step}/artifact",
+    tag = "execution",
+    params(
+        ("id"=String, Path, description = "Execution id"),
+        ("step"=i32, Path, description = "Step number, starting at 1"),
+    ),
+    responses(
+        (status = 200, description = "Stdout of the step as a file, typed by the program's output_type", content_type = "application/octet-stream"),
+        (status = 400, description = "Invalid ID format"),
+        (status = 404, description = "Execution, step or output not found"),
+    )
+)]
+pub async fn download_step_artifact(
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<Storage>,
+    path: web::Path<(String, i32)>,
+) -> Result<HttpResponse, Error> {
+    let (id, step) = path.into_inner();
+    let (record, data) = read_step_output(&db, &storage, &id, step).await?;
+    // Outputs stored before artifacts were typed have no content type.
+    let content_type = record
+        .content_type
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let filename = match media_type::extension(&content_type) {
+        Some(extension) => format!("{}-step-{}.{}", id.trim(), step, extension),
+        None => format!("{}-step-{}", id.trim(), step),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename)],
+        })
+        .body(data))
+}
+
+/* Private helper functions */
+
+/// The record of step `step` of an execution and its stored stdout.
+async fn read_step_output(
+    db: &DatabaseConnection,
+    storage: &Storage,
+    id: &str,
+    step: i32,
+) -> Result<(StepRecord, Vec<u8>), Error> {
+    let execution_id = parse_object_id(id)?;
+    let execution = db
+        .find_execution(&execution_id)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Execution not found"))?;
+
+    let record = execution
+        .steps
+        .into_iter()
+        .find(|s| s.step == step)
+        .filter(|s| s.output_path.is_some())
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Step output not found"))?;
+    let output_path = record.output_path.as_deref().unwrap_or_default();
+
+    let data = storage.get(output_path).await.map_err(|e| {
+        error!("Error reading {} from storage: {:?}", output_path, e);
+        actix_web::error::ErrorNotFound("Step output not found in storage")
+    })?;
+    Ok((record, data))
+}
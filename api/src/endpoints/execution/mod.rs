@@ -0,0 +1,4 @@
+pub mod cancel;
+pub mod events;
+pub mod history;
+pub mod routes;
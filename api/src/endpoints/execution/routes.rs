@@ -0,0 +1,19 @@
+use actix_web::web;
+
+use super::cancel::cancel_execution;
+use super::events::stream_events;
+use super::history::{download_step_artifact, get_execution, get_step_output};
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/executions")
+            .route("/{id}", web::get().to(get_execution))
+            .route("/{id}/events", web::get().to(stream_events))
+            .route("/{id}/cancel", web::post().to(cancel_execution))
+            .route("/{id}/steps/{step}/output", web::get().to(get_step_output))
+            .route(
+                "/{id}/steps/{step}/artifact",
+                web::get().to(download_step_artifact),
+            ),
+    );
+}
@@ -0,0 +1,66 @@
+use actix_web::{web, Error, HttpResponse};
+use shared::execution::media_type;
+use shared::storage::blob_store::Storage;
+
+use crate::utils::download::serve_blob;
+
+#[utoipa::path(
+    get,
+    path = "/group/{owner_id}/{filename}",
+    tag = "group",
+    params(
+        ("owner_id"=i32, Path, description = "Owner of the group"),
+        ("filename"=String, Path, description = "Name of the group avatar"),
+    ),
+    responses(
+        (status = 200, description = "The file, from the local disk", content_type = "application/octet-stream"),
+        (status = 307, description = "Redirect to a short-lived signed URL of the storage backend"),
+        (status = 404, description = "File not found"),
+    )
+)]
+pub async fn download_avatar(
+    storage: web::Data<Storage>,
+    path: web::Path<(i32, String)>,
+) -> Result<HttpResponse, Error> {
+    let (owner_id, filename) = path.into_inner();
+    serve_group_file(&storage, &format!("group/{}", owner_id), filename).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/group/{owner_id}/messages/{filename}",
+    tag = "group",
+    params(
+        ("owner_id"=i32, Path, description = "Owner of the group"),
+        ("filename"=String, Path, description = "Name of the file sent in a message"),
+    ),
+    responses(
+        (status = 200, description = "The file, from the local disk", content_type = "application/octet-stream"),
+        (status = 307, description = "Redirect to a short-lived signed URL of the storage backend"),
+        (status = 404, description = "File not found"),
+    )
+)]
+pub async fn download_message_file(
+    storage: web::Data<Storage>,
+    path: web::Path<(i32, String)>,
+) -> Result<HttpResponse, Error> {
+    let (owner_id, filename) = path.into_inner();
+    serve_group_file(&storage, &format!("group/{}/messages", owner_id), filename).await
+}
+
+/* Private helper functions */
+
+/// Group files are not recorded anywhere, their media type comes from
+/// their extension.
+async fn serve_group_file(
+    storage: &Storage,
+    directory: &str,
+    filename: String,
+) -> Result<HttpResponse, Error> {
+    let content_type = match filename.rsplit_once('.') {
+        Some((_, extension)) => media_type::content_type(&format!(".{}", extension)),
+        None => media_type::content_type(""),
+    };
+    let key = format!("{}/{}", directory, filename);
+    serve_blob(storage, &key, &content_type, filename).await
+}
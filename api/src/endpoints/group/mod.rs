@@ -1,2 +1,3 @@
+pub mod download;
 pub mod routes;
 pub mod upload;
@@ -1,7 +1,18 @@
 use actix_web::web;
 
-use super::upload;
+use super::{download, upload};
 
 pub fn config(cfg: &mut web::ServiceConfig) {
-    cfg.service(web::scope("/group").route("/upload", web::post().to(upload::upload)));
+    cfg.service(
+        web::scope("/group")
+            .route("/upload", web::post().to(upload::upload))
+            .route(
+                "/{owner_id}/messages/{filename}",
+                web::get().to(download::download_message_file),
+            )
+            .route(
+                "/{owner_id}/{filename}",
+                web::get().to(download::download_avatar),
+            ),
+    );
 }
@@ -1,14 +1,15 @@
 use actix_multipart::Multipart;
-use actix_web::{Error, HttpResponse};
+use actix_web::{web, Error, HttpResponse};
 use futures::StreamExt;
 use log::info;
-use reqwest::Client;
 use serde_json::json;
 use shared::database::api_response::ApiResponse;
+use shared::storage::blob_store::{BlobStore, Storage};
 
-use utils::error::UploadError;
-
-use crate::utils::{self, field_parser::parse_id};
+use crate::utils::{
+    field_parser::parse_id,
+    spool::{process_file_field, SpooledFile},
+};
 
 #[utoipa::path(
     post,
@@ -23,15 +24,14 @@ use crate::utils::{self, field_parser::parse_id};
         content = UploadGroup
     ),
 )]
-pub async fn upload(mut payload: Multipart) -> Result<HttpResponse, Error> {
-    let firebase_bucket =
-        std::env::var("FIREBASE_STORAGE_BUCKET").expect("FIREBASE_STORAGE_BUCKET must be set");
-    let client = Client::new();
-
+pub async fn upload(
+    storage: web::Data<Storage>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, Error> {
     let mut group_id: Option<i32> = None;
     let mut owner_id: Option<i32> = None;
     let mut message_id: Option<i32> = None;
-    let mut file_data: Option<Vec<u8>> = None;
+    let mut file_data: Option<SpooledFile> = None;
     let mut filename: Option<String> = None;
     let mut content_type: Option<String> = None;
 
@@ -63,8 +63,7 @@ pub async fn upload(mut payload: Multipart) -> Result<HttpResponse, Error> {
             message_id,
             &filename,
             &content_type,
-            &client,
-            &firebase_bucket,
+            &storage,
         )
         .await;
     }
@@ -73,19 +72,19 @@ pub async fn upload(mut payload: Multipart) -> Result<HttpResponse, Error> {
         .json(json!({"message": "No files, group_id or owner_id were provided."})))
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn update(
     owner_id: i32,
     group_id: i32,
-    file_data: Vec<u8>,
+    file_data: SpooledFile,
     message_id: Option<i32>,
     filename: &str,
     content_type: &str,
-    client: &Client,
-    firebase_bucket: &str,
+    storage: &Storage,
 ) -> Result<HttpResponse, Error> {
     let edited_filename = match message_id {
         Some(0) | None => format!("{}-{}", group_id, owner_id),
-        Some(_) => format!("{}", filename),
+        Some(_) => filename.to_string(),
     };
 
     let (base_filename, extension) = match filename.rsplit_once('.') {
@@ -94,38 +93,33 @@ async fn update(
     };
 
     let filename_with_extension = if extension.is_empty() {
-        format!("{}", base_filename)
+        base_filename
     } else {
         format!("{}.{}", base_filename, extension)
     };
 
     let directory = match message_id {
-        Some(0) | None => format!("group%2F{}", owner_id),
-        Some(_) => format!("group%2F{}%2Fmessages", owner_id),
+        Some(0) | None => format!("group/{}", owner_id),
+        Some(_) => format!("group/{}/messages", owner_id),
     };
 
-    let file_path: String = format!("{}%2F{}", directory, filename_with_extension);
-    let upload_url = format!(
-        "https://firebasestorage.googleapis.com/v0/b/{}/o?name={}",
-        firebase_bucket, file_path
-    );
+    let file_path: String = format!("{}/{}", directory, filename_with_extension);
 
     info!("Uploading file: {:?}", filename_with_extension);
 
-    let response = client
-        .post(&upload_url)
-        .header("Content-Type", content_type)
-        .body(file_data)
-        .send()
+    let upload = storage
+        .put_stream(
+            &file_path,
+            content_type,
+            file_data.size,
+            file_data.stream().await?,
+        )
         .await;
-
-    let file_url = format!(
-        "https://firebasestorage.googleapis.com/v0/b/{}/o/{}?alt=media",
-        firebase_bucket, file_path
-    );
-
-    match response {
-        Ok(res) if res.status().is_success() => {
+    match upload {
+        Ok(()) => {
+            // Downloads go through the API, see `download`, so the URL does
+            // not expire.
+            let file_url = format!("/v1/{}", file_path);
             let response_data = ApiResponse::new(
                 "File uploaded successfully",
                 Some("".to_string()),
@@ -133,45 +127,8 @@ async fn update(
             );
             Ok(HttpResponse::Created().json(response_data))
         }
-        Ok(res) => {
-            let error_message = res
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            Ok(HttpResponse::BadRequest().json(json!({
-                "message": format!("Error uploading to Firebase: {}", error_message)
-            })))
-        }
         Err(e) => Ok(HttpResponse::BadRequest().json(json!({
-            "message": format!("Error uploading to Firebase: {}", e)
+            "message": format!("Error uploading to storage: {}", e)
         }))),
     }
 }
-
-async fn process_file_field(
-    field: actix_multipart::Field,
-) -> Result<(String, String, Vec<u8>), Error> {
-    let content_disposition = field.content_disposition().clone();
-    let filename = match content_disposition
-        .get_filename()
-        .map(|name| name.to_string())
-    {
-        Some(name) if !name.is_empty() => name,
-        _ => {
-            return Err(UploadError::BadRequest("No filename provided".into()).into());
-        }
-    };
-
-    let content_type = field
-        .content_type()
-        .map(|mime| mime.to_string())
-        .unwrap_or_else(|| "application/octet-stream".to_string());
-
-    let mut data = Vec::new();
-    let mut field = field;
-    while let Some(chunk) = field.next().await {
-        data.extend_from_slice(&chunk?);
-    }
-
-    Ok((filename, content_type, data))
-}
@@ -1,3 +1,4 @@
 pub mod content;
+pub mod execution;
 pub mod group;
 pub mod pipeline;
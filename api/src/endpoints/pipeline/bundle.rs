@@ -0,0 +1,809 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read, Write};
+
+use actix_web::error::InternalError;
+use actix_web::http::header::{self, ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use anyhow::{anyhow, bail};
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use shared::database::{
+    db_interface::DatabaseConnection, pipeline_repository::PipelineRepository,
+    program_repository::ProgramRepository,
+};
+use shared::models::pipeline::{
+    Pipeline, PipelineEdge, PipelineParameter, PipelineSchedule, StepPolicy,
+};
+use shared::models::{program::Program, program_version::ProgramVersion};
+use shared::storage::blob_store::{BlobStore, Storage};
+use utoipa::ToSchema;
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use super::metadata::{check_graph, check_parameters, check_policies, check_schedule, check_types};
+use crate::endpoints::content::version::record_version;
+use crate::utils::error::database_error;
+use crate::utils::spool::{base_filename, max_upload_size, spool};
+
+/// Version of the bundle format written by this server.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Name of the bundle itself inside a zip, next to the program files.
+const BUNDLE_FILE: &str = "bundle.json";
+
+/// A pipeline and the programs it runs, without the ids, owner and storage
+/// paths of the server it was exported from.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PipelineBundle {
+    #[schema(example = 1)]
+    pub version: u32,
+
+    #[serde(default = "Utc::now")]
+    #[schema(example = "2024-08-01T12:34:56Z")]
+    pub exported_at: DateTime<Utc>,
+
+    pub pipeline: BundledPipeline,
+
+    /// Every program used as a step or a fallback.
+    pub programs: Vec<BundledProgram>,
+}
+
+/// A pipeline definition whose steps and fallbacks name programs by their
+/// `key` in the bundle.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BundledPipeline {
+    #[schema(example = "example_pipeline")]
+    pub name: String,
+
+    #[serde(default)]
+    #[schema(example = "example_description")]
+    pub description: String,
+
+    #[schema(example = json!(["60f7b3b3d4b3f3b3f3b3f3b3"]))]
+    pub steps: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edges: Option<Vec<PipelineEdge>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<PipelineSchedule>,
+
+    #[serde(default)]
+    pub parameters: Vec<PipelineParameter>,
+
+    #[serde(default)]
+    pub policies: Vec<StepPolicy>,
+
+    #[serde(default = "default_cache_results")]
+    #[schema(example = true)]
+    pub cache_results: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BundledProgram {
+    /// How the pipeline refers to the program; the id it had on export.
+    #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
+    pub key: String,
+
+    /// Name it was uploaded with.
+    #[schema(example = "example.py")]
+    pub filename: String,
+
+    #[schema(example = "text/plain")]
+    pub content_type: String,
+
+    #[schema(example = "text/plain")]
+    pub input_type: String,
+
+    #[schema(example = "text/plain")]
+    pub output_type: String,
+
+    #[serde(default)]
+    #[schema(example = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08")]
+    pub file_hash: String,
+
+    #[serde(default)]
+    #[schema(example = 1024)]
+    pub file_size: i64,
+
+    /// Path of the program file in a zip bundle.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "programs/60f7b3b3d4b3f3b3f3b3f3b3/example.py")]
+    pub file: Option<String>,
+}
+
+/// The pipeline created by an import and which program each key became.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportedPipeline {
+    pub pipeline: Pipeline,
+    pub programs: Vec<ImportedProgram>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportedProgram {
+    #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
+    pub key: String,
+
+    #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b4")]
+    pub program_id: String,
+
+    /// `false` when the owner already had a program with the same file.
+    pub created: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    /// `json` (the default), `yaml` or `zip`.
+    format: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    owner_id: i32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/pipeline/{id}/export",
+    tag = "pipeline",
+    params(
+        ("id"=String, Path, description = "Export Pipeline by id"),
+        ("format"=Option<String>, Query, description = "`json` (the default) or `yaml` for the bundle alone, `zip` for the bundle along with the program files"),
+    ),
+    responses(
+        (status = 200, description = "The bundle, as a download", body = PipelineBundle),
+        (status = 400, description = "Invalid ID format or format"),
+        (status = 404, description = "Pipeline not found"),
+        (status = 409, description = "A program of the pipeline no longer exists"),
+    )
+)]
+pub async fn export_pipeline(
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<Storage>,
+    id: web::Path<String>,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, Error> {
+    let object_id = match ObjectId::parse_str(id.as_ref().trim()) {
+        Ok(oid) => oid,
+        Err(e) => {
+            warn!("Invalid ID format: {}", e);
+            return Err(actix_web::error::ErrorBadRequest("Invalid ID format"));
+        }
+    };
+    let format = query.format.as_deref().unwrap_or("json");
+    if !["json", "yaml", "zip"].contains(&format) {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "Unknown format {:?}, expected json, yaml or zip",
+            format
+        )));
+    }
+
+    let pipeline = match db.find_pipeline(&object_id).await.map_err(database_error)? {
+        Some(pipeline) => pipeline,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let mut programs = Vec::new();
+    for key in program_keys(&pipeline.steps, &pipeline.policies) {
+        let program = match ObjectId::parse_str(key) {
+            Ok(program_id) => db.find_program(&program_id).await.map_err(database_error)?,
+            Err(_) => None,
+        };
+        match program {
+            Some(program) => programs.push(program),
+            None => {
+                return Err(actix_web::error::ErrorConflict(format!(
+                    "Program {} of the pipeline no longer exists",
+                    key
+                )))
+            }
+        }
+    }
+
+    let mut bundle = PipelineBundle {
+        version: BUNDLE_VERSION,
+        exported_at: Utc::now(),
+        pipeline: BundledPipeline::of(&pipeline),
+        programs: programs.iter().map(BundledProgram::of).collect(),
+    };
+    info!(
+        "Exporting pipeline {} with {} programs as {}",
+        pipeline.id,
+        programs.len(),
+        format
+    );
+
+    let (content_type, body) = match format {
+        "json" => (
+            "application/json",
+            serde_json::to_vec_pretty(&bundle)
+                .map_err(actix_web::error::ErrorInternalServerError)?,
+        ),
+        "yaml" => (
+            "application/yaml",
+            serde_yaml::to_string(&bundle)
+                .map_err(actix_web::error::ErrorInternalServerError)?
+                .into_bytes(),
+        ),
+        _ => {
+            let mut files = Vec::with_capacity(programs.len());
+            for (bundled, program) in bundle.programs.iter_mut().zip(&programs) {
+                let data = storage.get(&program.file_path).await.map_err(|e| {
+                    error!("Error reading {} from storage: {:?}", program.file_path, e);
+                    actix_web::error::ErrorNotFound("File not found in storage")
+                })?;
+                let path = format!("programs/{}/{}", bundled.key, bundled.filename);
+                bundled.file = Some(path.clone());
+                files.push((path, data));
+            }
+            (
+                "application/zip",
+                write_zip(&bundle, &files).map_err(actix_web::error::ErrorInternalServerError)?,
+            )
+        }
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "{}.{}",
+                file_stem(&pipeline.name),
+                format
+            ))],
+        })
+        .body(body))
+}
+
+#[utoipa::path(
+    post,
+    path = "/pipeline/import",
+    tag = "pipeline",
+    params(("owner_id"=i32, Query, description = "Owner of the new pipeline and programs")),
+    request_body(
+        content_type = "application/json",
+        content = PipelineBundle,
+        description = "A bundle from /pipeline/{id}/export: JSON, YAML with a YAML content type, or a zip"
+    ),
+    responses(
+        (status = 201, description = "Pipeline imported", body = ImportedPipeline),
+        (status = 400, description = "Invalid bundle, program files that are neither in the bundle nor owned already, listed as `missing`, or a pipeline create would reject"),
+        (status = 413, description = "Bundle larger than MAX_UPLOAD_SIZE"),
+    )
+)]
+pub async fn import_pipeline(
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<Storage>,
+    query: web::Query<ImportQuery>,
+    request: HttpRequest,
+    payload: web::Payload,
+) -> Result<HttpResponse, Error> {
+    let owner_id = query.owner_id;
+    let body = spool(payload, max_upload_size()).await?.read().await?;
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let (bundle, mut files) = read_bundle(&body, content_type)
+        .and_then(|(bundle, files)| check_bundle(&bundle).map(|()| (bundle, files)))
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Invalid bundle: {}", e)))?;
+
+    // Work out which program every key stands for before storing anything,
+    // so that a bundle which cannot be imported stores nothing. Files the
+    // owner already has are not stored twice.
+    let mut imports: Vec<ProgramImport> = Vec::with_capacity(bundle.programs.len());
+    let mut missing = Vec::new();
+    for bundled in &bundle.programs {
+        let data = bundled.file.as_ref().and_then(|path| files.remove(path));
+        let file_hash = match &data {
+            Some(data) => {
+                let file_hash = hex::encode(Sha256::digest(data));
+                if !bundled.file_hash.is_empty() && bundled.file_hash != file_hash {
+                    return Err(actix_web::error::ErrorBadRequest(format!(
+                        "The file of program {} does not match its file_hash",
+                        bundled.key
+                    )));
+                }
+                file_hash
+            }
+            None => bundled.file_hash.clone(),
+        };
+
+        let imported = imports
+            .iter()
+            .find(|import| !file_hash.is_empty() && import.program.file_hash == file_hash)
+            .map(|import| import.program.clone());
+        let existing = match imported {
+            Some(program) => Some(program),
+            None if file_hash.is_empty() => None,
+            None => db
+                .find_programs_by_hash(&file_hash, Some(owner_id))
+                .await
+                .map_err(database_error)?
+                .into_iter()
+                .next(),
+        };
+        match (existing, data) {
+            (Some(program), _) => imports.push(ProgramImport {
+                key: bundled.key.clone(),
+                program,
+                data: None,
+            }),
+            (None, Some(data)) => imports.push(ProgramImport {
+                key: bundled.key.clone(),
+                program: new_program(owner_id, bundled, file_hash, data.len()),
+                data: Some(data),
+            }),
+            (None, None) => missing.push(bundled.key.clone()),
+        }
+    }
+    if !missing.is_empty() {
+        let response = HttpResponse::BadRequest().json(json!({
+            "message": "The bundle does not hold the files of these programs and the owner has none with the same file_hash",
+            "missing": missing,
+        }));
+        return Err(InternalError::from_response("Missing program files", response).into());
+    }
+
+    let programs: HashMap<&str, &Program> = imports
+        .iter()
+        .map(|import| (import.key.as_str(), &import.program))
+        .collect();
+    let step_programs: Vec<Option<Program>> = bundle
+        .pipeline
+        .steps
+        .iter()
+        .map(|key| Some(programs[key.trim()].clone()))
+        .collect();
+    let mut pipeline = bundle
+        .pipeline
+        .into_pipeline(owner_id, |key| programs[key].id.to_hex());
+    let graph = check_graph(&pipeline)?;
+    check_schedule(pipeline.schedule.as_mut())?;
+    check_parameters(&pipeline)?;
+    check_policies(&pipeline)?;
+    check_types(&graph, &step_programs)?;
+
+    // The new programs are only of use to this pipeline: when storing one of
+    // them or the pipeline fails, those stored so far are removed again.
+    let mut stored = Vec::new();
+    let mut result = Ok(());
+    for import in &imports {
+        if let Some(data) = &import.data {
+            stored.push(&import.program);
+            result = store_program(&db, &storage, &import.program, data).await;
+            if result.is_err() {
+                break;
+            }
+        }
+    }
+    if result.is_ok() {
+        result = db.insert_pipeline(&pipeline).await.map_err(database_error);
+    }
+    if let Err(e) = result {
+        discard_programs(&db, &storage, &stored).await;
+        return Err(e);
+    }
+    info!(
+        "Imported pipeline {} for owner {} with {} programs",
+        pipeline.id,
+        owner_id,
+        imports.len()
+    );
+
+    Ok(HttpResponse::Created().json(ImportedPipeline {
+        pipeline,
+        programs: imports
+            .into_iter()
+            .map(|import| ImportedProgram {
+                key: import.key,
+                program_id: import.program.id.to_hex(),
+                created: import.data.is_some(),
+            })
+            .collect(),
+    }))
+}
+
+/* Private helper functions */
+
+/// What a key of the bundle becomes: a program the owner has, or a new one
+/// to store along with its file.
+struct ProgramImport {
+    key: String,
+    program: Program,
+    data: Option<Vec<u8>>,
+}
+
+fn default_cache_results() -> bool {
+    true
+}
+
+impl BundledPipeline {
+    fn of(pipeline: &Pipeline) -> Self {
+        BundledPipeline {
+            name: pipeline.name.clone(),
+            description: pipeline.description.clone(),
+            steps: pipeline.steps.clone(),
+            edges: pipeline.edges.clone(),
+            // The next run is worked out again on import.
+            schedule: pipeline.schedule.clone().map(|schedule| PipelineSchedule {
+                next_run: None,
+                ..schedule
+            }),
+            parameters: pipeline.parameters.clone(),
+            policies: pipeline.policies.clone(),
+            cache_results: pipeline.cache_results,
+        }
+    }
+
+    /// The pipeline for `owner_id`, with `program_id` turning every key into
+    /// the id of a program.
+    fn into_pipeline(self, owner_id: i32, program_id: impl Fn(&str) -> String) -> Pipeline {
+        Pipeline {
+            id: ObjectId::new(),
+            owner_id,
+            name: self.name,
+            description: self.description,
+            steps: self
+                .steps
+                .iter()
+                .map(|key| program_id(key.trim()))
+                .collect(),
+            edges: self.edges,
+            schedule: self.schedule,
+            parameters: self.parameters,
+            policies: self
+                .policies
+                .into_iter()
+                .map(|policy| StepPolicy {
+                    fallback: policy.fallback.as_deref().map(|key| program_id(key.trim())),
+                    ..policy
+                })
+                .collect(),
+            cache_results: self.cache_results,
+            created_date: Utc::now().to_string(),
+        }
+    }
+}
+
+impl BundledProgram {
+    fn of(program: &Program) -> Self {
+        BundledProgram {
+            key: program.id.to_hex(),
+            filename: uploaded_filename(program),
+            content_type: program.content_type.clone(),
+            input_type: program.input_type.clone(),
+            output_type: program.output_type.clone(),
+            file_hash: program.file_hash.clone(),
+            file_size: program.file_size,
+            file: None,
+        }
+    }
+}
+
+/// Keys of the programs run by the steps, then by the fallbacks, once each.
+fn program_keys<'a>(steps: &'a [String], policies: &'a [StepPolicy]) -> Vec<&'a str> {
+    let fallbacks = policies
+        .iter()
+        .filter_map(|policy| policy.fallback.as_ref());
+    let mut seen = HashSet::new();
+    steps
+        .iter()
+        .chain(fallbacks)
+        .map(|key| key.trim())
+        .filter(|key| seen.insert(*key))
+        .collect()
+}
+
+/// The bundle must be of a known version and every step and fallback must
+/// name one of its programs.
+fn check_bundle(bundle: &PipelineBundle) -> anyhow::Result<()> {
+    if bundle.version != BUNDLE_VERSION {
+        bail!(
+            "unsupported version {}, expected {}",
+            bundle.version,
+            BUNDLE_VERSION
+        );
+    }
+    let mut keys = HashSet::new();
+    for program in &bundle.programs {
+        if !keys.insert(program.key.as_str()) {
+            bail!("program {} is listed twice", program.key);
+        }
+        if base_filename(&program.filename).is_none() {
+            bail!(
+                "program {} has no usable filename {:?}",
+                program.key,
+                program.filename
+            );
+        }
+    }
+    for key in program_keys(&bundle.pipeline.steps, &bundle.pipeline.policies) {
+        if !keys.contains(key) {
+            bail!("program {} is used by the pipeline but not listed", key);
+        }
+    }
+    Ok(())
+}
+
+/// The bundle in `body`, a zip or a JSON or YAML document, along with the
+/// program files of a zip by path.
+fn read_bundle(
+    body: &[u8],
+    content_type: &str,
+) -> anyhow::Result<(PipelineBundle, HashMap<String, Vec<u8>>)> {
+    if body.starts_with(b"PK\x03\x04") {
+        return read_zip(body, max_upload_size());
+    }
+    let bundle = if content_type.contains("yaml") {
+        serde_yaml::from_slice(body)?
+    } else {
+        serde_json::from_slice(body)?
+    };
+    Ok((bundle, HashMap::new()))
+}
+
+/// Unpacks a zip bundle. Entries may claim any size, so the archive as a
+/// whole may not inflate to more than `max_size` bytes.
+fn read_zip(
+    body: &[u8],
+    max_size: u64,
+) -> anyhow::Result<(PipelineBundle, HashMap<String, Vec<u8>>)> {
+    let mut archive = ZipArchive::new(Cursor::new(body))?;
+    let mut budget = max_size;
+    let bundle: PipelineBundle = {
+        let entry = archive
+            .by_name(BUNDLE_FILE)
+            .map_err(|_| anyhow!("the zip holds no {}", BUNDLE_FILE))?;
+        serde_json::from_slice(&inflate(entry, BUNDLE_FILE, &mut budget, max_size)?)?
+    };
+
+    let mut files = HashMap::new();
+    for path in bundle.programs.iter().filter_map(|p| p.file.as_ref()) {
+        let entry = archive
+            .by_name(path)
+            .map_err(|_| anyhow!("the zip holds no {}", path))?;
+        let data = inflate(entry, path, &mut budget, max_size)?;
+        files.insert(path.clone(), data);
+    }
+    Ok((bundle, files))
+}
+
+/// Reads `entry` out of what is left of `budget`, failing rather than
+/// truncating it when it does not fit.
+fn inflate(
+    entry: impl Read,
+    path: &str,
+    budget: &mut u64,
+    max_size: u64,
+) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    entry.take(*budget + 1).read_to_end(&mut data)?;
+    let size = data.len() as u64;
+    if size > *budget {
+        return Err(anyhow!(
+            "the zip inflates to more than {} bytes at {}",
+            max_size,
+            path
+        ));
+    }
+    *budget -= size;
+    Ok(data)
+}
+
+fn write_zip(bundle: &PipelineBundle, files: &[(String, Vec<u8>)]) -> anyhow::Result<Vec<u8>> {
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file(BUNDLE_FILE, options)?;
+    zip.write_all(&serde_json::to_vec_pretty(bundle)?)?;
+    for (path, data) in files {
+        zip.start_file(path.as_str(), options)?;
+        zip.write_all(data)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Stored filenames carry the program id and a timestamp, see `upload`;
+/// bundles keep the name the program was uploaded with.
+fn uploaded_filename(program: &Program) -> String {
+    let marker = format!("-{}-", program.id.to_hex());
+    match program.filename.split_once(&marker) {
+        Some((base, rest)) => match rest.split_once('.') {
+            Some((_, extension)) => format!("{}.{}", base, extension),
+            None => base.to_string(),
+        },
+        None => program.filename.clone(),
+    }
+}
+
+/// A name for the downloaded bundle.
+fn file_stem(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if stem.is_empty() {
+        "pipeline".to_string()
+    } else {
+        stem
+    }
+}
+
+/// A program of `owner_id` for a file of the bundle, named and stored the
+/// way `upload` does it. Any directories in the bundled filename are dropped.
+fn new_program(
+    owner_id: i32,
+    bundled: &BundledProgram,
+    file_hash: String,
+    file_size: usize,
+) -> Program {
+    let id = ObjectId::new();
+    let now = Utc::now();
+    let name = base_filename(&bundled.filename).unwrap_or("program");
+    let filename = match name.rsplit_once('.') {
+        Some((base, extension)) => {
+            format!("{}-{}-{}.{}", base, id, now.timestamp_millis(), extension)
+        }
+        None => format!("{}-{}-{}", name, id, now.timestamp_millis()),
+    };
+    let file_path = format!("content/{}/{}", owner_id, filename);
+    Program {
+        id,
+        owner_id,
+        filename,
+        code_url: Program::code_url_for(&id),
+        content_type: bundled.content_type.clone(),
+        file_size: file_size as i64,
+        input_type: bundled.input_type.clone(),
+        output_type: bundled.output_type.clone(),
+        upload_time: now,
+        update_time: now,
+        file_path,
+        file_hash,
+        current_version: 1,
+    }
+}
+
+async fn store_program(
+    db: &DatabaseConnection,
+    storage: &Storage,
+    program: &Program,
+    data: &[u8],
+) -> Result<(), Error> {
+    storage
+        .put(&program.file_path, &program.content_type, data.to_vec())
+        .await
+        .map_err(|e| {
+            actix_web::error::ErrorInternalServerError(format!("Error uploading to storage: {}", e))
+        })?;
+    record_version(db, &ProgramVersion::from_program(program, 1)).await?;
+    db.insert_program(program).await.map_err(|e| {
+        log::error!("Insert error: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Insert error")
+    })
+}
+
+/// Removes programs stored by an import that failed, along with their
+/// versions and files. Failures are only logged: the import has failed
+/// already.
+async fn discard_programs(db: &DatabaseConnection, storage: &Storage, programs: &[&Program]) {
+    for program in programs {
+        if let Err(e) = db.delete_program(&program.id).await {
+            warn!("Error deleting imported program {}: {:?}", program.id, e);
+        }
+        if let Err(e) = db.delete_versions(&program.id).await {
+            warn!(
+                "Error deleting versions of imported program {}: {:?}",
+                program.id, e
+            );
+        }
+        if let Err(e) = storage.delete(&program.file_path).await {
+            warn!("Error deleting {} from storage: {:?}", program.file_path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zip_bundles_carry_program_files() {
+        let program = BundledProgram {
+            key: "a".to_string(),
+            filename: "hello.py".to_string(),
+            content_type: "text/x-python".to_string(),
+            input_type: "*/*".to_string(),
+            output_type: ".txt".to_string(),
+            file_hash: String::new(),
+            file_size: 14,
+            file: Some("programs/a/hello.py".to_string()),
+        };
+        let bundle = PipelineBundle {
+            version: BUNDLE_VERSION,
+            exported_at: Utc::now(),
+            pipeline: BundledPipeline {
+                name: "hello".to_string(),
+                description: String::new(),
+                steps: vec!["a".to_string()],
+                edges: None,
+                schedule: None,
+                parameters: Vec::new(),
+                policies: Vec::new(),
+                cache_results: true,
+            },
+            programs: vec![program],
+        };
+        let files = vec![(
+            "programs/a/hello.py".to_string(),
+            b"print('hello')".to_vec(),
+        )];
+
+        let body = write_zip(&bundle, &files).unwrap();
+        let (read, files) = read_bundle(&body, "application/octet-stream").unwrap();
+        check_bundle(&read).unwrap();
+        assert_eq!(read.pipeline.steps, vec!["a"]);
+        assert_eq!(files["programs/a/hello.py"], b"print('hello')");
+
+        let inflated = (serde_json::to_vec_pretty(&bundle).unwrap().len() + 14) as u64;
+        assert!(read_zip(&body, inflated).is_ok());
+        assert!(read_zip(&body, inflated - 1).is_err());
+
+        let yaml = serde_yaml::to_string(&bundle).unwrap();
+        let (read, _) = read_bundle(yaml.as_bytes(), "application/yaml").unwrap();
+        assert_eq!(read.programs[0].filename, "hello.py");
+
+        let mut unknown = read;
+        unknown.pipeline.steps.push("b".to_string());
+        assert!(check_bundle(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_bundled_filenames_stay_in_the_owner_directory() {
+        let mut program = BundledProgram {
+            key: "a".to_string(),
+            filename: "../../other/..\\evil.py".to_string(),
+            content_type: "text/x-python".to_string(),
+            input_type: "*/*".to_string(),
+            output_type: ".txt".to_string(),
+            file_hash: String::new(),
+            file_size: 0,
+            file: None,
+        };
+        let imported = new_program(1, &program, String::new(), 0);
+        assert!(imported.filename.starts_with("evil-"));
+        assert_eq!(
+            imported.file_path,
+            format!("content/1/{}", imported.filename)
+        );
+
+        program.filename = "programs/..".to_string();
+        let bundle = PipelineBundle {
+            version: BUNDLE_VERSION,
+            exported_at: Utc::now(),
+            pipeline: BundledPipeline {
+                name: "evil".to_string(),
+                description: String::new(),
+                steps: vec!["a".to_string()],
+                edges: None,
+                schedule: None,
+                parameters: Vec::new(),
+                policies: Vec::new(),
+                cache_results: true,
+            },
+            programs: vec![program],
+        };
+        assert!(check_bundle(&bundle).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use actix_multipart::Multipart;
+use actix_web::http::header;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use bson::oid::ObjectId;
+use futures::{StreamExt, TryStreamExt};
+use log::{error, warn};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use shared::database::{db_interface::DatabaseConnection, pipeline_repository::PipelineRepository};
+use shared::execution::engine::{ExecutionInput, InputFile};
+use shared::execution::{
+    cancel::CancelRegistry, engine, events::EventHub, parameters, sandbox::Sandbox,
+};
+use shared::storage::blob_store::Storage;
+use utoipa::ToSchema;
+
+use crate::utils::error::{database_error, sandbox_error};
+use crate::utils::spool::process_file_field;
+
+/// Largest JSON body, or `parameters` form field, accepted.
+const MAX_PARAMETERS_SIZE: usize = 64 * 1024;
+
+/// Body of an execute request. As `multipart/form-data`, `parameters` is a
+/// JSON text field and the file to give to the first steps goes in `input`.
+#[derive(Deserialize, ToSchema)]
+pub struct ExecutePipeline {
+    /// Parameter values by name; parameters left out take their default.
+    #[serde(default)]
+    #[schema(value_type = Object, example = json!({"limit": 10}))]
+    pub parameters: Map<String, Value>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/pipeline/{id}/execute",
+    tag = "pipeline",
+    params(("id"=String, Path, description = "Execute Pipeline by id")),
+    responses(
+        (status = 202, description = "Execution started, follow it at /executions/{id}", body = ExecutionRecord),
+        (status = 400, description = "Invalid ID format, or missing, unknown or mistyped parameters"),
+        (status = 404, description = "Pipeline not found"),
+        (status = 503, description = "Programs cannot run on this server, see EXECUTION_SANDBOX"),
+    ),
+    request_body(
+        content_type = "application/json",
+        content = Option<ExecutePipeline>,
+        description = "Optional; send multipart/form-data with `parameters` and an `input` file to give the steps without inputs a file on stdin"
+    ),
+)]
+pub async fn execute_pipeline(
+    db: web::Data<DatabaseConnection>,
+    storage: web::Data<Storage>,
+    events: web::Data<EventHub>,
+    cancels: web::Data<CancelRegistry>,
+    id: web::Path<String>,
+    request: HttpRequest,
+    payload: web::Payload,
+) -> Result<HttpResponse, Error> {
+    let object_id = match ObjectId::parse_str(id.as_ref().trim()) {
+        Ok(oid) => oid,
+        Err(e) => {
+            warn!("Invalid ID format: {}", e);
+            return Err(actix_web::error::ErrorBadRequest("Invalid ID format"));
+        }
+    };
+
+    Sandbox::shared().await.map_err(sandbox_error)?;
+
+    let (values, file) = read_request(&request, payload).await?;
+
+    let pipeline = match db.find_pipeline(&object_id).await.map_err(database_error)? {
+        Some(pipeline) => pipeline,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let parameters = parameters::resolve(&pipeline.parameters, &values)
+        .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))?;
+    let input = ExecutionInput { parameters, file };
+
+    let record = engine::start_execution(&db, &storage, &events, &cancels, &pipeline, input)
+        .await
+        .map_err(|e| {
+            error!(
+                "Execution of pipeline {} failed to start: {:?}",
+                pipeline.id, e
+            );
+            actix_web::error::ErrorInternalServerError(format!("Execution failed: {}", e))
+        })?;
+
+    Ok(HttpResponse::Accepted()
+        .insert_header((header::LOCATION, format!("/v1/executions/{}", record.id)))
+        .json(record))
+}
+
+/* Private helper functions */
+
+/// The parameter values and input file of an execute request, which may
+/// have no body at all.
+async fn read_request(
+    request: &HttpRequest,
+    payload: web::Payload,
+) -> Result<(Map<String, Value>, Option<InputFile>), Error> {
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if content_type.starts_with("multipart/form-data") {
+        return read_form(Multipart::new(request.headers(), payload)).await;
+    }
+
+    let body = payload
+        .to_bytes_limited(MAX_PARAMETERS_SIZE)
+        .await
+        .map_err(|_| actix_web::error::ErrorBadRequest("Request body is too large"))??;
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok((Map::new(), None));
+    }
+    let request: ExecutePipeline = serde_json::from_slice(&body)
+        .map_err(|e| actix_web::error::ErrorBadRequest(format!("Invalid request body: {}", e)))?;
+    Ok((request.parameters, None))
+}
+
+async fn read_form(
+    mut payload: Multipart,
+) -> Result<(Map<String, Value>, Option<InputFile>), Error> {
+    let mut values = Map::new();
+    let mut file = None;
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+        match field.name() {
+            "input" => {
+                let (filename, content_type, data) = process_file_field(field).await?;
+                file = Some(InputFile {
+                    filename,
+                    content_type,
+                    data: data.read().await?,
+                });
+            }
+            "parameters" => {
+                let mut data = Vec::new();
+                while let Some(chunk) = field.try_next().await? {
+                    if data.len() + chunk.len() > MAX_PARAMETERS_SIZE {
+                        return Err(actix_web::error::ErrorBadRequest(
+                            "parameters field is too large",
+                        ));
+                    }
+                    data.extend_from_slice(&chunk);
+                }
+                values = serde_json::from_slice(&data).map_err(|e| {
+                    actix_web::error::ErrorBadRequest(format!("Invalid parameters field: {}", e))
+                })?;
+            }
+            _ => {}
+        }
+    }
+    Ok((values, file))
+}
@@ -1,12 +1,21 @@
+use actix_web::error::InternalError;
 use actix_web::{web, Error, HttpResponse};
 use bson::oid::ObjectId;
-use futures::StreamExt;
+use chrono::Utc;
 use log::{debug, info, warn};
-use mongodb::{bson::doc, Collection, Database};
-use shared::models::{
-    pipeline::{CreatePipeline, Pipeline, UpdatePipeline},
-    program::Program,
+use serde_json::json;
+use shared::database::{
+    db_interface::DatabaseConnection, pipeline_repository::PipelineRepository,
+    program_repository::ProgramRepository,
 };
+use shared::execution::{graph::PipelineGraph, parameters, policy, runner::Limits, scheduler};
+use shared::models::pipeline::{
+    CreatePipeline, Pipeline, PipelineSchedule, TypeMismatch, UpdatePipeline,
+};
+use shared::models::program::Program;
+
+use crate::utils::error::database_error;
+
 #[utoipa::path(
     get,
     path = "/pipeline/{id}",
@@ -18,11 +27,9 @@ use shared::models::{
     )
 )]
 pub async fn get_pipeline(
-    db: web::Data<Database>,
+    db: web::Data<DatabaseConnection>,
     id: web::Path<String>,
 ) -> Result<HttpResponse, Error> {
-    let collection: Collection<Pipeline> = db.collection("pipelines");
-
     let id_str = id.as_ref().trim();
     info!("Raw ID string: {}", id_str);
 
@@ -36,14 +43,9 @@ pub async fn get_pipeline(
 
     info!("Parsed ObjectId: {}", object_id);
 
-    let result = collection.find_one(doc! {"_id": object_id}, None).await;
-    match result {
-        Ok(Some(program)) => Ok(HttpResponse::Ok().json(program)),
-        Ok(None) => Ok(HttpResponse::NotFound().finish()),
-        Err(e) => Err(actix_web::error::ErrorInternalServerError(format!(
-            "Database query failed: {}",
-            e
-        ))),
+    match db.find_pipeline(&object_id).await.map_err(database_error)? {
+        Some(pipeline) => Ok(HttpResponse::Ok().json(pipeline)),
+        None => Ok(HttpResponse::NotFound().finish()),
     }
 }
 
@@ -58,48 +60,21 @@ pub async fn get_pipeline(
     )
 )]
 pub async fn get_pipelines_by_owner(
-    db: web::Data<Database>,
+    db: web::Data<DatabaseConnection>,
     owner_id: web::Path<i32>,
 ) -> Result<HttpResponse, Error> {
-    let collection: Collection<Pipeline> = db.collection("pipelines");
-
     let owner_id_value = owner_id.into_inner();
     log::info!("Searching for pipelines with owner_id: {}", owner_id_value);
 
-    let filter = doc! {"owner_id": owner_id_value};
-    let result = collection.find(filter, None).await;
-
-    match result {
-        Ok(cursor) => {
-            let programs: Vec<Pipeline> = cursor
-                .filter_map(|item| async move {
-                    match item {
-                        Ok(program) => {
-                            log::info!("Found program: {:?}", program);
-                            Some(program)
-                        }
-                        Err(e) => {
-                            log::error!("Error reading program: {}", e);
-                            None
-                        }
-                    }
-                })
-                .collect()
-                .await;
+    let pipelines = db
+        .find_pipelines_by_owner(owner_id_value)
+        .await
+        .map_err(database_error)?;
 
-            if programs.is_empty() {
-                log::warn!("No pipelines found for owner_id: {}", owner_id_value);
-            }
-            Ok(HttpResponse::Ok().json(programs))
-        }
-        Err(e) => {
-            log::error!("Database query failed: {}", e);
-            Err(actix_web::error::ErrorInternalServerError(format!(
-                "Database query failed: {}",
-                e
-            )))
-        }
+    if pipelines.is_empty() {
+        log::warn!("No pipelines found for owner_id: {}", owner_id_value);
     }
+    Ok(HttpResponse::Ok().json(pipelines))
 }
 
 #[utoipa::path(
@@ -111,41 +86,13 @@ pub async fn get_pipelines_by_owner(
         (status = 404, description = "No pipelines found"),
     )
 )]
-pub async fn list_pipelines(db: web::Data<Database>) -> Result<HttpResponse, Error> {
-    let collection: Collection<Pipeline> = db.collection("pipelines");
+pub async fn list_pipelines(db: web::Data<DatabaseConnection>) -> Result<HttpResponse, Error> {
+    let pipelines = db.list_pipelines().await.map_err(database_error)?;
 
-    let result = collection.find(None, None).await;
-    match result {
-        Ok(cursor) => {
-            let pipelines: Vec<Pipeline> = cursor
-                .filter_map(|item| async move {
-                    match item {
-                        Ok(pipeline) => {
-                            log::info!("Found pipeline: {:?}", pipeline);
-                            Some(pipeline)
-                        }
-                        Err(e) => {
-                            log::error!("Error reading pipeline: {}", e);
-                            None
-                        }
-                    }
-                })
-                .collect()
-                .await;
-
-            if pipelines.is_empty() {
-                log::warn!("No pipelines found");
-            }
-            Ok(HttpResponse::Ok().json(pipelines))
-        }
-        Err(e) => {
-            log::error!("Database query failed: {}", e);
-            Err(actix_web::error::ErrorInternalServerError(format!(
-                "Database query failed: {}",
-                e
-            )))
-        }
+    if pipelines.is_empty() {
+        log::warn!("No pipelines found");
     }
+    Ok(HttpResponse::Ok().json(pipelines))
 }
 
 #[utoipa::path(
@@ -154,7 +101,7 @@ pub async fn list_pipelines(db: web::Data<Database>) -> Result<HttpResponse, Err
     tag = "pipeline",
     responses(
         (status = 201, description = "Pipeline created successfully", body = Pipeline),
-        (status = 400, description = "Bad Request"),
+        (status = 400, description = "Unknown program, invalid edges such as a cycle, invalid schedule, parameters or policies, or a list of type `mismatches`", body = Vec<TypeMismatch>),
     ),
     request_body(
         content_type = "application/json",
@@ -162,37 +109,20 @@ pub async fn list_pipelines(db: web::Data<Database>) -> Result<HttpResponse, Err
     ),
 )]
 pub async fn create_pipeline(
-    db: web::Data<Database>,
+    db: web::Data<DatabaseConnection>,
     pipeline: web::Json<CreatePipeline>,
 ) -> Result<HttpResponse, Error> {
-    let collection: Collection<Pipeline> = db.collection("pipelines");
-
-    let create_pipeline = pipeline.into_inner();
-
-    check_programs_exist(&db, &create_pipeline.steps).await?;
-
-    let pipeline: Pipeline = create_pipeline.into();
-    let result = collection.insert_one(&pipeline, None).await;
-
-    match result {
-        Ok(inserted) => {
-            let pipeline_id = inserted.inserted_id.as_object_id().unwrap();
-            let pipeline = collection.find_one(doc! {"_id": pipeline_id}, None).await;
-
-            match pipeline {
-                Ok(Some(pipeline)) => Ok(HttpResponse::Created().json(pipeline)),
-                Ok(None) => Ok(HttpResponse::NotFound().finish()),
-                Err(e) => Err(actix_web::error::ErrorInternalServerError(format!(
-                    "Database query failed: {}",
-                    e
-                ))),
-            }
-        }
-        Err(e) => Err(actix_web::error::ErrorInternalServerError(format!(
-            "Database query failed: {}",
-            e
-        ))),
-    }
+    let mut pipeline: Pipeline = pipeline.into_inner().into();
+    let graph = check_graph(&pipeline)?;
+    check_schedule(pipeline.schedule.as_mut())?;
+    check_parameters(&pipeline)?;
+    check_policies(&pipeline)?;
+    check_programs_exist(&db, &pipeline, &graph).await?;
+    db.insert_pipeline(&pipeline)
+        .await
+        .map_err(database_error)?;
+
+    Ok(HttpResponse::Created().json(pipeline))
 }
 
 #[utoipa::path(
@@ -206,11 +136,9 @@ pub async fn create_pipeline(
     )
 )]
 pub async fn delete_pipeline(
-    db: web::Data<Database>,
+    db: web::Data<DatabaseConnection>,
     id: web::Path<String>,
 ) -> Result<HttpResponse, Error> {
-    let collection: Collection<Pipeline> = db.collection("pipelines");
-
     let id_str = id.as_ref().trim();
     debug!("Raw ID string: {}", id_str);
 
@@ -224,14 +152,14 @@ pub async fn delete_pipeline(
 
     debug!("Parsed ObjectId: {}", object_id);
 
-    let result = collection.delete_one(doc! {"_id": object_id}, None).await;
-    match result {
-        Ok(deleted) if deleted.deleted_count == 1 => Ok(HttpResponse::NoContent().finish()),
-        Ok(_) => Ok(HttpResponse::NotFound().finish()),
-        Err(e) => Err(actix_web::error::ErrorInternalServerError(format!(
-            "Database query failed: {}",
-            e
-        ))),
+    if db
+        .delete_pipeline(&object_id)
+        .await
+        .map_err(database_error)?
+    {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
     }
 }
 
@@ -242,7 +170,7 @@ pub async fn delete_pipeline(
     params(("id"=String, Path, description = "Update Pipeline by id")),
     responses(
         (status = 200, description = "Pipeline updated successfully", body = Pipeline),
-        (status = 400, description = "Bad Request"),
+        (status = 400, description = "Unknown program, invalid edges such as a cycle, invalid schedule, parameters or policies, or a list of type `mismatches`", body = Vec<TypeMismatch>),
         (status = 404, description = "Pipeline not found"),
     ),
     request_body(
@@ -251,12 +179,10 @@ pub async fn delete_pipeline(
     ),
 )]
 pub async fn update_pipeline(
-    db: web::Data<Database>,
+    db: web::Data<DatabaseConnection>,
     id: web::Path<String>,
     update_pipeline: web::Json<UpdatePipeline>,
 ) -> Result<HttpResponse, Error> {
-    let collection: Collection<Pipeline> = db.collection("pipelines");
-
     let id_str = id.as_ref().trim();
     debug!("Raw ID string: {}", id_str);
 
@@ -270,73 +196,126 @@ pub async fn update_pipeline(
 
     debug!("Parsed ObjectId: {}", object_id);
 
-    let update_pipeline = update_pipeline.into_inner();
-    check_programs_exist(&db, &update_pipeline.steps).await?;
+    let mut update_pipeline = update_pipeline.into_inner();
+    check_schedule(update_pipeline.schedule.as_mut())?;
 
-    let update_doc = update_pipeline.build_update_document();
-    let update_command = doc! {
-        "$set": update_doc,
-        "$currentDate": {"update_time": true}
+    // The new steps may not fit the stored edges or the other way round.
+    let mut updated = match db.find_pipeline(&object_id).await.map_err(database_error)? {
+        Some(pipeline) => pipeline,
+        None => return Ok(HttpResponse::NotFound().finish()),
     };
+    update_pipeline.apply(&mut updated);
+    let graph = check_graph(&updated)?;
+    check_parameters(&updated)?;
+    check_policies(&updated)?;
+    check_programs_exist(&db, &updated, &graph).await?;
+
+    match db
+        .update_pipeline(&object_id, &update_pipeline)
+        .await
+        .map_err(database_error)?
+    {
+        Some(pipeline) => Ok(HttpResponse::Ok().json(pipeline)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
 
-    let result = collection
-        .update_one(doc! {"_id": object_id}, update_command, None)
-        .await;
-
-    match result {
-        Ok(update) if update.matched_count == 1 => {
-            let pipeline = collection.find_one(doc! {"_id": object_id}, None).await;
+/* Private helper functions */
+pub(super) fn check_graph(pipeline: &Pipeline) -> Result<PipelineGraph, Error> {
+    PipelineGraph::of(pipeline).map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))
+}
 
-            match pipeline {
-                Ok(Some(pipeline)) => Ok(HttpResponse::Ok().json(pipeline)),
-                Ok(None) => Ok(HttpResponse::NotFound().finish()),
-                Err(e) => Err(actix_web::error::ErrorInternalServerError(format!(
-                    "Database query failed: {}",
-                    e
-                ))),
-            }
-        }
-        Ok(_) => Ok(HttpResponse::NotFound().finish()),
-        Err(e) => Err(actix_web::error::ErrorInternalServerError(format!(
-            "Database query failed: {}",
-            e
-        ))),
+/// Checks the schedule and works out when it runs next.
+pub(super) fn check_schedule(schedule: Option<&mut PipelineSchedule>) -> Result<(), Error> {
+    match schedule {
+        Some(schedule) => scheduler::prepare(schedule, Utc::now())
+            .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string())),
+        None => Ok(()),
     }
 }
 
-/* Private helper functions */
-async fn check_programs_exist(db: &Database, steps: &Vec<String>) -> Result<(), Error> {
-    let programs_collection: Collection<Program> = db.collection("programs");
-    for step in steps {
+pub(super) fn check_parameters(pipeline: &Pipeline) -> Result<(), Error> {
+    parameters::check_pipeline(pipeline)
+        .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))
+}
+
+pub(super) fn check_policies(pipeline: &Pipeline) -> Result<(), Error> {
+    policy::check(pipeline, &Limits::from_env())
+        .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))
+}
+
+/// Every step and fallback must name an existing program, and each program
+/// must accept the output of the programs feeding it, see `check_types`.
+async fn check_programs_exist(
+    db: &DatabaseConnection,
+    pipeline: &Pipeline,
+    graph: &PipelineGraph,
+) -> Result<(), Error> {
+    let mut programs: Vec<Option<Program>> = Vec::with_capacity(pipeline.steps.len());
+    for step in &pipeline.steps {
         let program_id = match ObjectId::parse_str(step) {
             Ok(id) => id,
             Err(e) => {
-                return Err(actix_web::error::ErrorInternalServerError(format!(
-                    "Failed to parse program ID: {}",
-                    e
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "Invalid program ID {}: {}",
+                    step, e
                 )));
             }
         };
 
-        let program: Result<Option<Program>, mongodb::error::Error> = programs_collection
-            .find_one(doc! {"_id": program_id}, None)
-            .await;
-
-        match program {
-            Ok(Some(_)) => {}
-            Ok(None) => {
+        match db.find_program(&program_id).await.map_err(database_error)? {
+            Some(program) => programs.push(Some(program)),
+            None => {
                 return Err(actix_web::error::ErrorBadRequest(format!(
                     "Program not found: {}",
                     program_id
                 )));
             }
-            Err(e) => {
-                return Err(actix_web::error::ErrorInternalServerError(format!(
-                    "Database query failed: {}",
-                    e
-                )));
-            }
         }
     }
-    Ok(())
+
+    // `check_policies` made sure the ids parse.
+    let fallbacks = pipeline.policies.iter().filter_map(|p| p.fallback.as_ref());
+    for fallback in fallbacks {
+        let program_id = ObjectId::parse_str(fallback.trim())
+            .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))?;
+        if db
+            .find_program(&program_id)
+            .await
+            .map_err(database_error)?
+            .is_none()
+        {
+            return Err(actix_web::error::ErrorBadRequest(format!(
+                "Fallback program not found: {}",
+                program_id
+            )));
+        }
+    }
+
+    check_types(graph, &programs)
+}
+
+/// Every step must accept the output of the steps feeding it; `programs`
+/// holds the program of every step, in order. Type mismatches are all
+/// listed, along with the edges whose types cannot be told; those alone
+/// are only logged.
+pub(super) fn check_types(
+    graph: &PipelineGraph,
+    programs: &[Option<Program>],
+) -> Result<(), Error> {
+    let mismatches = graph.type_mismatches(programs);
+    if !mismatches.iter().any(TypeMismatch::is_error) {
+        for mismatch in &mismatches {
+            warn!(
+                "Cannot tell whether step {} accepts the output of step {} ({} to {})",
+                mismatch.to_step, mismatch.from_step, mismatch.output_type, mismatch.input_type
+            );
+        }
+        return Ok(());
+    }
+    let response = HttpResponse::BadRequest().json(json!({
+        "message": "Step output types are not accepted by the steps they feed",
+        "mismatches": mismatches,
+    }));
+    Err(InternalError::from_response("Step type mismatch", response).into())
 }
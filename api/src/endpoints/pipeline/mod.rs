@@ -1,2 +1,6 @@
+pub mod bundle;
+pub mod execute;
 pub mod metadata;
 pub mod routes;
+pub mod schedule;
+pub mod validate;
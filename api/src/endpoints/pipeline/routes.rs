@@ -1,18 +1,31 @@
 use actix_web::web;
 
+use super::bundle::{export_pipeline, import_pipeline};
+use super::execute::execute_pipeline;
 use super::metadata::{
     create_pipeline, delete_pipeline, get_pipeline, get_pipelines_by_owner, list_pipelines,
     update_pipeline,
 };
+use super::schedule::{list_upcoming_runs, pause_schedule, resume_schedule};
+use super::validate::validate_pipeline;
+use crate::endpoints::execution::history::list_pipeline_executions;
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/pipeline")
             .route("/list", web::get().to(list_pipelines))
             .route("/create", web::post().to(create_pipeline))
+            .route("/validate", web::post().to(validate_pipeline))
+            .route("/import", web::post().to(import_pipeline))
+            .route("/schedules/upcoming", web::get().to(list_upcoming_runs))
             .route("/{id}", web::get().to(get_pipeline))
             .route("/{id}", web::delete().to(delete_pipeline))
             .route("/{id}", web::put().to(update_pipeline))
+            .route("/{id}/export", web::get().to(export_pipeline))
+            .route("/{id}/execute", web::post().to(execute_pipeline))
+            .route("/{id}/executions", web::get().to(list_pipeline_executions))
+            .route("/{id}/schedule/pause", web::post().to(pause_schedule))
+            .route("/{id}/schedule/resume", web::post().to(resume_schedule))
             .route("/owner/{id}", web::get().to(get_pipelines_by_owner)),
     );
 }
@@ -0,0 +1,147 @@
+use actix_web::{web, Error, HttpResponse};
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use shared::database::{db_interface::DatabaseConnection, pipeline_repository::PipelineRepository};
+use shared::execution::scheduler;
+use utoipa::ToSchema;
+
+use crate::utils::error::database_error;
+
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 100;
+
+#[derive(Deserialize)]
+pub struct UpcomingQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UpcomingRun {
+    #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
+    pub pipeline_id: String,
+    #[schema(example = "example_pipeline")]
+    pub name: String,
+    #[schema(example = "2024-08-02T00:00:00Z")]
+    pub run_time: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/pipeline/schedules/upcoming",
+    tag = "pipeline",
+    params(("limit"=Option<usize>, Query, description = "How many runs to list, 20 by default and at most 100")),
+    responses(
+        (status = 200, description = "The next runs of all enabled schedules, soonest first", body = Vec<UpcomingRun>),
+    )
+)]
+pub async fn list_upcoming_runs(
+    db: web::Data<DatabaseConnection>,
+    query: web::Query<UpcomingQuery>,
+) -> Result<HttpResponse, Error> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let pipelines = db
+        .find_scheduled_pipelines()
+        .await
+        .map_err(database_error)?;
+
+    let mut runs = Vec::new();
+    for pipeline in pipelines {
+        let Some(schedule) = &pipeline.schedule else {
+            continue;
+        };
+        let run_times = match scheduler::upcoming_runs(schedule, limit) {
+            Ok(run_times) => run_times,
+            Err(e) => {
+                warn!("Pipeline {} has a broken schedule: {}", pipeline.id, e);
+                continue;
+            }
+        };
+        runs.extend(run_times.into_iter().map(|run_time| UpcomingRun {
+            pipeline_id: pipeline.id.to_hex(),
+            name: pipeline.name.clone(),
+            run_time,
+        }));
+    }
+    runs.sort_by_key(|run| run.run_time);
+    runs.truncate(limit);
+    Ok(HttpResponse::Ok().json(runs))
+}
+
+#[utoipa::path(
+    post,
+    path = "/pipeline/{id}/schedule/pause",
+    tag = "pipeline",
+    params(("id"=String, Path, description = "Pipeline id")),
+    responses(
+        (status = 200, description = "Schedule paused", body = Pipeline),
+        (status = 400, description = "Invalid ID format"),
+        (status = 404, description = "Pipeline not found"),
+        (status = 409, description = "The pipeline has no schedule"),
+    )
+)]
+pub async fn pause_schedule(
+    db: web::Data<DatabaseConnection>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    set_enabled(&db, &id, false).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/pipeline/{id}/schedule/resume",
+    tag = "pipeline",
+    params(("id"=String, Path, description = "Pipeline id")),
+    responses(
+        (status = 200, description = "Schedule resumed from the next run after now", body = Pipeline),
+        (status = 400, description = "Invalid ID format"),
+        (status = 404, description = "Pipeline not found"),
+        (status = 409, description = "The pipeline has no schedule"),
+    )
+)]
+pub async fn resume_schedule(
+    db: web::Data<DatabaseConnection>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    set_enabled(&db, &id, true).await
+}
+
+/* Private helper functions */
+async fn set_enabled(
+    db: &DatabaseConnection,
+    id: &str,
+    enabled: bool,
+) -> Result<HttpResponse, Error> {
+    let object_id = match ObjectId::parse_str(id.trim()) {
+        Ok(oid) => oid,
+        Err(e) => {
+            warn!("Invalid ID format: {}", e);
+            return Err(actix_web::error::ErrorBadRequest("Invalid ID format"));
+        }
+    };
+
+    let pipeline = match db.find_pipeline(&object_id).await.map_err(database_error)? {
+        Some(pipeline) => pipeline,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let Some(mut schedule) = pipeline.schedule else {
+        return Ok(HttpResponse::Conflict().body("The pipeline has no schedule"));
+    };
+
+    schedule.enabled = enabled;
+    scheduler::prepare(&mut schedule, Utc::now()).map_err(|e| {
+        // Schedules are checked when they are stored.
+        error!("Pipeline {} has a broken schedule: {}", object_id, e);
+        actix_web::error::ErrorInternalServerError(e.to_string())
+    })?;
+
+    match db
+        .set_schedule(&object_id, &schedule)
+        .await
+        .map_err(database_error)?
+    {
+        Some(pipeline) => Ok(HttpResponse::Ok().json(pipeline)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
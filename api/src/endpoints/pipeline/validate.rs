@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+
+use actix_web::{web, Error, HttpResponse};
+use bson::oid::ObjectId;
+use chrono::Utc;
+use serde::Serialize;
+use shared::database::{db_interface::DatabaseConnection, program_repository::ProgramRepository};
+use shared::execution::{graph::PipelineGraph, parameters, policy, runner::Limits, scheduler};
+use shared::models::pipeline::{CreatePipeline, Pipeline, TypeMismatch};
+use shared::models::program::Program;
+use utoipa::ToSchema;
+
+use crate::utils::error::database_error;
+
+#[derive(Serialize, ToSchema)]
+pub struct StepProblem {
+    #[schema(example = 2)]
+    pub step: i32,
+    #[schema(example = "not-an-id")]
+    pub program_id: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DuplicateStep {
+    #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
+    pub program_id: String,
+    #[schema(example = json!([1, 3]))]
+    pub steps: Vec<i32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PlannedStep {
+    #[schema(example = 2)]
+    pub step: i32,
+    #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
+    pub program_id: String,
+    /// `None` when the program does not exist.
+    #[schema(example = "example.py")]
+    pub filename: Option<String>,
+    /// Steps whose stdout this step reads, in that order.
+    #[schema(example = json!([1]))]
+    pub inputs: Vec<i32>,
+    /// Steps of the same stage can run at the same time.
+    #[schema(example = 1)]
+    pub stage: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ValidationReport {
+    /// Whether the pipeline would be accepted by `/pipeline/create`.
+    #[schema(example = false)]
+    pub valid: bool,
+    pub malformed_ids: Vec<StepProblem>,
+    pub missing_programs: Vec<StepProblem>,
+    /// Programs used by several steps; allowed, but often a mistake.
+    pub duplicate_steps: Vec<DuplicateStep>,
+    /// Why the edges cannot be used, such as a cycle.
+    #[schema(example = "Pipeline steps 2, 3 form a cycle")]
+    pub edge_error: Option<String>,
+    /// Why the parameters cannot be used, such as a mistyped default.
+    #[schema(example = "Parameter limit must be of type integer, got \"ten\"")]
+    pub parameter_error: Option<String>,
+    /// Why the step policies cannot be used, such as a missing fallback
+    /// program.
+    #[schema(example = "Step 2 has no fallback program")]
+    pub policy_error: Option<String>,
+    /// Why the schedule cannot be used, such as a bad cron expression or
+    /// time zone.
+    #[schema(example = "Unknown time zone \"Mars/Olympus\"")]
+    pub schedule_error: Option<String>,
+    /// Type mismatches, and edges whose types cannot be told, flagged
+    /// `unknown`; only the former make the pipeline invalid.
+    pub type_mismatches: Vec<TypeMismatch>,
+    /// The steps by stage, then by step number; empty when the edges are
+    /// invalid.
+    pub plan: Vec<PlannedStep>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/pipeline/validate",
+    tag = "pipeline",
+    responses(
+        (status = 200, description = "What is wrong with the pipeline and how it would run; nothing is stored", body = ValidationReport),
+        (status = 400, description = "Bad Request"),
+    ),
+    request_body(
+        content_type = "application/json",
+        content = CreatePipeline
+    ),
+)]
+pub async fn validate_pipeline(
+    db: web::Data<DatabaseConnection>,
+    pipeline: web::Json<CreatePipeline>,
+) -> Result<HttpResponse, Error> {
+    let pipeline: Pipeline = pipeline.into_inner().into();
+
+    let mut malformed_ids = Vec::new();
+    let mut missing_programs = Vec::new();
+    let mut programs: Vec<Option<Program>> = Vec::with_capacity(pipeline.steps.len());
+    let mut uses: BTreeMap<&str, Vec<i32>> = BTreeMap::new();
+    for (index, step) in pipeline.steps.iter().enumerate() {
+        let problem = || StepProblem {
+            step: index as i32 + 1,
+            program_id: step.clone(),
+        };
+        uses.entry(step.trim()).or_default().push(index as i32 + 1);
+        let program = match ObjectId::parse_str(step.trim()) {
+            Ok(program_id) => db.find_program(&program_id).await.map_err(database_error)?,
+            Err(_) => {
+                malformed_ids.push(problem());
+                programs.push(None);
+                continue;
+            }
+        };
+        if program.is_none() {
+            missing_programs.push(problem());
+        }
+        programs.push(program);
+    }
+    let duplicate_steps = uses
+        .into_iter()
+        .filter(|(_, steps)| steps.len() > 1)
+        .map(|(program_id, steps)| DuplicateStep {
+            program_id: program_id.to_string(),
+            steps,
+        })
+        .collect();
+
+    let (edge_error, type_mismatches, plan) = match PipelineGraph::of(&pipeline) {
+        Ok(graph) => {
+            let stages = graph.stages();
+            let mut order = graph.topological_order();
+            order.sort_by_key(|step| (stages[*step], *step));
+            let plan = order
+                .into_iter()
+                .map(|step| PlannedStep {
+                    step: step as i32 + 1,
+                    program_id: pipeline.steps[step].clone(),
+                    filename: programs[step].as_ref().map(|p| p.filename.clone()),
+                    inputs: graph
+                        .inputs(step)
+                        .iter()
+                        .map(|input| *input as i32 + 1)
+                        .collect(),
+                    stage: stages[step],
+                })
+                .collect();
+            (None, graph.type_mismatches(&programs), plan)
+        }
+        Err(e) => (Some(e.to_string()), Vec::new(), Vec::new()),
+    };
+
+    let parameter_error = parameters::check_pipeline(&pipeline)
+        .err()
+        .map(|e| e.to_string());
+    let policy_error = check_policies(&db, &pipeline).await?;
+    let schedule_error = pipeline.schedule.clone().and_then(|mut schedule| {
+        scheduler::prepare(&mut schedule, Utc::now())
+            .err()
+            .map(|e| e.to_string())
+    });
+
+    let valid = malformed_ids.is_empty()
+        && missing_programs.is_empty()
+        && edge_error.is_none()
+        && parameter_error.is_none()
+        && policy_error.is_none()
+        && schedule_error.is_none()
+        && !type_mismatches.iter().any(TypeMismatch::is_error);
+    Ok(HttpResponse::Ok().json(ValidationReport {
+        valid,
+        malformed_ids,
+        missing_programs,
+        duplicate_steps,
+        edge_error,
+        parameter_error,
+        policy_error,
+        schedule_error,
+        type_mismatches,
+        plan,
+    }))
+}
+
+/* Private helper functions */
+async fn check_policies(
+    db: &DatabaseConnection,
+    pipeline: &Pipeline,
+) -> Result<Option<String>, Error> {
+    if let Err(e) = policy::check(pipeline, &Limits::from_env()) {
+        return Ok(Some(e.to_string()));
+    }
+    for fallback in pipeline.policies.iter().filter_map(|p| p.fallback.as_ref()) {
+        let Ok(program_id) = ObjectId::parse_str(fallback.trim()) else {
+            continue;
+        };
+        if db
+            .find_program(&program_id)
+            .await
+            .map_err(database_error)?
+            .is_none()
+        {
+            return Ok(Some(format!("Fallback program not found: {}", program_id)));
+        }
+    }
+    Ok(None)
+}
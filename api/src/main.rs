@@ -5,6 +5,7 @@ use dotenv::dotenv;
 use log::info;
 use logger::init_logger;
 use shared::database::db_interface::{DatabaseConnection, DatabaseInterface};
+use shared::storage::blob_store::{BlobStore, Storage};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -14,7 +15,9 @@ async fn main() -> Result<()> {
     let db = DatabaseConnection::init().await?;
 
     info!("Database connection established.");
-    run_server(db).await?;
+    let storage = Storage::init().await?;
+
+    run_server(db, storage).await?;
 
     Ok(())
 }
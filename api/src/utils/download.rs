@@ -0,0 +1,37 @@
+use actix_web::http::header::{self, ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{Error, HttpResponse};
+use log::error;
+use shared::storage::blob_store::{BlobStore, Storage, SIGNED_URL_TTL};
+
+/// Answers a download of the blob at `key`. Remote backends redirect to a
+/// URL signed for this request only, which is why the links stored in the
+/// database point at the API rather than at the backend; files on the local
+/// disk are served by the API itself.
+pub async fn serve_blob(
+    storage: &Storage,
+    key: &str,
+    content_type: &str,
+    filename: String,
+) -> Result<HttpResponse, Error> {
+    if !matches!(storage, Storage::Local(_)) {
+        let url = storage.signed_url(key, SIGNED_URL_TTL).await.map_err(|e| {
+            error!("Error signing a URL for {}: {:?}", key, e);
+            actix_web::error::ErrorInternalServerError("Could not sign a download URL")
+        })?;
+        return Ok(HttpResponse::TemporaryRedirect()
+            .insert_header((header::LOCATION, url))
+            .finish());
+    }
+
+    let data = storage.get(key).await.map_err(|e| {
+        error!("Error reading {} from storage: {:?}", key, e);
+        actix_web::error::ErrorNotFound("File not found in storage")
+    })?;
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Inline,
+            parameters: vec![DispositionParam::Filename(filename)],
+        })
+        .body(data))
+}
@@ -6,6 +6,8 @@ use serde_json::json;
 pub enum UploadError {
     #[display(fmt = "Bad Request: {}", _0)]
     BadRequest(String),
+    #[display(fmt = "Payload Too Large: limit is {} bytes", _0)]
+    PayloadTooLarge(u64),
     #[display(fmt = "Internal Server Error")]
     InternalServerError,
 }
@@ -16,8 +18,24 @@ impl ResponseError for UploadError {
             UploadError::BadRequest(ref message) => {
                 HttpResponse::BadRequest().json(json!({ "message": message }))
             }
+            UploadError::PayloadTooLarge(limit) => HttpResponse::PayloadTooLarge().json(
+                json!({ "message": format!("File exceeds the maximum upload size of {} bytes", limit) }),
+            ),
             UploadError::InternalServerError => HttpResponse::InternalServerError()
                 .json(json!({ "message": "Internal Server Error" })),
         }
     }
 }
+
+/// Maps a missing sandbox, see `Sandbox::shared`, to a 503 response: no
+/// program can run on this server until its configuration changes.
+pub fn sandbox_error(e: anyhow::Error) -> actix_web::Error {
+    log::error!("Programs cannot run: {}", e);
+    actix_web::error::ErrorServiceUnavailable(format!("Programs cannot run: {}", e))
+}
+
+/// Maps a repository failure to a 500 response, logging the cause.
+pub fn database_error(e: anyhow::Error) -> actix_web::Error {
+    log::error!("Database query failed: {}", e);
+    actix_web::error::ErrorInternalServerError(format!("Database query failed: {}", e))
+}
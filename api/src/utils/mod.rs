@@ -1,3 +1,4 @@
+pub mod download;
 pub mod error;
 pub mod field_parser;
-pub mod firebase;
+pub mod spool;
@@ -0,0 +1,142 @@
+use std::env;
+
+use actix_web::Error;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use log::debug;
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+
+use super::error::UploadError;
+
+const DEFAULT_MAX_UPLOAD_SIZE: u64 = 100 * 1024 * 1024;
+
+/// An uploaded file written chunk by chunk to a temporary file, along with
+/// the size and SHA-256 computed while it was being received. The temporary
+/// file is removed when the value is dropped.
+///
+/// Multipart fields may arrive in any order, so the file has to be parked
+/// somewhere until the other fields (owner, extension...) are known; the disk
+/// keeps memory usage flat whatever the upload size.
+pub struct SpooledFile {
+    file: NamedTempFile,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl SpooledFile {
+    /// Streams the spooled bytes back, e.g. into `BlobStore::put_stream`.
+    pub async fn stream(
+        &self,
+    ) -> Result<impl Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static, Error> {
+        let file = tokio::fs::File::open(self.file.path()).await?;
+        Ok(ReaderStream::new(file))
+    }
+
+    /// Reads the spooled bytes back into memory.
+    pub async fn read(&self) -> Result<Vec<u8>, Error> {
+        Ok(tokio::fs::read(self.file.path()).await?)
+    }
+}
+
+/// Upper bound on a single uploaded file, configured through
+/// `MAX_UPLOAD_SIZE` (in bytes).
+pub fn max_upload_size() -> u64 {
+    env::var("MAX_UPLOAD_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_MAX_UPLOAD_SIZE)
+}
+
+/// Writes `stream` to a temporary file, aborting with a 413 as soon as more
+/// than `max_size` bytes have been received.
+pub async fn spool<S, E>(mut stream: S, max_size: u64) -> Result<SpooledFile, Error>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: Into<Error>,
+{
+    let temp_file = NamedTempFile::new()?;
+    let mut writer = tokio::fs::File::from_std(temp_file.reopen()?);
+    let mut hasher = Sha256::new();
+    let mut size: u64 = 0;
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(Into::into)?;
+        size += chunk.len() as u64;
+        if size > max_size {
+            return Err(UploadError::PayloadTooLarge(max_size).into());
+        }
+        hasher.update(&chunk);
+        writer.write_all(&chunk).await?;
+    }
+    writer.flush().await?;
+    debug!("Spooled {} bytes to {:?}", size, temp_file.path());
+
+    Ok(SpooledFile {
+        file: temp_file,
+        size,
+        sha256: hex::encode(hasher.finalize()),
+    })
+}
+
+/// Reads the `file` field of a multipart upload into a `SpooledFile`,
+/// returning it with the client provided filename and content type.
+pub async fn process_file_field(
+    field: actix_multipart::Field,
+) -> Result<(String, String, SpooledFile), Error> {
+    let content_disposition = field.content_disposition().clone();
+    let filename = match content_disposition.get_filename().and_then(base_filename) {
+        Some(name) => name.to_string(),
+        None => {
+            return Err(UploadError::BadRequest("No filename provided".into()).into());
+        }
+    };
+
+    let content_type = field
+        .content_type()
+        .map(|mime| mime.to_string())
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+
+    let spooled = spool(field, max_upload_size()).await?;
+
+    Ok((filename, content_type, spooled))
+}
+
+/// The last component of a client provided filename, which ends up in a
+/// storage path: `None` when nothing usable is left, such as for `..`.
+pub fn base_filename(name: &str) -> Option<&str> {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+    match base {
+        "" | "." | ".." => None,
+        base => Some(base),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(sizes: &[usize]) -> impl Stream<Item = Result<Bytes, Error>> + Unpin {
+        let chunks: Vec<Result<Bytes, Error>> = sizes
+            .iter()
+            .map(|&size| Ok(Bytes::from(vec![b'x'; size])))
+            .collect();
+        futures::stream::iter(chunks)
+    }
+
+    #[test]
+    fn test_spool_refuses_files_above_the_limit() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let spooled = spool(chunks(&[6, 4]), 10).await.unwrap();
+            assert_eq!(spooled.size, 10);
+            assert_eq!(spooled.read().await.unwrap(), vec![b'x'; 10]);
+
+            let error = spool(chunks(&[6, 4, 1]), 10).await.err().unwrap();
+            let error = error.as_error::<UploadError>().unwrap();
+            assert!(matches!(error, UploadError::PayloadTooLarge(10)));
+        });
+    }
+}
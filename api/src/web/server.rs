@@ -2,12 +2,24 @@ use actix_cors::Cors;
 use actix_web::http;
 use actix_web::web::{Data, JsonConfig};
 use actix_web::{middleware::Logger, web, App, HttpServer};
-use log::info;
-use shared::models::pipeline::{CreatePipeline, Pipeline, UpdatePipeline};
+use log::{error, info, warn};
+use shared::execution::{
+    cancel::CancelRegistry, events::EventHub, result_cache, sandbox::Sandbox, scheduler,
+};
+use shared::models::pipeline::{
+    CreatePipeline, ExecutionRecord, Pipeline, PipelineEdge, PipelineParameter, PipelineSchedule,
+    StepAttempt, StepPolicy, StepRecord, TypeMismatch, UpdatePipeline,
+};
+use shared::models::program_version::{ProgramVersion, Restoration};
 use shared::models::upload_file::UploadGroup;
+use shared::models::upload_session::{CreateUploadSession, UploadPart, UploadSession};
+use shared::storage::{blob_store::Storage, upload_expiry};
 use shared::{
     database::db_interface::DatabaseConnection,
-    models::{program::Program, upload_file::UploadFile},
+    models::{
+        program::{Program, UpdateProgramDto},
+        upload_file::UploadFile,
+    },
 };
 use std::env;
 use std::net::Ipv4Addr;
@@ -15,22 +27,36 @@ use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
 use crate::endpoints::content::{
-    routes::config as content_config, update_program_dto::UpdateProgramDto,
+    diff::{DiffHunk, DiffLine, VersionDiff},
+    routes::config as content_config,
+    run::ProgramRun,
+    version::RestoreVersionDto,
 };
 
+use crate::endpoints::execution::routes::config as execution_config;
 use crate::endpoints::group::routes::config as group_config;
+use crate::endpoints::pipeline::bundle::{
+    BundledPipeline, BundledProgram, ImportedPipeline, ImportedProgram, PipelineBundle,
+};
+use crate::endpoints::pipeline::execute::ExecutePipeline;
 use crate::endpoints::pipeline::routes::config as pipeline_config;
+use crate::endpoints::pipeline::schedule::UpcomingRun;
+use crate::endpoints::pipeline::validate::{
+    DuplicateStep, PlannedStep, StepProblem, ValidationReport,
+};
 
 const DEFAULT_PORT: u16 = 8080;
 
 /// This is an asynchronous function `run_server` that starts a server and binds it to a specified port.
 /// It accepts a generic parameter `T` that implements the `DatabaseInterface` trait.
-/// The function takes a `db` parameter of type `T` which represents the database interface.
+/// The function takes a `db` parameter of type `T` which represents the database interface,
+/// and a `storage` parameter holding the blob storage backend used for uploaded files.
 /// The function returns a `std::io::Result<()>` indicating whether the server started successfully or encountered an error.
 ///
 /// # Arguments
 ///
-/// * `db` - A generic parameter `T` that implements the `DatabaseInterface` trait. It represents the database interface.
+/// * `db` - The `DatabaseConnection`, MongoDB or the in-memory mock (`USE_MOCK_DB=1`). Handlers reach it through the repository traits.
+/// * `storage` - The `Storage` backend (Firebase, local filesystem or S3) selected at startup.
 ///
 /// # Panics
 ///
@@ -49,23 +75,17 @@ const DEFAULT_PORT: u16 = 8080;
 /// - The function uses the `get_server_port` function to determine the port to bind the server to.
 /// - It creates a server address tuple `(Ipv4Addr, u16)` with the unspecified IP address and the determined port.
 /// - The function generates a Swagger UI URL based on the server address.
-/// - It creates an `HttpServer` instance and configures it with the provided `db` parameter, logger middleware, Swagger UI, and content routes.
+/// - It creates an `HttpServer` instance and configures it with the provided `db` and `storage` parameters, logger middleware, Swagger UI, and content routes.
 /// - Finally, it binds the server to the server address and runs it asynchronously.
 ///
 /// # Returns
 ///
 /// The function returns a `std::io::Result<()>` indicating whether the server started successfully or encountered an error.
 ///
-pub async fn run_server(db: DatabaseConnection) -> std::io::Result<()> {
-    let web_db = match db {
-        DatabaseConnection::Real(real_db) => real_db.client,
-        _ => {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Unsupported database connection",
-            ))
-        }
-    };
+pub async fn run_server(db: DatabaseConnection, storage: Storage) -> std::io::Result<()> {
+    if let DatabaseConnection::Mock(_) = db {
+        warn!("Using the in-memory mock database, data will be lost on shutdown");
+    }
 
     let port = get_server_port();
     let server_address: (Ipv4Addr, u16) = (Ipv4Addr::UNSPECIFIED, port);
@@ -74,28 +94,58 @@ pub async fn run_server(db: DatabaseConnection) -> std::io::Result<()> {
         server_address.0, server_address.1
     );
 
+    // Settle how programs are isolated now rather than on the first run.
+    // Without a sandbox everything but running programs still works.
+    if let Err(e) = Sandbox::shared().await {
+        error!("Programs cannot run: {}", e);
+    }
+
+    let events = EventHub::new(db.clone());
+    let cancels = CancelRegistry::default();
+    tokio::spawn(scheduler::run_scheduler(
+        db.clone(),
+        storage.clone(),
+        events.clone(),
+        cancels.clone(),
+    ));
+    tokio::spawn(result_cache::run_result_sweep(db.clone(), storage.clone()));
+    tokio::spawn(upload_expiry::run_upload_expiry(
+        db.clone(),
+        storage.clone(),
+    ));
+
     info!("Starting server on port {}", port);
     info!("Swagger UI available at {}", swagger_url);
 
     HttpServer::new(move || {
         let cors = Cors::default()
             .allowed_origin_fn(|origin, _req_head| {
-                if let Some(origin_str) = origin.to_str().ok() {
+                if let Ok(origin_str) = origin.to_str() {
                     origin_str.ends_with(":3000") || origin_str.contains("code-valley.xyz")
                 } else {
                     false
                 }
             })
-            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
+            .allowed_methods(vec!["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE"])
             .allowed_headers(vec![
                 http::header::AUTHORIZATION,
                 http::header::ACCEPT,
                 http::header::CONTENT_TYPE,
+                http::header::HeaderName::from_static("upload-offset"),
+                http::header::HeaderName::from_static("last-event-id"),
+            ])
+            .expose_headers(vec![
+                http::header::LOCATION,
+                http::header::HeaderName::from_static("upload-offset"),
+                http::header::HeaderName::from_static("upload-length"),
             ])
             .supports_credentials()
             .max_age(3600);
         App::new()
-            .app_data(Data::new(web_db.clone()))
+            .app_data(Data::new(db.clone()))
+            .app_data(Data::new(storage.clone()))
+            .app_data(Data::new(events.clone()))
+            .app_data(Data::new(cancels.clone()))
             .app_data(JsonConfig::default())
             .wrap(cors)
             .wrap(Logger::default())
@@ -108,6 +158,7 @@ pub async fn run_server(db: DatabaseConnection) -> std::io::Result<()> {
                 web::scope("/v1")
                     .configure(content_config)
                     .configure(pipeline_config)
+                    .configure(execution_config)
                     .configure(group_config),
             )
     })
@@ -127,33 +178,91 @@ fn get_server_port() -> u16 {
 #[openapi(
     paths(
         crate::endpoints::content::upload::upload,
+        crate::endpoints::content::resumable::create_upload,
+        crate::endpoints::content::resumable::get_upload_offset,
+        crate::endpoints::content::resumable::append_chunk,
+        crate::endpoints::content::resumable::finalize_upload,
+        crate::endpoints::content::resumable::cancel_upload,
         crate::endpoints::content::metadata::get_contents_by_owner,
+        crate::endpoints::content::metadata::get_contents_by_hash,
         crate::endpoints::content::metadata::get_details,
         crate::endpoints::content::metadata::update_metadata,
         crate::endpoints::content::metadata::delete,
+        crate::endpoints::content::download::download_raw,
+        crate::endpoints::content::download::download_version_raw,
+        crate::endpoints::content::version::list_versions,
+        crate::endpoints::content::version::get_version,
+        crate::endpoints::content::version::restore_version,
+        crate::endpoints::content::diff::diff_versions,
+        crate::endpoints::content::run::run_program,
         crate::endpoints::pipeline::metadata::get_pipelines_by_owner,
         crate::endpoints::pipeline::metadata::get_pipeline,
         crate::endpoints::pipeline::metadata::list_pipelines,
         crate::endpoints::pipeline::metadata::create_pipeline,
         crate::endpoints::pipeline::metadata::delete_pipeline,
         crate::endpoints::pipeline::metadata::update_pipeline,
+        crate::endpoints::pipeline::validate::validate_pipeline,
+        crate::endpoints::pipeline::bundle::export_pipeline,
+        crate::endpoints::pipeline::bundle::import_pipeline,
+        crate::endpoints::pipeline::schedule::list_upcoming_runs,
+        crate::endpoints::pipeline::schedule::pause_schedule,
+        crate::endpoints::pipeline::schedule::resume_schedule,
+        crate::endpoints::pipeline::execute::execute_pipeline,
+        crate::endpoints::execution::history::list_pipeline_executions,
+        crate::endpoints::execution::history::get_execution,
+        crate::endpoints::execution::history::get_step_output,
+        crate::endpoints::execution::history::download_step_artifact,
+        crate::endpoints::execution::events::stream_events,
+        crate::endpoints::execution::cancel::cancel_execution,
         crate::endpoints::group::upload::upload,
+        crate::endpoints::group::download::download_avatar,
+        crate::endpoints::group::download::download_message_file,
     ),
     components(
         schemas(
             UpdateProgramDto,
+            RestoreVersionDto,
             UploadFile,
             UploadGroup,
+            UploadSession,
+            UploadPart,
+            CreateUploadSession,
             Program,
+            ProgramVersion,
+            Restoration,
+            VersionDiff,
+            DiffHunk,
+            DiffLine,
+            ProgramRun,
             Pipeline,
+            PipelineEdge,
+            PipelineSchedule,
+            PipelineParameter,
+            StepPolicy,
+            ExecutePipeline,
+            UpcomingRun,
+            TypeMismatch,
+            ValidationReport,
+            StepProblem,
+            DuplicateStep,
+            PlannedStep,
+            PipelineBundle,
+            BundledPipeline,
+            BundledProgram,
+            ImportedPipeline,
+            ImportedProgram,
             CreatePipeline,
-            UpdatePipeline
+            UpdatePipeline,
+            ExecutionRecord,
+            StepRecord,
+            StepAttempt
         ),
     ),
 
     tags(
             (name = "content", description = "Content related operations"),
             (name = "pipeline", description = "Pipeline related operations"),
+            (name = "execution", description = "Pipeline execution history"),
             (name = "group", description = "Group related operations"),
     ),
     servers(
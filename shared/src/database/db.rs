@@ -2,6 +2,7 @@ use anyhow::{Error, Result};
 use bson::doc;
 use log::info;
 use mongodb::bson::Document;
+use mongodb::error::{ErrorKind, WriteFailure};
 use mongodb::options::SelectionCriteria;
 use mongodb::{options::ClientOptions, Client, Database};
 use std::env;
@@ -25,6 +26,8 @@ impl DatabaseInterface for Db {
         let db_instance = Db { client: database };
 
         db_instance.run_command(doc! {"ping": 0}, None).await?;
+        db_instance.create_indexes().await?;
+        db_instance.migrate_encoded_file_paths().await?;
 
         Ok(db_instance)
     }
@@ -38,3 +41,28 @@ impl DatabaseInterface for Db {
         Ok(result)
     }
 }
+
+impl Db {
+    /// Indexes the repositories rely on for correctness; creating an index
+    /// that already exists does nothing.
+    async fn create_indexes(&self) -> Result<()> {
+        self.create_version_indexes().await?;
+        self.create_step_result_indexes().await?;
+        self.create_execution_event_indexes().await?;
+        Ok(())
+    }
+}
+
+const DUPLICATE_KEY: i32 = 11000;
+
+/// Whether `error` is a unique index refusing a second document with the
+/// same key.
+pub(super) fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    match *error.kind {
+        ErrorKind::Write(WriteFailure::WriteError(ref write_error)) => {
+            write_error.code == DUPLICATE_KEY
+        }
+        ErrorKind::Command(ref command_error) => command_error.code == DUPLICATE_KEY,
+        _ => false,
+    }
+}
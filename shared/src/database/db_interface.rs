@@ -24,7 +24,8 @@ pub enum DatabaseConnection {
 
 impl DatabaseInterface for DatabaseConnection {
     async fn init() -> Result<Self> {
-        if env::var("USE_MOCK_DB").unwrap_or_else(|_| "0".to_string()) == "1" {
+        let use_mock_db = env::var("USE_MOCK_DB").unwrap_or_default();
+        if matches!(use_mock_db.as_str(), "1" | "true") {
             Ok(DatabaseConnection::Mock(MockDb::init().await?))
         } else {
             Ok(DatabaseConnection::Real(Db::init().await?))
@@ -0,0 +1,167 @@
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Error;
+use bson::{doc, oid::ObjectId};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::options::{FindOptions, IndexOptions};
+use mongodb::{Collection, IndexModel};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{db::Db, db_interface::DatabaseConnection, mock_db::MockDb};
+use crate::serializers::bson_datetime_serializer;
+
+const EXECUTION_EVENTS: &str = "execution_events";
+/// How long the events of an execution can be replayed. The execution
+/// record keeps its outcome after that.
+const EVENT_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// An event of an execution as stored, so that any server can replay it to
+/// a client that reconnects, see `events::EventHub::follow`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredEvent {
+    #[serde(rename = "execution_id")]
+    pub execution_id: ObjectId,
+
+    /// Sequence number within the execution, from 1.
+    #[serde(rename = "id")]
+    pub id: i64,
+
+    #[serde(rename = "event")]
+    pub event: String,
+
+    #[serde(rename = "data")]
+    pub data: Value,
+
+    #[serde(rename = "created_at", with = "bson_datetime_serializer")]
+    pub created_at: DateTime<Utc>,
+}
+
+pub trait ExecutionEventRepository {
+    /// Stores `events`, in order.
+    fn insert_execution_events(
+        &self,
+        events: &[StoredEvent],
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Up to `limit` events of the execution after the one numbered
+    /// `after`, in order.
+    fn find_execution_events(
+        &self,
+        execution_id: &ObjectId,
+        after: u64,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<StoredEvent>, Error>> + Send;
+}
+
+impl Db {
+    fn execution_events(&self) -> Collection<StoredEvent> {
+        self.client.collection(EXECUTION_EVENTS)
+    }
+
+    /// Events are read by execution and number, and dropped by MongoDB
+    /// after `EVENT_RETENTION`.
+    pub(super) async fn create_execution_event_indexes(&self) -> Result<(), Error> {
+        let indexes = [
+            IndexModel::builder()
+                .keys(doc! {"execution_id": 1, "id": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            IndexModel::builder()
+                .keys(doc! {"created_at": 1})
+                .options(
+                    IndexOptions::builder()
+                        .expire_after(EVENT_RETENTION)
+                        .build(),
+                )
+                .build(),
+        ];
+        self.execution_events()
+            .create_indexes(indexes, None)
+            .await?;
+        Ok(())
+    }
+}
+
+impl ExecutionEventRepository for Db {
+    async fn insert_execution_events(&self, events: &[StoredEvent]) -> Result<(), Error> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.execution_events().insert_many(events, None).await?;
+        Ok(())
+    }
+
+    async fn find_execution_events(
+        &self,
+        execution_id: &ObjectId,
+        after: u64,
+        limit: usize,
+    ) -> Result<Vec<StoredEvent>, Error> {
+        let options = FindOptions::builder()
+            .sort(doc! {"id": 1})
+            .limit(limit as i64)
+            .build();
+        let after = after.min(i64::MAX as u64) as i64;
+        let cursor = self
+            .execution_events()
+            .find(
+                doc! {"execution_id": execution_id, "id": {"$gt": after}},
+                options,
+            )
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+}
+
+impl ExecutionEventRepository for MockDb {
+    async fn insert_execution_events(&self, events: &[StoredEvent]) -> Result<(), Error> {
+        self.write()?.execution_events.extend_from_slice(events);
+        Ok(())
+    }
+
+    async fn find_execution_events(
+        &self,
+        execution_id: &ObjectId,
+        after: u64,
+        limit: usize,
+    ) -> Result<Vec<StoredEvent>, Error> {
+        let store = self.read()?;
+        let mut events: Vec<StoredEvent> = store
+            .execution_events
+            .iter()
+            .filter(|event| &event.execution_id == execution_id && event.id as u64 > after)
+            .cloned()
+            .collect();
+        events.sort_by_key(|event| event.id);
+        events.truncate(limit);
+        Ok(events)
+    }
+}
+
+impl ExecutionEventRepository for DatabaseConnection {
+    async fn insert_execution_events(&self, events: &[StoredEvent]) -> Result<(), Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.insert_execution_events(events).await,
+            DatabaseConnection::Mock(mock) => mock.insert_execution_events(events).await,
+        }
+    }
+
+    async fn find_execution_events(
+        &self,
+        execution_id: &ObjectId,
+        after: u64,
+        limit: usize,
+    ) -> Result<Vec<StoredEvent>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => {
+                db.find_execution_events(execution_id, after, limit).await
+            }
+            DatabaseConnection::Mock(mock) => {
+                mock.find_execution_events(execution_id, after, limit).await
+            }
+        }
+    }
+}
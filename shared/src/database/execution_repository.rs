@@ -0,0 +1,387 @@
+use std::future::Future;
+
+use anyhow::Error;
+use bson::{doc, oid::ObjectId, DateTime as BsonDateTime, Document};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use mongodb::Collection;
+
+use super::{db::Db, db_interface::DatabaseConnection, mock_db::MockDb};
+use crate::models::pipeline::ExecutionRecord;
+
+const EXECUTIONS: &str = "executions";
+
+/// Restricts a list of executions; unset fields match everything.
+#[derive(Debug, Default)]
+pub struct ExecutionFilter {
+    pub status: Option<String>,
+    /// Executions started at or after this time.
+    pub from: Option<DateTime<Utc>>,
+    /// Executions started at or before this time.
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl ExecutionFilter {
+    fn matches(&self, execution: &ExecutionRecord) -> bool {
+        self.status
+            .as_ref()
+            .is_none_or(|status| &execution.status == status)
+            && self
+                .from
+                .is_none_or(|from| execution.execution_time >= from)
+            && self.to.is_none_or(|to| execution.execution_time <= to)
+    }
+
+    fn to_document(&self, pipeline_id: &ObjectId) -> Document {
+        let mut filter = doc! {"pipeline_id": pipeline_id};
+        if let Some(status) = &self.status {
+            filter.insert("status", status);
+        }
+        let mut execution_time = Document::new();
+        if let Some(from) = self.from {
+            execution_time.insert("$gte", BsonDateTime::from_chrono(from));
+        }
+        if let Some(to) = self.to {
+            execution_time.insert("$lte", BsonDateTime::from_chrono(to));
+        }
+        if !execution_time.is_empty() {
+            filter.insert("execution_time", execution_time);
+        }
+        filter
+    }
+}
+
+pub trait ExecutionRepository {
+    fn find_execution(
+        &self,
+        id: &ObjectId,
+    ) -> impl Future<Output = Result<Option<ExecutionRecord>, Error>> + Send;
+
+    /// Executions of a pipeline matching `filter`, most recent first.
+    fn find_executions_by_pipeline(
+        &self,
+        pipeline_id: &ObjectId,
+        filter: &ExecutionFilter,
+    ) -> impl Future<Output = Result<Vec<ExecutionRecord>, Error>> + Send;
+
+    fn insert_execution(
+        &self,
+        execution: &ExecutionRecord,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Overwrites the stored execution; `false` if it does not exist.
+    /// Stores `execution`, apart from its `heartbeat` and
+    /// `cancel_requested`, which `beat_execution` and
+    /// `request_execution_cancel` keep.
+    fn update_execution(
+        &self,
+        execution: &ExecutionRecord,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Records that the owner of the execution is still running it, and
+    /// returns whether it was asked to cancel it meanwhile.
+    fn beat_execution(&self, id: &ObjectId) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Asks the owner of a running execution to cancel it. Returns `false`
+    /// when the execution is not running.
+    fn request_execution_cancel(
+        &self,
+        id: &ObjectId,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+}
+
+impl Db {
+    fn executions(&self) -> Collection<ExecutionRecord> {
+        self.client.collection(EXECUTIONS)
+    }
+}
+
+impl ExecutionRepository for Db {
+    async fn find_execution(&self, id: &ObjectId) -> Result<Option<ExecutionRecord>, Error> {
+        Ok(self.executions().find_one(doc! {"_id": id}, None).await?)
+    }
+
+    async fn find_executions_by_pipeline(
+        &self,
+        pipeline_id: &ObjectId,
+        filter: &ExecutionFilter,
+    ) -> Result<Vec<ExecutionRecord>, Error> {
+        let options = FindOptions::builder()
+            .sort(doc! {"execution_time": -1})
+            .build();
+        let cursor = self
+            .executions()
+            .find(filter.to_document(pipeline_id), options)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn insert_execution(&self, execution: &ExecutionRecord) -> Result<(), Error> {
+        self.executions().insert_one(execution, None).await?;
+        Ok(())
+    }
+
+    async fn update_execution(&self, execution: &ExecutionRecord) -> Result<bool, Error> {
+        let mut fields = bson::to_document(execution)?;
+        for owned_elsewhere in ["_id", "heartbeat", "cancel_requested"] {
+            fields.remove(owned_elsewhere);
+        }
+        let result = self
+            .executions()
+            .update_one(doc! {"_id": execution.id}, doc! {"$set": fields}, None)
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn beat_execution(&self, id: &ObjectId) -> Result<bool, Error> {
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let execution = self
+            .executions()
+            .find_one_and_update(
+                doc! {"_id": id},
+                doc! {"$set": {"heartbeat": BsonDateTime::from_chrono(Utc::now())}},
+                options,
+            )
+            .await?;
+        Ok(execution.is_some_and(|execution| execution.cancel_requested))
+    }
+
+    async fn request_execution_cancel(&self, id: &ObjectId) -> Result<bool, Error> {
+        let result = self
+            .executions()
+            .update_one(
+                doc! {"_id": id, "status": "running"},
+                doc! {"$set": {"cancel_requested": true}},
+                None,
+            )
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+}
+
+impl ExecutionRepository for MockDb {
+    async fn find_execution(&self, id: &ObjectId) -> Result<Option<ExecutionRecord>, Error> {
+        let store = self.read()?;
+        Ok(store.executions.iter().find(|e| &e.id == id).cloned())
+    }
+
+    async fn find_executions_by_pipeline(
+        &self,
+        pipeline_id: &ObjectId,
+        filter: &ExecutionFilter,
+    ) -> Result<Vec<ExecutionRecord>, Error> {
+        let store = self.read()?;
+        let mut executions: Vec<ExecutionRecord> = store
+            .executions
+            .iter()
+            .filter(|e| &e.pipeline_id == pipeline_id && filter.matches(e))
+            .cloned()
+            .collect();
+        executions.sort_by_key(|e| std::cmp::Reverse(e.execution_time));
+        Ok(executions)
+    }
+
+    async fn insert_execution(&self, execution: &ExecutionRecord) -> Result<(), Error> {
+        let mut store = self.write()?;
+        if store.executions.iter().any(|e| e.id == execution.id) {
+            return Err(Error::msg(format!("Duplicate key: {}", execution.id)));
+        }
+        store.executions.push(execution.clone());
+        Ok(())
+    }
+
+    async fn update_execution(&self, execution: &ExecutionRecord) -> Result<bool, Error> {
+        let mut store = self.write()?;
+        match store.executions.iter_mut().find(|e| e.id == execution.id) {
+            Some(stored) => {
+                *stored = ExecutionRecord {
+                    heartbeat: stored.heartbeat,
+                    cancel_requested: stored.cancel_requested,
+                    ..execution.clone()
+                };
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn beat_execution(&self, id: &ObjectId) -> Result<bool, Error> {
+        let mut store = self.write()?;
+        match store.executions.iter_mut().find(|e| &e.id == id) {
+            Some(stored) => {
+                stored.heartbeat = Some(Utc::now());
+                Ok(stored.cancel_requested)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn request_execution_cancel(&self, id: &ObjectId) -> Result<bool, Error> {
+        let mut store = self.write()?;
+        match store
+            .executions
+            .iter_mut()
+            .find(|e| &e.id == id && e.status == "running")
+        {
+            Some(stored) => {
+                stored.cancel_requested = true;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+impl ExecutionRepository for DatabaseConnection {
+    async fn find_execution(&self, id: &ObjectId) -> Result<Option<ExecutionRecord>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.find_execution(id).await,
+            DatabaseConnection::Mock(mock) => mock.find_execution(id).await,
+        }
+    }
+
+    async fn find_executions_by_pipeline(
+        &self,
+        pipeline_id: &ObjectId,
+        filter: &ExecutionFilter,
+    ) -> Result<Vec<ExecutionRecord>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => {
+                db.find_executions_by_pipeline(pipeline_id, filter).await
+            }
+            DatabaseConnection::Mock(mock) => {
+                mock.find_executions_by_pipeline(pipeline_id, filter).await
+            }
+        }
+    }
+
+    async fn insert_execution(&self, execution: &ExecutionRecord) -> Result<(), Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.insert_execution(execution).await,
+            DatabaseConnection::Mock(mock) => mock.insert_execution(execution).await,
+        }
+    }
+
+    async fn update_execution(&self, execution: &ExecutionRecord) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.update_execution(execution).await,
+            DatabaseConnection::Mock(mock) => mock.update_execution(execution).await,
+        }
+    }
+
+    async fn beat_execution(&self, id: &ObjectId) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.beat_execution(id).await,
+            DatabaseConnection::Mock(mock) => mock.beat_execution(id).await,
+        }
+    }
+
+    async fn request_execution_cancel(&self, id: &ObjectId) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.request_execution_cancel(id).await,
+            DatabaseConnection::Mock(mock) => mock.request_execution_cancel(id).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use tokio::runtime::Runtime;
+
+    fn execution(
+        pipeline_id: ObjectId,
+        status: &str,
+        execution_time: DateTime<Utc>,
+    ) -> ExecutionRecord {
+        ExecutionRecord {
+            id: ObjectId::new(),
+            pipeline_id,
+            execution_time,
+            status: status.to_string(),
+            output: String::new(),
+            output_size: 0,
+            finished_time: None,
+            steps: Vec::new(),
+            parameters: Default::default(),
+            input_path: None,
+            input_filename: None,
+            cache_hits: 0,
+            instance: String::new(),
+            heartbeat: None,
+            cancel_requested: false,
+        }
+    }
+
+    #[test]
+    fn test_mock_find_executions_by_pipeline_filters() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let db = MockDb::default();
+            let pipeline_id = ObjectId::new();
+            let now = Utc::now();
+            let old = execution(pipeline_id, "failed", now - Duration::days(2));
+            let recent = execution(pipeline_id, "success", now);
+            let other = execution(ObjectId::new(), "success", now);
+            for record in [&old, &recent, &other] {
+                db.insert_execution(record).await.unwrap();
+            }
+
+            let all = db
+                .find_executions_by_pipeline(&pipeline_id, &ExecutionFilter::default())
+                .await
+                .unwrap();
+            assert_eq!(
+                all.iter().map(|e| e.id).collect::<Vec<_>>(),
+                vec![recent.id, old.id]
+            );
+
+            let filter = ExecutionFilter {
+                status: Some("failed".to_string()),
+                ..Default::default()
+            };
+            let failed = db
+                .find_executions_by_pipeline(&pipeline_id, &filter)
+                .await
+                .unwrap();
+            assert_eq!(failed.len(), 1);
+            assert_eq!(failed[0].id, old.id);
+
+            let filter = ExecutionFilter {
+                from: Some(now - Duration::days(1)),
+                to: Some(now),
+                ..Default::default()
+            };
+            let today = db
+                .find_executions_by_pipeline(&pipeline_id, &filter)
+                .await
+                .unwrap();
+            assert_eq!(today.len(), 1);
+            assert_eq!(today[0].id, recent.id);
+        });
+    }
+
+    #[test]
+    fn test_mock_cancel_request_survives_updates() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let db = MockDb::default();
+            let mut running = execution(ObjectId::new(), "running", Utc::now());
+            db.insert_execution(&running).await.unwrap();
+
+            assert!(!db.beat_execution(&running.id).await.unwrap());
+            assert!(db.request_execution_cancel(&running.id).await.unwrap());
+            running.cache_hits = 1;
+            db.update_execution(&running).await.unwrap();
+            assert!(db.beat_execution(&running.id).await.unwrap());
+
+            running.status = "cancelled".to_string();
+            db.update_execution(&running).await.unwrap();
+            assert!(!db.request_execution_cancel(&running.id).await.unwrap());
+        });
+    }
+}
@@ -0,0 +1,125 @@
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Error;
+use bson::{doc, DateTime as BsonDateTime};
+use chrono::{DateTime, Utc};
+use mongodb::options::UpdateOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+
+use super::db::{is_duplicate_key, Db};
+use super::{db_interface::DatabaseConnection, mock_db::MockDb};
+use crate::serializers::bson_datetime_serializer;
+
+const LEASES: &str = "leases";
+
+/// Exclusive right of one server to do some work until `expires_at`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lease {
+    #[serde(rename = "_id")]
+    pub name: String,
+
+    #[serde(rename = "holder")]
+    pub holder: String,
+
+    #[serde(rename = "expires_at", with = "bson_datetime_serializer")]
+    pub expires_at: DateTime<Utc>,
+}
+
+pub trait LeaseRepository {
+    /// Takes the lease `name` for `holder`, or extends it if `holder`
+    /// already has it, until `ttl` from now. `false` while another holder's
+    /// lease has not expired.
+    fn acquire_lease(
+        &self,
+        name: &str,
+        holder: &str,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+}
+
+impl Db {
+    fn leases(&self) -> Collection<Lease> {
+        self.client.collection(LEASES)
+    }
+}
+
+impl LeaseRepository for Db {
+    async fn acquire_lease(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool, Error> {
+        let now = Utc::now();
+        let expires_at = now + chrono::Duration::from_std(ttl)?;
+        let filter = doc! {
+            "_id": name,
+            "$or": [
+                {"holder": holder},
+                {"expires_at": {"$lt": BsonDateTime::from_chrono(now)}},
+            ],
+        };
+        let update = doc! {
+            "$set": {"holder": holder, "expires_at": BsonDateTime::from_chrono(expires_at)}
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+        match self.leases().update_one(filter, update, options).await {
+            Ok(_) => Ok(true),
+            // Nothing matched, so the upsert tried to create a lease that
+            // someone else holds.
+            Err(e) if is_duplicate_key(&e) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl LeaseRepository for MockDb {
+    async fn acquire_lease(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool, Error> {
+        let now = Utc::now();
+        let expires_at = now + chrono::Duration::from_std(ttl)?;
+        let mut store = self.write()?;
+        match store.leases.iter_mut().find(|lease| lease.name == name) {
+            Some(lease) if lease.holder != holder && lease.expires_at >= now => Ok(false),
+            Some(lease) => {
+                lease.holder = holder.to_string();
+                lease.expires_at = expires_at;
+                Ok(true)
+            }
+            None => {
+                store.leases.push(Lease {
+                    name: name.to_string(),
+                    holder: holder.to_string(),
+                    expires_at,
+                });
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl LeaseRepository for DatabaseConnection {
+    async fn acquire_lease(&self, name: &str, holder: &str, ttl: Duration) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.acquire_lease(name, holder, ttl).await,
+            DatabaseConnection::Mock(mock) => mock.acquire_lease(name, holder, ttl).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_mock_lease_is_exclusive_until_it_expires() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let db = MockDb::default();
+            let ttl = Duration::from_secs(60);
+            assert!(db.acquire_lease("scheduler", "a", ttl).await.unwrap());
+            assert!(!db.acquire_lease("scheduler", "b", ttl).await.unwrap());
+            assert!(db.acquire_lease("scheduler", "a", ttl).await.unwrap());
+
+            db.write().unwrap().leases[0].expires_at = Utc::now() - chrono::Duration::seconds(1);
+            assert!(db.acquire_lease("scheduler", "b", ttl).await.unwrap());
+        });
+    }
+}
@@ -1,12 +1,84 @@
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
 use bson::{doc, Document};
 use log::{debug, info};
 use mongodb::options::SelectionCriteria;
 
 use super::db_interface::DatabaseInterface;
+use super::execution_event_repository::StoredEvent;
+use super::lease_repository::Lease;
+use super::step_result_repository::StepResult;
+use crate::models::{
+    pipeline::{ExecutionRecord, Pipeline},
+    program::Program,
+    program_version::ProgramVersion,
+    upload_session::UploadSession,
+};
 use anyhow::Error;
 
-#[derive(Clone)]
-pub struct MockDb {}
+/// In-memory stand-in for MongoDB. Clones share the same store, like clones
+/// of a `mongodb::Database` share the same server.
+#[derive(Clone, Default)]
+pub struct MockDb {
+    pub(crate) store: Arc<RwLock<MockStore>>,
+}
+
+/// Collections of the mock database, kept in insertion order like MongoDB's
+/// natural order.
+#[derive(Default)]
+pub(crate) struct MockStore {
+    pub(crate) programs: Vec<Program>,
+    pub(crate) program_versions: Vec<ProgramVersion>,
+    pub(crate) pipelines: Vec<Pipeline>,
+    pub(crate) upload_sessions: Vec<UploadSession>,
+    pub(crate) executions: Vec<ExecutionRecord>,
+    pub(crate) leases: Vec<Lease>,
+    pub(crate) step_results: Vec<StepResult>,
+    pub(crate) execution_events: Vec<StoredEvent>,
+}
+
+impl MockStore {
+    fn collection_names() -> [&'static str; 8] {
+        [
+            "programs",
+            "program_versions",
+            "pipelines",
+            "upload_sessions",
+            "executions",
+            "leases",
+            "step_results",
+            "execution_events",
+        ]
+    }
+
+    fn count(&self, collection: &str) -> Option<usize> {
+        match collection {
+            "programs" => Some(self.programs.len()),
+            "program_versions" => Some(self.program_versions.len()),
+            "pipelines" => Some(self.pipelines.len()),
+            "upload_sessions" => Some(self.upload_sessions.len()),
+            "executions" => Some(self.executions.len()),
+            "leases" => Some(self.leases.len()),
+            "step_results" => Some(self.step_results.len()),
+            "execution_events" => Some(self.execution_events.len()),
+            _ => None,
+        }
+    }
+}
+
+impl MockDb {
+    pub(crate) fn read(&self) -> Result<RwLockReadGuard<'_, MockStore>, Error> {
+        self.store
+            .read()
+            .map_err(|_| Error::msg("Mock database lock poisoned"))
+    }
+
+    pub(crate) fn write(&self) -> Result<RwLockWriteGuard<'_, MockStore>, Error> {
+        self.store
+            .write()
+            .map_err(|_| Error::msg("Mock database lock poisoned"))
+    }
+}
 
 impl DatabaseInterface for MockDb {
     async fn run_command(
@@ -17,6 +89,18 @@ impl DatabaseInterface for MockDb {
         if cmd.get("ping").is_some() {
             debug!("ping reply: {:?}", doc! {"ok": 1});
             Ok(doc! {"ok": 1})
+        } else if let Ok(collection) = cmd.get_str("count") {
+            let count = self.read()?.count(collection).unwrap_or(0);
+            Ok(doc! {"n": count as i64, "ok": 1})
+        } else if cmd.get("listCollections").is_some() {
+            let collections: Vec<Document> = MockStore::collection_names()
+                .iter()
+                .map(|name| doc! {"name": *name, "type": "collection"})
+                .collect();
+            Ok(doc! {
+                "cursor": {"id": 0_i64, "ns": "mock.$cmd.listCollections", "firstBatch": collections},
+                "ok": 1,
+            })
         } else {
             Err(Error::msg("Invalid command"))
         }
@@ -26,7 +110,7 @@ impl DatabaseInterface for MockDb {
     where
         Self: Sized,
     {
-        let db_instance = MockDb {};
+        let db_instance = MockDb::default();
         db_instance.run_command(doc! {"ping": 1}, None).await?;
         info!("Database mock connection established.");
         Ok(db_instance)
@@ -43,7 +127,7 @@ mod tests {
     fn test_run_command_with_valid_ping() {
         let rt = Runtime::new().unwrap();
         rt.block_on(async {
-            let db = MockDb {};
+            let db = MockDb::default();
             let command = doc! {"ping": "1"};
             let result = db.run_command(command, None).await;
             assert_eq!(result.unwrap(), doc! {"ok": 1});
@@ -54,13 +138,23 @@ mod tests {
     fn test_run_command_with_invalid_command() {
         let rt = Runtime::new().unwrap();
         rt.block_on(async {
-            let db = MockDb {};
+            let db = MockDb::default();
             let command = doc! {"select * from users": "0"};
             let result = db.run_command(command, None).await;
             assert!(result.is_err());
         });
     }
 
+    #[test]
+    fn test_run_command_count() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let db = MockDb::default();
+            let result = db.run_command(doc! {"count": "programs"}, None).await;
+            assert_eq!(result.unwrap(), doc! {"n": 0_i64, "ok": 1});
+        });
+    }
+
     #[test]
     fn test_init_method_success() {
         let rt = Runtime::new().unwrap();
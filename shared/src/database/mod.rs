@@ -1,4 +1,11 @@
 pub mod api_response;
 pub mod db;
 pub mod db_interface;
+pub mod execution_event_repository;
+pub mod execution_repository;
+pub mod lease_repository;
 pub mod mock_db;
+pub mod pipeline_repository;
+pub mod program_repository;
+pub mod step_result_repository;
+pub mod upload_session_repository;
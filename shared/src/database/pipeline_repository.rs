@@ -0,0 +1,388 @@
+use std::future::Future;
+
+use anyhow::Error;
+use bson::{doc, oid::ObjectId, Bson, DateTime as BsonDateTime};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::Collection;
+
+use super::{db::Db, db_interface::DatabaseConnection, mock_db::MockDb};
+use crate::models::pipeline::{Pipeline, PipelineSchedule, UpdatePipeline};
+
+const PIPELINES: &str = "pipelines";
+
+pub trait PipelineRepository {
+    fn find_pipeline(
+        &self,
+        id: &ObjectId,
+    ) -> impl Future<Output = Result<Option<Pipeline>, Error>> + Send;
+
+    fn find_pipelines_by_owner(
+        &self,
+        owner_id: i32,
+    ) -> impl Future<Output = Result<Vec<Pipeline>, Error>> + Send;
+
+    fn list_pipelines(&self) -> impl Future<Output = Result<Vec<Pipeline>, Error>> + Send;
+
+    fn insert_pipeline(
+        &self,
+        pipeline: &Pipeline,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Applies `update` and returns the updated pipeline, `None` if it does
+    /// not exist.
+    fn update_pipeline(
+        &self,
+        id: &ObjectId,
+        update: &UpdatePipeline,
+    ) -> impl Future<Output = Result<Option<Pipeline>, Error>> + Send;
+
+    fn delete_pipeline(&self, id: &ObjectId) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Pipelines whose schedule is enabled.
+    fn find_scheduled_pipelines(&self)
+        -> impl Future<Output = Result<Vec<Pipeline>, Error>> + Send;
+
+    /// Replaces the schedule and returns the updated pipeline, `None` if it
+    /// does not exist.
+    fn set_schedule(
+        &self,
+        id: &ObjectId,
+        schedule: &PipelineSchedule,
+    ) -> impl Future<Output = Result<Option<Pipeline>, Error>> + Send;
+
+    /// Moves `schedule.next_run` from `due` to `next` if it is still `due`
+    /// and the schedule enabled; `false` if someone else got there first.
+    fn claim_scheduled_run(
+        &self,
+        id: &ObjectId,
+        due: DateTime<Utc>,
+        next: Option<DateTime<Utc>>,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+}
+
+impl Db {
+    fn pipelines(&self) -> Collection<Pipeline> {
+        self.client.collection(PIPELINES)
+    }
+}
+
+impl PipelineRepository for Db {
+    async fn find_pipeline(&self, id: &ObjectId) -> Result<Option<Pipeline>, Error> {
+        Ok(self.pipelines().find_one(doc! {"_id": id}, None).await?)
+    }
+
+    async fn find_pipelines_by_owner(&self, owner_id: i32) -> Result<Vec<Pipeline>, Error> {
+        let cursor = self
+            .pipelines()
+            .find(doc! {"owner_id": owner_id}, None)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn list_pipelines(&self) -> Result<Vec<Pipeline>, Error> {
+        let cursor = self.pipelines().find(None, None).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn insert_pipeline(&self, pipeline: &Pipeline) -> Result<(), Error> {
+        self.pipelines().insert_one(pipeline, None).await?;
+        Ok(())
+    }
+
+    async fn update_pipeline(
+        &self,
+        id: &ObjectId,
+        update: &UpdatePipeline,
+    ) -> Result<Option<Pipeline>, Error> {
+        let update_command = doc! {
+            "$set": update.build_update_document(),
+            "$currentDate": {"update_time": true}
+        };
+        let result = self
+            .pipelines()
+            .update_one(doc! {"_id": id}, update_command, None)
+            .await?;
+        if result.matched_count == 0 {
+            return Ok(None);
+        }
+        self.find_pipeline(id).await
+    }
+
+    async fn delete_pipeline(&self, id: &ObjectId) -> Result<bool, Error> {
+        let result = self.pipelines().delete_one(doc! {"_id": id}, None).await?;
+        Ok(result.deleted_count == 1)
+    }
+
+    async fn find_scheduled_pipelines(&self) -> Result<Vec<Pipeline>, Error> {
+        let cursor = self
+            .pipelines()
+            .find(doc! {"schedule.enabled": true}, None)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn set_schedule(
+        &self,
+        id: &ObjectId,
+        schedule: &PipelineSchedule,
+    ) -> Result<Option<Pipeline>, Error> {
+        let update_command = doc! {
+            "$set": {"schedule": bson::to_bson(schedule)?},
+            "$currentDate": {"update_time": true}
+        };
+        let result = self
+            .pipelines()
+            .update_one(doc! {"_id": id}, update_command, None)
+            .await?;
+        if result.matched_count == 0 {
+            return Ok(None);
+        }
+        self.find_pipeline(id).await
+    }
+
+    async fn claim_scheduled_run(
+        &self,
+        id: &ObjectId,
+        due: DateTime<Utc>,
+        next: Option<DateTime<Utc>>,
+    ) -> Result<bool, Error> {
+        let filter = doc! {
+            "_id": id,
+            "schedule.enabled": true,
+            "schedule.next_run": BsonDateTime::from_chrono(due),
+        };
+        let next = next.map_or(Bson::Null, |next| {
+            Bson::DateTime(BsonDateTime::from_chrono(next))
+        });
+        let result = self
+            .pipelines()
+            .update_one(filter, doc! {"$set": {"schedule.next_run": next}}, None)
+            .await?;
+        Ok(result.modified_count == 1)
+    }
+}
+
+impl PipelineRepository for MockDb {
+    async fn find_pipeline(&self, id: &ObjectId) -> Result<Option<Pipeline>, Error> {
+        let store = self.read()?;
+        Ok(store.pipelines.iter().find(|p| &p.id == id).cloned())
+    }
+
+    async fn find_pipelines_by_owner(&self, owner_id: i32) -> Result<Vec<Pipeline>, Error> {
+        let store = self.read()?;
+        Ok(store
+            .pipelines
+            .iter()
+            .filter(|p| p.owner_id == owner_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn list_pipelines(&self) -> Result<Vec<Pipeline>, Error> {
+        Ok(self.read()?.pipelines.clone())
+    }
+
+    async fn insert_pipeline(&self, pipeline: &Pipeline) -> Result<(), Error> {
+        let mut store = self.write()?;
+        if store.pipelines.iter().any(|p| p.id == pipeline.id) {
+            return Err(Error::msg(format!("Duplicate key: {}", pipeline.id)));
+        }
+        store.pipelines.push(pipeline.clone());
+        Ok(())
+    }
+
+    async fn update_pipeline(
+        &self,
+        id: &ObjectId,
+        update: &UpdatePipeline,
+    ) -> Result<Option<Pipeline>, Error> {
+        let mut store = self.write()?;
+        Ok(store
+            .pipelines
+            .iter_mut()
+            .find(|p| &p.id == id)
+            .map(|pipeline| {
+                update.apply(pipeline);
+                pipeline.clone()
+            }))
+    }
+
+    async fn delete_pipeline(&self, id: &ObjectId) -> Result<bool, Error> {
+        let mut store = self.write()?;
+        let count = store.pipelines.len();
+        store.pipelines.retain(|p| &p.id != id);
+        Ok(store.pipelines.len() < count)
+    }
+
+    async fn find_scheduled_pipelines(&self) -> Result<Vec<Pipeline>, Error> {
+        let store = self.read()?;
+        Ok(store
+            .pipelines
+            .iter()
+            .filter(|p| p.schedule.as_ref().is_some_and(|s| s.enabled))
+            .cloned()
+            .collect())
+    }
+
+    async fn set_schedule(
+        &self,
+        id: &ObjectId,
+        schedule: &PipelineSchedule,
+    ) -> Result<Option<Pipeline>, Error> {
+        let mut store = self.write()?;
+        Ok(store
+            .pipelines
+            .iter_mut()
+            .find(|p| &p.id == id)
+            .map(|pipeline| {
+                pipeline.schedule = Some(schedule.clone());
+                pipeline.clone()
+            }))
+    }
+
+    async fn claim_scheduled_run(
+        &self,
+        id: &ObjectId,
+        due: DateTime<Utc>,
+        next: Option<DateTime<Utc>>,
+    ) -> Result<bool, Error> {
+        let mut store = self.write()?;
+        let schedule = store
+            .pipelines
+            .iter_mut()
+            .find(|p| &p.id == id)
+            .and_then(|p| p.schedule.as_mut())
+            .filter(|s| s.enabled && s.next_run == Some(due));
+        Ok(schedule.map(|s| s.next_run = next).is_some())
+    }
+}
+
+impl PipelineRepository for DatabaseConnection {
+    async fn find_pipeline(&self, id: &ObjectId) -> Result<Option<Pipeline>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.find_pipeline(id).await,
+            DatabaseConnection::Mock(mock) => mock.find_pipeline(id).await,
+        }
+    }
+
+    async fn find_pipelines_by_owner(&self, owner_id: i32) -> Result<Vec<Pipeline>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.find_pipelines_by_owner(owner_id).await,
+            DatabaseConnection::Mock(mock) => mock.find_pipelines_by_owner(owner_id).await,
+        }
+    }
+
+    async fn list_pipelines(&self) -> Result<Vec<Pipeline>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.list_pipelines().await,
+            DatabaseConnection::Mock(mock) => mock.list_pipelines().await,
+        }
+    }
+
+    async fn insert_pipeline(&self, pipeline: &Pipeline) -> Result<(), Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.insert_pipeline(pipeline).await,
+            DatabaseConnection::Mock(mock) => mock.insert_pipeline(pipeline).await,
+        }
+    }
+
+    async fn update_pipeline(
+        &self,
+        id: &ObjectId,
+        update: &UpdatePipeline,
+    ) -> Result<Option<Pipeline>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.update_pipeline(id, update).await,
+            DatabaseConnection::Mock(mock) => mock.update_pipeline(id, update).await,
+        }
+    }
+
+    async fn delete_pipeline(&self, id: &ObjectId) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.delete_pipeline(id).await,
+            DatabaseConnection::Mock(mock) => mock.delete_pipeline(id).await,
+        }
+    }
+
+    async fn find_scheduled_pipelines(&self) -> Result<Vec<Pipeline>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.find_scheduled_pipelines().await,
+            DatabaseConnection::Mock(mock) => mock.find_scheduled_pipelines().await,
+        }
+    }
+
+    async fn set_schedule(
+        &self,
+        id: &ObjectId,
+        schedule: &PipelineSchedule,
+    ) -> Result<Option<Pipeline>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.set_schedule(id, schedule).await,
+            DatabaseConnection::Mock(mock) => mock.set_schedule(id, schedule).await,
+        }
+    }
+
+    async fn claim_scheduled_run(
+        &self,
+        id: &ObjectId,
+        due: DateTime<Utc>,
+        next: Option<DateTime<Utc>>,
+    ) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.claim_scheduled_run(id, due, next).await,
+            DatabaseConnection::Mock(mock) => mock.claim_scheduled_run(id, due, next).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::pipeline::CreatePipeline;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_mock_update_pipeline_keeps_empty_fields() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let db = MockDb::default();
+            let pipeline: Pipeline = CreatePipeline {
+                owner_id: 1,
+                name: "pipeline".to_string(),
+                description: "description".to_string(),
+                steps: vec![ObjectId::new().to_hex()],
+                edges: None,
+                schedule: None,
+                parameters: Vec::new(),
+                policies: Vec::new(),
+                cache_results: true,
+            }
+            .into();
+            db.insert_pipeline(&pipeline).await.unwrap();
+
+            let update = UpdatePipeline {
+                name: "renamed".to_string(),
+                description: String::new(),
+                steps: Vec::new(),
+                edges: None,
+                schedule: None,
+                parameters: None,
+                policies: None,
+                cache_results: None,
+            };
+            let updated = db.update_pipeline(&pipeline.id, &update).await.unwrap();
+            let updated = updated.unwrap();
+            assert_eq!(updated.name, "renamed");
+            assert_eq!(updated.description, "description");
+            assert_eq!(updated.steps, pipeline.steps);
+
+            assert!(db.delete_pipeline(&pipeline.id).await.unwrap());
+            assert!(db
+                .update_pipeline(&pipeline.id, &update)
+                .await
+                .unwrap()
+                .is_none());
+        });
+    }
+}
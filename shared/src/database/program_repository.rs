@@ -0,0 +1,764 @@
+use std::future::Future;
+
+use anyhow::Error;
+use bson::{doc, oid::ObjectId, Document};
+use futures::TryStreamExt;
+use log::info;
+use mongodb::{
+    options::{FindOneOptions, FindOptions, IndexOptions},
+    Collection, IndexModel,
+};
+
+use super::db::{is_duplicate_key, Db};
+use super::{db_interface::DatabaseConnection, mock_db::MockDb};
+use crate::models::{
+    program::{Program, UpdateProgramDto},
+    program_version::{ProgramVersion, Restoration},
+};
+
+const PROGRAMS: &str = "programs";
+const PROGRAM_VERSIONS: &str = "program_versions";
+
+/// Storage of programs and of their version history.
+pub trait ProgramRepository {
+    fn find_program(
+        &self,
+        id: &ObjectId,
+    ) -> impl Future<Output = Result<Option<Program>, Error>> + Send;
+
+    fn find_programs_by_owner(
+        &self,
+        owner_id: i32,
+    ) -> impl Future<Output = Result<Vec<Program>, Error>> + Send;
+
+    fn find_programs_by_hash(
+        &self,
+        file_hash: &str,
+        owner_id: Option<i32>,
+    ) -> impl Future<Output = Result<Vec<Program>, Error>> + Send;
+
+    /// First program of `owner_id` whose filename starts with `prefix`,
+    /// ignoring case. Re-uploads are matched to their program this way since
+    /// stored filenames carry an id and a timestamp.
+    fn find_program_by_filename_prefix(
+        &self,
+        owner_id: i32,
+        prefix: &str,
+    ) -> impl Future<Output = Result<Option<Program>, Error>> + Send;
+
+    fn insert_program(&self, program: &Program) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Overwrites the stored fields of `program`; `false` if it does not exist.
+    fn update_program(&self, program: &Program)
+        -> impl Future<Output = Result<bool, Error>> + Send;
+
+    fn update_program_metadata(
+        &self,
+        id: &ObjectId,
+        update: &UpdateProgramDto,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    fn delete_program(&self, id: &ObjectId) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Path of a blob holding `file_hash` among the owner's programs and their
+    /// previous versions.
+    fn find_blob_by_hash(
+        &self,
+        owner_id: i32,
+        file_hash: &str,
+    ) -> impl Future<Output = Result<Option<String>, Error>> + Send;
+
+    /// Whether a program other than `program_id`, or one of its versions,
+    /// references the blob at `file_path`.
+    fn is_blob_shared(
+        &self,
+        file_path: &str,
+        program_id: &ObjectId,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Versions of a program, oldest first.
+    fn find_versions(
+        &self,
+        program_id: &ObjectId,
+    ) -> impl Future<Output = Result<Vec<ProgramVersion>, Error>> + Send;
+
+    fn find_version(
+        &self,
+        program_id: &ObjectId,
+        version: i32,
+    ) -> impl Future<Output = Result<Option<ProgramVersion>, Error>> + Send;
+
+    fn latest_version_number(
+        &self,
+        program_id: &ObjectId,
+    ) -> impl Future<Output = Result<Option<i32>, Error>> + Send;
+
+    /// `false` when the program already has a version with that number,
+    /// which a concurrent upload took.
+    fn insert_version(
+        &self,
+        version: &ProgramVersion,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    fn add_restoration(
+        &self,
+        version_id: &ObjectId,
+        restoration: &Restoration,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    fn delete_versions(
+        &self,
+        program_id: &ObjectId,
+    ) -> impl Future<Output = Result<u64, Error>> + Send;
+}
+
+impl Db {
+    fn programs(&self) -> Collection<Program> {
+        self.client.collection(PROGRAMS)
+    }
+
+    fn program_versions(&self) -> Collection<ProgramVersion> {
+        self.client.collection(PROGRAM_VERSIONS)
+    }
+
+    /// A version number is only ever given once per program, see
+    /// `insert_version`.
+    pub(super) async fn create_version_indexes(&self) -> Result<(), Error> {
+        let index = IndexModel::builder()
+            .keys(doc! {"program_id": 1, "version": 1})
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        self.program_versions().create_index(index, None).await?;
+        Ok(())
+    }
+
+    /// Paths written before the storage abstraction existed were stored
+    /// already URL-encoded, with only the slashes encoded
+    /// (`content%2F121%2F...`). Turns them into plain keys, which storage
+    /// backends encode themselves.
+    pub(super) async fn migrate_encoded_file_paths(&self) -> Result<(), Error> {
+        for name in [PROGRAMS, PROGRAM_VERSIONS] {
+            let collection = self.client.collection::<Document>(name);
+            let options = FindOptions::builder()
+                .projection(doc! {"file_path": 1})
+                .build();
+            let mut cursor = collection
+                .find(doc! {"file_path": {"$regex": "%2F"}}, options)
+                .await?;
+            let mut migrated = 0;
+            while let Some(document) = cursor.try_next().await? {
+                let (Ok(id), Ok(file_path)) =
+                    (document.get_object_id("_id"), document.get_str("file_path"))
+                else {
+                    continue;
+                };
+                collection
+                    .update_one(
+                        doc! {"_id": id, "file_path": file_path},
+                        doc! {"$set": {"file_path": file_path.replace("%2F", "/")}},
+                        None,
+                    )
+                    .await?;
+                migrated += 1;
+            }
+            if migrated > 0 {
+                info!("Decoded {} file paths in {}", migrated, name);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ProgramRepository for Db {
+    async fn find_program(&self, id: &ObjectId) -> Result<Option<Program>, Error> {
+        Ok(self.programs().find_one(doc! {"_id": id}, None).await?)
+    }
+
+    async fn find_programs_by_owner(&self, owner_id: i32) -> Result<Vec<Program>, Error> {
+        let cursor = self
+            .programs()
+            .find(doc! {"owner_id": owner_id}, None)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn find_programs_by_hash(
+        &self,
+        file_hash: &str,
+        owner_id: Option<i32>,
+    ) -> Result<Vec<Program>, Error> {
+        let mut filter = doc! {"file_hash": file_hash};
+        if let Some(owner_id) = owner_id {
+            filter.insert("owner_id", owner_id);
+        }
+        let cursor = self.programs().find(filter, None).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn find_program_by_filename_prefix(
+        &self,
+        owner_id: i32,
+        prefix: &str,
+    ) -> Result<Option<Program>, Error> {
+        let regex_pattern = format!("^{}", regex::escape(prefix));
+        let filter = doc! {
+            "owner_id": owner_id,
+            "filename": { "$regex": regex_pattern, "$options": "i" }
+        };
+        Ok(self.programs().find_one(filter, None).await?)
+    }
+
+    async fn insert_program(&self, program: &Program) -> Result<(), Error> {
+        self.programs().insert_one(program, None).await?;
+        Ok(())
+    }
+
+    async fn update_program(&self, program: &Program) -> Result<bool, Error> {
+        // `$set` rather than a replacement so that fields the model does not
+        // know about are kept.
+        let mut fields = bson::to_document(program)?;
+        fields.remove("_id");
+        let result = self
+            .programs()
+            .update_one(doc! {"_id": program.id}, doc! {"$set": fields}, None)
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn update_program_metadata(
+        &self,
+        id: &ObjectId,
+        update: &UpdateProgramDto,
+    ) -> Result<bool, Error> {
+        let update_command = doc! {
+            "$set": update.build_update_document(),
+            "$currentDate": {"update_time": true}
+        };
+        let result = self
+            .programs()
+            .update_one(doc! {"_id": id}, update_command, None)
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn delete_program(&self, id: &ObjectId) -> Result<bool, Error> {
+        let result = self.programs().delete_one(doc! {"_id": id}, None).await?;
+        Ok(result.deleted_count == 1)
+    }
+
+    async fn find_blob_by_hash(
+        &self,
+        owner_id: i32,
+        file_hash: &str,
+    ) -> Result<Option<String>, Error> {
+        let program = self
+            .programs()
+            .find_one(doc! {"owner_id": owner_id, "file_hash": file_hash}, None)
+            .await?;
+        if let Some(program) = program {
+            return Ok(Some(program.file_path));
+        }
+
+        let version = self
+            .program_versions()
+            .find_one(doc! {"uploaded_by": owner_id, "file_hash": file_hash}, None)
+            .await?;
+        Ok(version.map(|version| version.file_path))
+    }
+
+    async fn is_blob_shared(&self, file_path: &str, program_id: &ObjectId) -> Result<bool, Error> {
+        let programs = self
+            .programs()
+            .count_documents(
+                doc! {"file_path": file_path, "_id": {"$ne": program_id}},
+                None,
+            )
+            .await?;
+        let versions = self
+            .program_versions()
+            .count_documents(
+                doc! {"file_path": file_path, "program_id": {"$ne": program_id}},
+                None,
+            )
+            .await?;
+        Ok(programs + versions > 0)
+    }
+
+    async fn find_versions(&self, program_id: &ObjectId) -> Result<Vec<ProgramVersion>, Error> {
+        let options = FindOptions::builder().sort(doc! {"version": 1}).build();
+        let cursor = self
+            .program_versions()
+            .find(doc! {"program_id": program_id}, options)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn find_version(
+        &self,
+        program_id: &ObjectId,
+        version: i32,
+    ) -> Result<Option<ProgramVersion>, Error> {
+        Ok(self
+            .program_versions()
+            .find_one(doc! {"program_id": program_id, "version": version}, None)
+            .await?)
+    }
+
+    async fn latest_version_number(&self, program_id: &ObjectId) -> Result<Option<i32>, Error> {
+        let options = FindOneOptions::builder().sort(doc! {"version": -1}).build();
+        let latest = self
+            .program_versions()
+            .find_one(doc! {"program_id": program_id}, options)
+            .await?;
+        Ok(latest.map(|version| version.version))
+    }
+
+    async fn insert_version(&self, version: &ProgramVersion) -> Result<bool, Error> {
+        match self.program_versions().insert_one(version, None).await {
+            Ok(_) => Ok(true),
+            Err(e) if is_duplicate_key(&e) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn add_restoration(
+        &self,
+        version_id: &ObjectId,
+        restoration: &Restoration,
+    ) -> Result<(), Error> {
+        let restoration = bson::to_document(restoration)?;
+        self.program_versions()
+            .update_one(
+                doc! {"_id": version_id},
+                doc! {"$push": {"restorations": restoration}},
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_versions(&self, program_id: &ObjectId) -> Result<u64, Error> {
+        let result = self
+            .program_versions()
+            .delete_many(doc! {"program_id": program_id}, None)
+            .await?;
+        Ok(result.deleted_count)
+    }
+}
+
+impl ProgramRepository for MockDb {
+    async fn find_program(&self, id: &ObjectId) -> Result<Option<Program>, Error> {
+        let store = self.read()?;
+        Ok(store.programs.iter().find(|p| &p.id == id).cloned())
+    }
+
+    async fn find_programs_by_owner(&self, owner_id: i32) -> Result<Vec<Program>, Error> {
+        let store = self.read()?;
+        Ok(store
+            .programs
+            .iter()
+            .filter(|p| p.owner_id == owner_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn find_programs_by_hash(
+        &self,
+        file_hash: &str,
+        owner_id: Option<i32>,
+    ) -> Result<Vec<Program>, Error> {
+        let store = self.read()?;
+        Ok(store
+            .programs
+            .iter()
+            .filter(|p| p.file_hash == file_hash)
+            .filter(|p| owner_id.is_none_or(|owner_id| p.owner_id == owner_id))
+            .cloned()
+            .collect())
+    }
+
+    async fn find_program_by_filename_prefix(
+        &self,
+        owner_id: i32,
+        prefix: &str,
+    ) -> Result<Option<Program>, Error> {
+        let prefix = prefix.to_lowercase();
+        let store = self.read()?;
+        Ok(store
+            .programs
+            .iter()
+            .find(|p| p.owner_id == owner_id && p.filename.to_lowercase().starts_with(&prefix))
+            .cloned())
+    }
+
+    async fn insert_program(&self, program: &Program) -> Result<(), Error> {
+        let mut store = self.write()?;
+        if store.programs.iter().any(|p| p.id == program.id) {
+            return Err(Error::msg(format!("Duplicate key: {}", program.id)));
+        }
+        store.programs.push(program.clone());
+        Ok(())
+    }
+
+    async fn update_program(&self, program: &Program) -> Result<bool, Error> {
+        let mut store = self.write()?;
+        match store.programs.iter_mut().find(|p| p.id == program.id) {
+            Some(stored) => {
+                *stored = program.clone();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn update_program_metadata(
+        &self,
+        id: &ObjectId,
+        update: &UpdateProgramDto,
+    ) -> Result<bool, Error> {
+        let mut store = self.write()?;
+        match store.programs.iter_mut().find(|p| &p.id == id) {
+            Some(program) => {
+                update.apply(program);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn delete_program(&self, id: &ObjectId) -> Result<bool, Error> {
+        let mut store = self.write()?;
+        let count = store.programs.len();
+        store.programs.retain(|p| &p.id != id);
+        Ok(store.programs.len() < count)
+    }
+
+    async fn find_blob_by_hash(
+        &self,
+        owner_id: i32,
+        file_hash: &str,
+    ) -> Result<Option<String>, Error> {
+        let store = self.read()?;
+        let program = store
+            .programs
+            .iter()
+            .find(|p| p.owner_id == owner_id && p.file_hash == file_hash)
+            .map(|p| p.file_path.clone());
+        Ok(program.or_else(|| {
+            store
+                .program_versions
+                .iter()
+                .find(|v| v.uploaded_by == owner_id && v.file_hash == file_hash)
+                .map(|v| v.file_path.clone())
+        }))
+    }
+
+    async fn is_blob_shared(&self, file_path: &str, program_id: &ObjectId) -> Result<bool, Error> {
+        let store = self.read()?;
+        Ok(store
+            .programs
+            .iter()
+            .any(|p| p.file_path == file_path && &p.id != program_id)
+            || store
+                .program_versions
+                .iter()
+                .any(|v| v.file_path == file_path && &v.program_id != program_id))
+    }
+
+    async fn find_versions(&self, program_id: &ObjectId) -> Result<Vec<ProgramVersion>, Error> {
+        let store = self.read()?;
+        let mut versions: Vec<ProgramVersion> = store
+            .program_versions
+            .iter()
+            .filter(|v| &v.program_id == program_id)
+            .cloned()
+            .collect();
+        versions.sort_by_key(|v| v.version);
+        Ok(versions)
+    }
+
+    async fn find_version(
+        &self,
+        program_id: &ObjectId,
+        version: i32,
+    ) -> Result<Option<ProgramVersion>, Error> {
+        let store = self.read()?;
+        Ok(store
+            .program_versions
+            .iter()
+            .find(|v| &v.program_id == program_id && v.version == version)
+            .cloned())
+    }
+
+    async fn latest_version_number(&self, program_id: &ObjectId) -> Result<Option<i32>, Error> {
+        let store = self.read()?;
+        Ok(store
+            .program_versions
+            .iter()
+            .filter(|v| &v.program_id == program_id)
+            .map(|v| v.version)
+            .max())
+    }
+
+    async fn insert_version(&self, version: &ProgramVersion) -> Result<bool, Error> {
+        let mut store = self.write()?;
+        if store
+            .program_versions
+            .iter()
+            .any(|v| v.program_id == version.program_id && v.version == version.version)
+        {
+            return Ok(false);
+        }
+        store.program_versions.push(version.clone());
+        Ok(true)
+    }
+
+    async fn add_restoration(
+        &self,
+        version_id: &ObjectId,
+        restoration: &Restoration,
+    ) -> Result<(), Error> {
+        let mut store = self.write()?;
+        if let Some(version) = store
+            .program_versions
+            .iter_mut()
+            .find(|v| &v.id == version_id)
+        {
+            version.restorations.push(restoration.clone());
+        }
+        Ok(())
+    }
+
+    async fn delete_versions(&self, program_id: &ObjectId) -> Result<u64, Error> {
+        let mut store = self.write()?;
+        let count = store.program_versions.len();
+        store
+            .program_versions
+            .retain(|v| &v.program_id != program_id);
+        Ok((count - store.program_versions.len()) as u64)
+    }
+}
+
+impl ProgramRepository for DatabaseConnection {
+    async fn find_program(&self, id: &ObjectId) -> Result<Option<Program>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.find_program(id).await,
+            DatabaseConnection::Mock(mock) => mock.find_program(id).await,
+        }
+    }
+
+    async fn find_programs_by_owner(&self, owner_id: i32) -> Result<Vec<Program>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.find_programs_by_owner(owner_id).await,
+            DatabaseConnection::Mock(mock) => mock.find_programs_by_owner(owner_id).await,
+        }
+    }
+
+    async fn find_programs_by_hash(
+        &self,
+        file_hash: &str,
+        owner_id: Option<i32>,
+    ) -> Result<Vec<Program>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.find_programs_by_hash(file_hash, owner_id).await,
+            DatabaseConnection::Mock(mock) => mock.find_programs_by_hash(file_hash, owner_id).await,
+        }
+    }
+
+    async fn find_program_by_filename_prefix(
+        &self,
+        owner_id: i32,
+        prefix: &str,
+    ) -> Result<Option<Program>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => {
+                db.find_program_by_filename_prefix(owner_id, prefix).await
+            }
+            DatabaseConnection::Mock(mock) => {
+                mock.find_program_by_filename_prefix(owner_id, prefix).await
+            }
+        }
+    }
+
+    async fn insert_program(&self, program: &Program) -> Result<(), Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.insert_program(program).await,
+            DatabaseConnection::Mock(mock) => mock.insert_program(program).await,
+        }
+    }
+
+    async fn update_program(&self, program: &Program) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.update_program(program).await,
+            DatabaseConnection::Mock(mock) => mock.update_program(program).await,
+        }
+    }
+
+    async fn update_program_metadata(
+        &self,
+        id: &ObjectId,
+        update: &UpdateProgramDto,
+    ) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.update_program_metadata(id, update).await,
+            DatabaseConnection::Mock(mock) => mock.update_program_metadata(id, update).await,
+        }
+    }
+
+    async fn delete_program(&self, id: &ObjectId) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.delete_program(id).await,
+            DatabaseConnection::Mock(mock) => mock.delete_program(id).await,
+        }
+    }
+
+    async fn find_blob_by_hash(
+        &self,
+        owner_id: i32,
+        file_hash: &str,
+    ) -> Result<Option<String>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.find_blob_by_hash(owner_id, file_hash).await,
+            DatabaseConnection::Mock(mock) => mock.find_blob_by_hash(owner_id, file_hash).await,
+        }
+    }
+
+    async fn is_blob_shared(&self, file_path: &str, program_id: &ObjectId) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.is_blob_shared(file_path, program_id).await,
+            DatabaseConnection::Mock(mock) => mock.is_blob_shared(file_path, program_id).await,
+        }
+    }
+
+    async fn find_versions(&self, program_id: &ObjectId) -> Result<Vec<ProgramVersion>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.find_versions(program_id).await,
+            DatabaseConnection::Mock(mock) => mock.find_versions(program_id).await,
+        }
+    }
+
+    async fn find_version(
+        &self,
+        program_id: &ObjectId,
+        version: i32,
+    ) -> Result<Option<ProgramVersion>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.find_version(program_id, version).await,
+            DatabaseConnection::Mock(mock) => mock.find_version(program_id, version).await,
+        }
+    }
+
+    async fn latest_version_number(&self, program_id: &ObjectId) -> Result<Option<i32>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.latest_version_number(program_id).await,
+            DatabaseConnection::Mock(mock) => mock.latest_version_number(program_id).await,
+        }
+    }
+
+    async fn insert_version(&self, version: &ProgramVersion) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.insert_version(version).await,
+            DatabaseConnection::Mock(mock) => mock.insert_version(version).await,
+        }
+    }
+
+    async fn add_restoration(
+        &self,
+        version_id: &ObjectId,
+        restoration: &Restoration,
+    ) -> Result<(), Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.add_restoration(version_id, restoration).await,
+            DatabaseConnection::Mock(mock) => mock.add_restoration(version_id, restoration).await,
+        }
+    }
+
+    async fn delete_versions(&self, program_id: &ObjectId) -> Result<u64, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.delete_versions(program_id).await,
+            DatabaseConnection::Mock(mock) => mock.delete_versions(program_id).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::program::any_media_type;
+    use chrono::Utc;
+    use tokio::runtime::Runtime;
+
+    fn program(owner_id: i32, filename: &str, file_hash: &str) -> Program {
+        let now = Utc::now();
+        Program {
+            id: ObjectId::new(),
+            owner_id,
+            filename: filename.to_string(),
+            code_url: String::new(),
+            content_type: "text/plain".to_string(),
+            file_size: 0,
+            input_type: any_media_type(),
+            output_type: ".txt".to_string(),
+            upload_time: now,
+            update_time: now,
+            file_path: format!("content/{}/{}", owner_id, filename),
+            file_hash: file_hash.to_string(),
+            current_version: 1,
+        }
+    }
+
+    #[test]
+    fn test_mock_program_lookups() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let db = MockDb::default();
+            let script = program(1, "Script-abc-123.py", "aa");
+            db.insert_program(&script).await.unwrap();
+            db.insert_program(&program(2, "other.py", "aa"))
+                .await
+                .unwrap();
+
+            let found = db.find_program_by_filename_prefix(1, "script").await;
+            assert_eq!(found.unwrap().map(|p| p.id), Some(script.id));
+            assert_eq!(db.find_programs_by_hash("aa", None).await.unwrap().len(), 2);
+            assert_eq!(
+                db.find_programs_by_hash("aa", Some(2)).await.unwrap().len(),
+                1
+            );
+            assert!(db
+                .is_blob_shared(&script.file_path, &ObjectId::new())
+                .await
+                .unwrap());
+            assert!(!db
+                .is_blob_shared(&script.file_path, &script.id)
+                .await
+                .unwrap());
+        });
+    }
+
+    #[test]
+    fn test_mock_versions_are_sorted() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let db = MockDb::default();
+            let script = program(1, "script.py", "aa");
+            for version in [2, 1, 3] {
+                db.insert_version(&ProgramVersion::from_program(&script, version))
+                    .await
+                    .unwrap();
+            }
+
+            // A number already taken is refused, as the unique index does.
+            assert!(!db
+                .insert_version(&ProgramVersion::from_program(&script, 2))
+                .await
+                .unwrap());
+
+            let versions = db.find_versions(&script.id).await.unwrap();
+            let numbers: Vec<i32> = versions.iter().map(|v| v.version).collect();
+            assert_eq!(numbers, vec![1, 2, 3]);
+            assert_eq!(db.latest_version_number(&script.id).await.unwrap(), Some(3));
+            assert_eq!(db.delete_versions(&script.id).await.unwrap(), 3);
+        });
+    }
+}
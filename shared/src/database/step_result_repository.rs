@@ -0,0 +1,222 @@
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Error;
+use bson::{doc, DateTime as BsonDateTime};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::options::{IndexOptions, ReplaceOptions};
+use mongodb::{Collection, IndexModel};
+use serde::{Deserialize, Serialize};
+
+use super::{db::Db, db_interface::DatabaseConnection, mock_db::MockDb};
+use crate::serializers::bson_datetime_serializer;
+
+const STEP_RESULTS: &str = "step_results";
+/// How long an expired result is kept before MongoDB drops it. The outputs
+/// are deleted by `result_cache::run_result_sweep`, which needs the records
+/// to find them, so this only catches what the sweep missed.
+const EXPIRED_RESULT_GRACE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Output of a successful step, reusable by any run of the same program on
+/// the same input until `expires_at`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepResult {
+    /// See `result_cache::key`.
+    #[serde(rename = "_id")]
+    pub key: String,
+
+    #[serde(rename = "program_id")]
+    pub program_id: String,
+
+    /// Storage key of the cached stdout.
+    #[serde(rename = "output_path")]
+    pub output_path: String,
+
+    #[serde(rename = "output_size")]
+    pub output_size: i64,
+
+    #[serde(rename = "created_at", with = "bson_datetime_serializer")]
+    pub created_at: DateTime<Utc>,
+
+    #[serde(rename = "expires_at", with = "bson_datetime_serializer")]
+    pub expires_at: DateTime<Utc>,
+}
+
+pub trait StepResultRepository {
+    /// The result stored under `key`, unless it has expired.
+    fn find_step_result(
+        &self,
+        key: &str,
+    ) -> impl Future<Output = Result<Option<StepResult>, Error>> + Send;
+
+    /// Stores `result`, replacing any result with the same key.
+    fn save_step_result(
+        &self,
+        result: &StepResult,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Results that expired before `before`.
+    fn find_expired_step_results(
+        &self,
+        before: DateTime<Utc>,
+    ) -> impl Future<Output = Result<Vec<StepResult>, Error>> + Send;
+
+    /// Deletes the result under `key` if it still expires at `expires_at`,
+    /// so that a result saved again meanwhile is kept.
+    fn delete_step_result(
+        &self,
+        key: &str,
+        expires_at: DateTime<Utc>,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+}
+
+impl Db {
+    fn step_results(&self) -> Collection<StepResult> {
+        self.client.collection(STEP_RESULTS)
+    }
+
+    /// Expired results are dropped by MongoDB too, in case the sweep does
+    /// not get to them.
+    pub(super) async fn create_step_result_indexes(&self) -> Result<(), Error> {
+        let index = IndexModel::builder()
+            .keys(doc! {"expires_at": 1})
+            .options(
+                IndexOptions::builder()
+                    .expire_after(EXPIRED_RESULT_GRACE)
+                    .build(),
+            )
+            .build();
+        self.step_results().create_index(index, None).await?;
+        Ok(())
+    }
+}
+
+impl StepResultRepository for Db {
+    async fn find_step_result(&self, key: &str) -> Result<Option<StepResult>, Error> {
+        let filter = doc! {
+            "_id": key,
+            "expires_at": {"$gt": BsonDateTime::from_chrono(Utc::now())},
+        };
+        Ok(self.step_results().find_one(filter, None).await?)
+    }
+
+    async fn save_step_result(&self, result: &StepResult) -> Result<(), Error> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.step_results()
+            .replace_one(doc! {"_id": &result.key}, result, options)
+            .await?;
+        Ok(())
+    }
+
+    async fn find_expired_step_results(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<StepResult>, Error> {
+        let cursor = self
+            .step_results()
+            .find(
+                doc! {"expires_at": {"$lte": BsonDateTime::from_chrono(before)}},
+                None,
+            )
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn delete_step_result(
+        &self,
+        key: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, Error> {
+        let result = self
+            .step_results()
+            .delete_one(
+                doc! {"_id": key, "expires_at": BsonDateTime::from_chrono(expires_at)},
+                None,
+            )
+            .await?;
+        Ok(result.deleted_count == 1)
+    }
+}
+
+impl StepResultRepository for MockDb {
+    async fn find_step_result(&self, key: &str) -> Result<Option<StepResult>, Error> {
+        let now = Utc::now();
+        Ok(self
+            .read()?
+            .step_results
+            .iter()
+            .find(|result| result.key == key && result.expires_at > now)
+            .cloned())
+    }
+
+    async fn save_step_result(&self, result: &StepResult) -> Result<(), Error> {
+        let mut store = self.write()?;
+        store.step_results.retain(|stored| stored.key != result.key);
+        store.step_results.push(result.clone());
+        Ok(())
+    }
+
+    async fn find_expired_step_results(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<StepResult>, Error> {
+        Ok(self
+            .read()?
+            .step_results
+            .iter()
+            .filter(|result| result.expires_at <= before)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_step_result(
+        &self,
+        key: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, Error> {
+        let mut store = self.write()?;
+        let count = store.step_results.len();
+        store
+            .step_results
+            .retain(|result| result.key != key || result.expires_at != expires_at);
+        Ok(store.step_results.len() < count)
+    }
+}
+
+impl StepResultRepository for DatabaseConnection {
+    async fn find_step_result(&self, key: &str) -> Result<Option<StepResult>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.find_step_result(key).await,
+            DatabaseConnection::Mock(mock) => mock.find_step_result(key).await,
+        }
+    }
+
+    async fn save_step_result(&self, result: &StepResult) -> Result<(), Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.save_step_result(result).await,
+            DatabaseConnection::Mock(mock) => mock.save_step_result(result).await,
+        }
+    }
+
+    async fn find_expired_step_results(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<StepResult>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.find_expired_step_results(before).await,
+            DatabaseConnection::Mock(mock) => mock.find_expired_step_results(before).await,
+        }
+    }
+
+    async fn delete_step_result(
+        &self,
+        key: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.delete_step_result(key, expires_at).await,
+            DatabaseConnection::Mock(mock) => mock.delete_step_result(key, expires_at).await,
+        }
+    }
+}
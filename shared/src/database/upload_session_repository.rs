@@ -0,0 +1,273 @@
+use std::future::Future;
+
+use anyhow::Error;
+use bson::{doc, oid::ObjectId, DateTime as BsonDateTime};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::Collection;
+
+use super::{db::Db, db_interface::DatabaseConnection, mock_db::MockDb};
+use crate::models::upload_session::{UploadPart, UploadSession};
+
+const UPLOAD_SESSIONS: &str = "upload_sessions";
+
+pub trait UploadSessionRepository {
+    fn find_upload_session(
+        &self,
+        id: &ObjectId,
+    ) -> impl Future<Output = Result<Option<UploadSession>, Error>> + Send;
+
+    fn insert_upload_session(
+        &self,
+        session: &UploadSession,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Records `part` and moves the offset past it, provided the session is
+    /// still open and at `offset`. Returns `false` otherwise, so that only one
+    /// of two concurrent chunks for the same offset is accepted.
+    fn append_upload_part(
+        &self,
+        id: &ObjectId,
+        offset: i64,
+        part: &UploadPart,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Moves the session from status `from` to `to`. Returns `false` when
+    /// it was not in `from`, so that only one of two concurrent finalizes
+    /// goes on.
+    fn set_upload_session_status(
+        &self,
+        id: &ObjectId,
+        from: &str,
+        to: &str,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Sessions, in any status, last updated before `before`.
+    fn find_stale_upload_sessions(
+        &self,
+        before: DateTime<Utc>,
+    ) -> impl Future<Output = Result<Vec<UploadSession>, Error>> + Send;
+
+    fn delete_upload_session(
+        &self,
+        id: &ObjectId,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+}
+
+impl Db {
+    fn upload_sessions(&self) -> Collection<UploadSession> {
+        self.client.collection(UPLOAD_SESSIONS)
+    }
+}
+
+impl UploadSessionRepository for Db {
+    async fn find_upload_session(&self, id: &ObjectId) -> Result<Option<UploadSession>, Error> {
+        Ok(self
+            .upload_sessions()
+            .find_one(doc! {"_id": id}, None)
+            .await?)
+    }
+
+    async fn insert_upload_session(&self, session: &UploadSession) -> Result<(), Error> {
+        self.upload_sessions().insert_one(session, None).await?;
+        Ok(())
+    }
+
+    async fn append_upload_part(
+        &self,
+        id: &ObjectId,
+        offset: i64,
+        part: &UploadPart,
+    ) -> Result<bool, Error> {
+        let part_doc = bson::to_document(part)?;
+        let result = self
+            .upload_sessions()
+            .update_one(
+                doc! {"_id": id, "offset": offset, "status": "open"},
+                doc! {
+                    "$set": {
+                        "offset": offset + part.size,
+                        "update_time": BsonDateTime::from_chrono(Utc::now()),
+                    },
+                    "$push": {"parts": part_doc},
+                },
+                None,
+            )
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn set_upload_session_status(
+        &self,
+        id: &ObjectId,
+        from: &str,
+        to: &str,
+    ) -> Result<bool, Error> {
+        let result = self
+            .upload_sessions()
+            .update_one(
+                doc! {"_id": id, "status": from},
+                doc! {"$set": {
+                    "status": to,
+                    "update_time": BsonDateTime::from_chrono(Utc::now()),
+                }},
+                None,
+            )
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn find_stale_upload_sessions(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<UploadSession>, Error> {
+        let cursor = self
+            .upload_sessions()
+            .find(
+                doc! {"update_time": {"$lt": BsonDateTime::from_chrono(before)}},
+                None,
+            )
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn delete_upload_session(&self, id: &ObjectId) -> Result<bool, Error> {
+        let result = self
+            .upload_sessions()
+            .delete_one(doc! {"_id": id}, None)
+            .await?;
+        Ok(result.deleted_count == 1)
+    }
+}
+
+impl UploadSessionRepository for MockDb {
+    async fn find_upload_session(&self, id: &ObjectId) -> Result<Option<UploadSession>, Error> {
+        let store = self.read()?;
+        Ok(store.upload_sessions.iter().find(|s| &s.id == id).cloned())
+    }
+
+    async fn insert_upload_session(&self, session: &UploadSession) -> Result<(), Error> {
+        self.write()?.upload_sessions.push(session.clone());
+        Ok(())
+    }
+
+    async fn append_upload_part(
+        &self,
+        id: &ObjectId,
+        offset: i64,
+        part: &UploadPart,
+    ) -> Result<bool, Error> {
+        let mut store = self.write()?;
+        let session = store
+            .upload_sessions
+            .iter_mut()
+            .find(|s| &s.id == id && s.offset == offset && s.status == "open");
+        match session {
+            Some(session) => {
+                session.offset = offset + part.size;
+                session.update_time = Utc::now();
+                session.parts.push(part.clone());
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn set_upload_session_status(
+        &self,
+        id: &ObjectId,
+        from: &str,
+        to: &str,
+    ) -> Result<bool, Error> {
+        let mut store = self.write()?;
+        let session = store
+            .upload_sessions
+            .iter_mut()
+            .find(|s| &s.id == id && s.status == from);
+        match session {
+            Some(session) => {
+                session.status = to.to_string();
+                session.update_time = Utc::now();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn find_stale_upload_sessions(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<UploadSession>, Error> {
+        let store = self.read()?;
+        Ok(store
+            .upload_sessions
+            .iter()
+            .filter(|s| s.update_time < before)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_upload_session(&self, id: &ObjectId) -> Result<bool, Error> {
+        let mut store = self.write()?;
+        let count = store.upload_sessions.len();
+        store.upload_sessions.retain(|s| &s.id != id);
+        Ok(store.upload_sessions.len() < count)
+    }
+}
+
+impl UploadSessionRepository for DatabaseConnection {
+    async fn find_upload_session(&self, id: &ObjectId) -> Result<Option<UploadSession>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.find_upload_session(id).await,
+            DatabaseConnection::Mock(mock) => mock.find_upload_session(id).await,
+        }
+    }
+
+    async fn insert_upload_session(&self, session: &UploadSession) -> Result<(), Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.insert_upload_session(session).await,
+            DatabaseConnection::Mock(mock) => mock.insert_upload_session(session).await,
+        }
+    }
+
+    async fn append_upload_part(
+        &self,
+        id: &ObjectId,
+        offset: i64,
+        part: &UploadPart,
+    ) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.append_upload_part(id, offset, part).await,
+            DatabaseConnection::Mock(mock) => mock.append_upload_part(id, offset, part).await,
+        }
+    }
+
+    async fn set_upload_session_status(
+        &self,
+        id: &ObjectId,
+        from: &str,
+        to: &str,
+    ) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.set_upload_session_status(id, from, to).await,
+            DatabaseConnection::Mock(mock) => mock.set_upload_session_status(id, from, to).await,
+        }
+    }
+
+    async fn find_stale_upload_sessions(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<UploadSession>, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.find_stale_upload_sessions(before).await,
+            DatabaseConnection::Mock(mock) => mock.find_stale_upload_sessions(before).await,
+        }
+    }
+
+    async fn delete_upload_session(&self, id: &ObjectId) -> Result<bool, Error> {
+        match self {
+            DatabaseConnection::Real(db) => db.delete_upload_session(id).await,
+            DatabaseConnection::Mock(mock) => mock.delete_upload_session(id).await,
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+
+use bson::oid::ObjectId;
+use tokio::sync::watch;
+
+/// Names this process among the servers sharing the database: the host name
+/// and a random part, since several processes may run on one host.
+pub fn instance_id() -> &'static str {
+    static INSTANCE: OnceLock<String> = OnceLock::new();
+    INSTANCE.get_or_init(|| {
+        format!(
+            "{}-{}",
+            std::env::var("HOSTNAME").unwrap_or_else(|_| "server".to_string()),
+            ObjectId::new()
+        )
+    })
+}
+
+/// Lets requests stop the executions running in this process.
+#[derive(Clone, Default)]
+pub struct CancelRegistry {
+    running: Arc<Mutex<HashMap<ObjectId, Arc<watch::Sender<bool>>>>>,
+}
+
+/// Held by a running execution; dropping it tells waiting cancellers that
+/// the execution has stopped.
+pub struct CancelToken {
+    receiver: watch::Receiver<bool>,
+}
+
+impl CancelToken {
+    pub fn is_cancelled(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Completes once the execution is cancelled.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.receiver.clone();
+        if receiver.wait_for(|cancelled| *cancelled).await.is_err() {
+            // The registry forgot the execution, so nobody can cancel it.
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+impl CancelRegistry {
+    fn running(&self) -> MutexGuard<'_, HashMap<ObjectId, Arc<watch::Sender<bool>>>> {
+        self.running.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn register(&self, execution_id: ObjectId) -> CancelToken {
+        let (sender, receiver) = watch::channel(false);
+        self.running().insert(execution_id, Arc::new(sender));
+        CancelToken { receiver }
+    }
+
+    pub fn remove(&self, execution_id: &ObjectId) {
+        self.running().remove(execution_id);
+    }
+
+    /// Asks the execution to stop and waits until it did, or `None` if it is
+    /// not running in this process.
+    pub fn cancel(&self, execution_id: &ObjectId) -> Option<impl std::future::Future<Output = ()>> {
+        let sender = self.running().get(execution_id)?.clone();
+        sender.send_replace(true);
+        Some(async move { sender.closed().await })
+    }
+}
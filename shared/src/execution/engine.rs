@@ -0,0 +1,760 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use bson::oid::ObjectId;
+use chrono::Utc;
+use futures::stream::{FuturesUnordered, StreamExt};
+use log::{error, info, warn};
+use serde_json::json;
+
+use super::cancel::{instance_id, CancelRegistry, CancelToken};
+use super::events::{self, EventHub};
+use super::graph::PipelineGraph;
+use super::media_type;
+use super::policy;
+use super::result_cache;
+use super::runner::{self, Limits, RunOutput};
+use super::sandbox::Sandbox;
+use crate::database::{
+    db_interface::DatabaseConnection, execution_repository::ExecutionRepository,
+    program_repository::ProgramRepository,
+};
+use crate::models::pipeline::{ExecutionRecord, Pipeline, StepAttempt, StepPolicy, StepRecord};
+use crate::storage::blob_store::{BlobStore, Storage};
+
+/// How often the server running an execution says it still is.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// After how many missed heartbeats the server is taken for gone.
+const MISSED_HEARTBEATS: u32 = 3;
+
+/// What a run starts from besides the pipeline itself.
+#[derive(Debug, Default)]
+pub struct ExecutionInput {
+    /// The value of every parameter, see `parameters::resolve`.
+    pub parameters: BTreeMap<String, String>,
+    /// Given on stdin to the steps without inputs.
+    pub file: Option<InputFile>,
+}
+
+#[derive(Debug)]
+pub struct InputFile {
+    pub filename: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// How the steps of one execution run.
+struct Plan {
+    graph: PipelineGraph,
+    /// One per step, in step order.
+    policies: Vec<StepPolicy>,
+    /// Given to the steps without inputs.
+    input: Vec<u8>,
+    /// How long step outputs are cached, `None` when the pipeline does not
+    /// use the result cache.
+    cache_ttl: Option<Duration>,
+}
+
+/// Stores a `running` execution of `pipeline` and runs its steps in the
+/// background. A step starts once the steps feeding it are done and reads
+/// their stdout, in step order; the stdout of the steps nobody reads from
+/// becomes the output of the execution. The steps without inputs read the
+/// input file instead, if any.
+///
+/// Failed steps are retried, limited and replaced by a fallback program as
+/// their `StepPolicy` says. Unless the pipeline opts out, a step whose
+/// program already succeeded on the same input and parameters reuses that
+/// output instead of running.
+///
+/// The record is updated after every step, so its `steps` show the progress
+/// of the run, and the progress is published on `events` as it happens. The
+/// run can be stopped through `cancels`, or from another server through
+/// `cancel_requested`, which is checked on every heartbeat.
+pub async fn start_execution(
+    db: &DatabaseConnection,
+    storage: &Storage,
+    events: &EventHub,
+    cancels: &CancelRegistry,
+    pipeline: &Pipeline,
+    input: ExecutionInput,
+) -> Result<ExecutionRecord> {
+    let graph = PipelineGraph::of(pipeline)?;
+    let mut record = ExecutionRecord {
+        id: ObjectId::new(),
+        pipeline_id: pipeline.id,
+        execution_time: Utc::now(),
+        status: "running".to_string(),
+        output: String::new(),
+        output_size: 0,
+        finished_time: None,
+        steps: pipeline
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| StepRecord::pending(index as i32 + 1, step))
+            .collect(),
+        parameters: input.parameters,
+        input_path: None,
+        input_filename: None,
+        cache_hits: 0,
+        instance: instance_id().to_string(),
+        heartbeat: Some(Utc::now()),
+        cancel_requested: false,
+    };
+    let input = match input.file {
+        Some(file) => {
+            let key = execution_input_key(&record.id);
+            storage
+                .put(&key, &file.content_type, file.data.clone())
+                .await?;
+            record.input_path = Some(key);
+            record.input_filename = Some(file.filename);
+            file.data
+        }
+        None => Vec::new(),
+    };
+    let plan = Plan {
+        graph,
+        policies: policy::for_steps(pipeline),
+        input,
+        cache_ttl: Some(result_cache::cache_ttl())
+            .filter(|ttl| pipeline.cache_results && !ttl.is_zero()),
+    };
+    db.insert_execution(&record).await?;
+    events.open(record.id);
+    let token = cancels.register(record.id);
+    info!(
+        "Execution {} of pipeline {} started",
+        record.id, pipeline.id
+    );
+
+    let (db, storage, events, cancels) =
+        (db.clone(), storage.clone(), events.clone(), cancels.clone());
+    let mut running = record.clone();
+    tokio::spawn(async move {
+        let id = running.id;
+        let execution = run_execution(&db, &storage, &events, token, &plan, &mut running);
+        tokio::select! {
+            result = execution => if let Err(e) = result {
+                error!("Could not record execution {}: {:?}", id, e);
+            },
+            _ = heartbeat(&db, &cancels, id) => {}
+        }
+        cancels.remove(&running.id);
+        events.publish(
+            &running.id,
+            events::EXECUTION_FINISHED,
+            json!({"status": running.status, "output": running.output}),
+        );
+        events.finish(&running.id);
+    });
+
+    Ok(record)
+}
+
+async fn run_execution(
+    db: &DatabaseConnection,
+    storage: &Storage,
+    events: &EventHub,
+    token: CancelToken,
+    plan: &Plan,
+    record: &mut ExecutionRecord,
+) -> Result<()> {
+    match run_steps(db, storage, events, &token, plan, record).await {
+        Ok(output) => {
+            record.status = "success".to_string();
+            record.output = preview(&output).unwrap_or_default();
+            record.output_size = output.len() as i64;
+        }
+        Err(_) if token.is_cancelled() => cancel_record(record),
+        Err(e) => {
+            record.status = "failed".to_string();
+            record.output = e.to_string();
+            skip_pending_steps(record);
+        }
+    }
+    record.finished_time = Some(Utc::now());
+    db.update_execution(record).await?;
+    info!("Execution {} finished: {}", record.id, record.status);
+    // Only now may a canceller look at the record.
+    drop(token);
+    Ok(())
+}
+
+/// Tells the other servers that this one is still running the execution,
+/// forever, and cancels it once one of them asks to.
+async fn heartbeat(db: &DatabaseConnection, cancels: &CancelRegistry, id: ObjectId) {
+    let mut cancelled = false;
+    loop {
+        tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+        match db.beat_execution(&id).await {
+            Ok(true) if !cancelled => {
+                info!("Execution {} cancelled from another server", id);
+                cancelled = cancels.cancel(&id).is_some();
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Could not record heartbeat of execution {}: {}", id, e),
+        }
+    }
+}
+
+/// Whether the server running `record` still says so, as opposed to having
+/// stopped without finishing it.
+pub fn owner_alive(record: &ExecutionRecord) -> bool {
+    let stale_after = HEARTBEAT_INTERVAL * MISSED_HEARTBEATS;
+    record.heartbeat.is_some_and(|heartbeat| {
+        (Utc::now() - heartbeat)
+            .to_std()
+            .map_or(true, |age| age < stale_after)
+    })
+}
+
+fn skip_pending_steps(record: &mut ExecutionRecord) {
+    for step in record.steps.iter_mut() {
+        if step.status == "pending" {
+            step.status = "skipped".to_string();
+        }
+    }
+}
+
+/// Marks an execution as cancelled: the step that was running is cancelled
+/// and the ones after it are skipped.
+pub fn cancel_record(record: &mut ExecutionRecord) {
+    record.status = "cancelled".to_string();
+    record.output = "Cancelled".to_string();
+    for step in record.steps.iter_mut() {
+        if step.status == "running" {
+            step.status = "cancelled".to_string();
+        }
+    }
+    skip_pending_steps(record);
+    record.finished_time = Some(Utc::now());
+}
+
+/// Storage key of the file an execution was started with.
+pub fn execution_input_key(execution_id: &ObjectId) -> String {
+    format!("executions/{}/input", execution_id)
+}
+
+/// Storage key of the stdout of step `step` (1-based) of an execution.
+pub fn step_output_key(execution_id: &ObjectId, step: i32) -> String {
+    format!("executions/{}/{}.out", execution_id, step)
+}
+
+/// A program a step runs, as stored.
+struct StepProgram {
+    program_id: String,
+    filename: String,
+    code: Vec<u8>,
+    /// Content hash, part of the result cache key.
+    hash: String,
+    output_type: String,
+}
+
+/// A step whose program is about to run.
+struct StartedStep {
+    index: usize,
+    program: StepProgram,
+    fallback: Option<StepProgram>,
+    /// Result cache key of the run, when the pipeline uses the cache.
+    cache_key: Option<String>,
+    /// Output of an earlier run with the same cache key.
+    cached: Option<Vec<u8>>,
+}
+
+struct FinishedStep {
+    index: usize,
+    /// The last program that ran, the fallback if it came to that.
+    filename: String,
+    /// Media type of that program's output.
+    content_type: String,
+    output: Result<RunOutput>,
+    attempts: Vec<StepAttempt>,
+    /// Where to cache the output, if it is worth caching.
+    cache_key: Option<String>,
+    /// The output came from the result cache.
+    cached: bool,
+}
+
+/// Runs every step once all of its inputs are done, several at a time when
+/// the graph allows it. After a failure or cancellation no new step starts,
+/// but the running ones are waited for.
+async fn run_steps(
+    db: &DatabaseConnection,
+    storage: &Storage,
+    events: &EventHub,
+    token: &CancelToken,
+    plan: &Plan,
+    record: &mut ExecutionRecord,
+) -> Result<Vec<u8>> {
+    let graph = &plan.graph;
+    let defaults = Limits::from_env();
+    let sandbox = Sandbox::shared().await?;
+    let parameters = record.parameters.clone();
+    let mut waiting: Vec<usize> = (0..graph.len())
+        .map(|step| graph.inputs(step).len())
+        .collect();
+    let mut ready: BTreeSet<usize> = (0..graph.len())
+        .filter(|step| waiting[*step] == 0)
+        .collect();
+    let mut outputs: Vec<Vec<u8>> = vec![Vec::new(); graph.len()];
+    let mut running = FuturesUnordered::new();
+    let mut failure = None;
+
+    loop {
+        while failure.is_none() && !token.is_cancelled() {
+            let Some(index) = ready.pop_first() else {
+                break;
+            };
+            let input: Vec<u8> = if graph.inputs(index).is_empty() {
+                plan.input.clone()
+            } else {
+                graph
+                    .inputs(index)
+                    .iter()
+                    .flat_map(|input| outputs[*input].iter().copied())
+                    .collect()
+            };
+            let policy = &plan.policies[index];
+            match start_step(db, storage, events, record, index, policy).await {
+                Ok(mut started) => {
+                    if plan.cache_ttl.is_some() {
+                        let key = result_cache::key(
+                            &started.program.hash,
+                            &started.program.filename,
+                            &input,
+                            &parameters,
+                        );
+                        started.cached = result_cache::lookup(db, storage, &key).await;
+                        started.cache_key = Some(key);
+                    }
+                    let run = StepRun {
+                        events,
+                        token,
+                        execution_id: record.id,
+                        step: index as i32 + 1,
+                        input,
+                        parameters: &parameters,
+                        sandbox,
+                        limits: policy::limits(policy, &defaults),
+                    };
+                    running.push(run_step(run, policy, started))
+                }
+                Err(e) => failure = Some(e),
+            }
+        }
+
+        let Some(finished) = running.next().await else {
+            break;
+        };
+        match finish_step(db, storage, events, plan, record, finished).await {
+            Ok((index, stdout)) => {
+                outputs[index] = stdout;
+                for next in graph.outputs(index) {
+                    waiting[*next] -= 1;
+                    if waiting[*next] == 0 {
+                        ready.insert(*next);
+                    }
+                }
+            }
+            Err(e) => {
+                failure.get_or_insert(e);
+            }
+        }
+        db.update_execution(record).await?;
+    }
+
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    if token.is_cancelled() {
+        return Err(Error::msg("Cancelled"));
+    }
+    Ok(graph
+        .sinks()
+        .flat_map(|step| std::mem::take(&mut outputs[step]))
+        .collect())
+}
+
+async fn load_program(
+    db: &DatabaseConnection,
+    storage: &Storage,
+    program_id: &str,
+) -> Result<StepProgram> {
+    let id = ObjectId::parse_str(program_id.trim())
+        .map_err(|e| Error::msg(format!("Invalid program id {}: {}", program_id, e)))?;
+    let program = db
+        .find_program(&id)
+        .await?
+        .ok_or_else(|| Error::msg(format!("Program not found: {}", id)))?;
+    let code = storage.get(&program.file_path).await?;
+    let hash = if program.file_hash.is_empty() {
+        result_cache::program_hash(&code)
+    } else {
+        program.file_hash
+    };
+    Ok(StepProgram {
+        program_id: program.id.to_hex(),
+        filename: program.filename,
+        code,
+        hash,
+        output_type: program.output_type,
+    })
+}
+
+async fn start_step(
+    db: &DatabaseConnection,
+    storage: &Storage,
+    events: &EventHub,
+    record: &mut ExecutionRecord,
+    index: usize,
+    policy: &StepPolicy,
+) -> Result<StartedStep> {
+    let program = load_program(db, storage, &record.steps[index].program_id).await?;
+    let fallback = match &policy.fallback {
+        Some(fallback) => Some(load_program(db, storage, fallback).await?),
+        None => None,
+    };
+
+    let step = &mut record.steps[index];
+    step.filename = program.filename.clone();
+    step.status = "running".to_string();
+    step.start_time = Some(Utc::now());
+    let number = step.step;
+    db.update_execution(record).await?;
+    events.publish(
+        &record.id,
+        events::STEP_STARTED,
+        json!({"step": number, "program_id": program.program_id, "filename": program.filename}),
+    );
+    info!(
+        "Step {} of execution {}: running {}",
+        number, record.id, program.filename
+    );
+
+    Ok(StartedStep {
+        index,
+        program,
+        fallback,
+        cache_key: None,
+        cached: None,
+    })
+}
+
+/// What every run of one step shares.
+struct StepRun<'a> {
+    events: &'a EventHub,
+    token: &'a CancelToken,
+    execution_id: ObjectId,
+    step: i32,
+    input: Vec<u8>,
+    parameters: &'a BTreeMap<String, String>,
+    sandbox: &'a Sandbox,
+    limits: Limits,
+}
+
+impl StepRun<'_> {
+    async fn attempt(
+        &self,
+        program: &StepProgram,
+        attempt: u32,
+    ) -> (StepAttempt, Result<RunOutput>) {
+        let start_time = Utc::now();
+        // Steps wait for a free slot rather than fail, unless the execution
+        // is cancelled meanwhile.
+        let permit = tokio::select! {
+            permit = runner::slots().acquire() => permit.ok(),
+            _ = self.token.cancelled() => None,
+        };
+        let output = match permit {
+            Some(_permit) => {
+                runner::run(
+                    self.sandbox,
+                    &program.filename,
+                    &program.code,
+                    &self.input,
+                    self.parameters,
+                    &self.limits,
+                    |line| {
+                        self.events.publish(
+                            &self.execution_id,
+                            events::STDOUT,
+                            json!({"step": self.step, "line": line}),
+                        )
+                    },
+                    self.token.cancelled(),
+                )
+                .await
+            }
+            None => Ok(RunOutput::cancelled()),
+        };
+        let (status, exit_code, error) = match &output {
+            Ok(output) if output.success() => ("success", output.exit_code, None),
+            Ok(output) if output.cancelled => ("cancelled", None, None),
+            Ok(output) => ("failed", output.exit_code, Some(failure_reason(output))),
+            Err(e) => ("failed", None, Some(e.to_string())),
+        };
+        let attempt = StepAttempt {
+            attempt,
+            program_id: program.program_id.clone(),
+            status: status.to_string(),
+            start_time,
+            end_time: Utc::now(),
+            exit_code,
+            error: error.map(|error| tail(&error, ATTEMPT_ERROR_LIMIT).to_string()),
+        };
+        (attempt, output)
+    }
+}
+
+/// Longest `StepAttempt.error` kept, in bytes.
+const ATTEMPT_ERROR_LIMIT: usize = 1000;
+
+/// Runs the step's program until it succeeds or has no retries left, then
+/// its fallback if it still failed. A cached output is used as is.
+async fn run_step(run: StepRun<'_>, policy: &StepPolicy, started: StartedStep) -> FinishedStep {
+    if let Some(stdout) = started.cached {
+        info!(
+            "Step {} of execution {}: reusing the cached output of {}",
+            run.step, run.execution_id, started.program.filename
+        );
+        return FinishedStep {
+            index: started.index,
+            filename: started.program.filename,
+            content_type: media_type::content_type(&started.program.output_type),
+            output: Ok(RunOutput {
+                exit_code: Some(0),
+                stdout,
+                stderr: Vec::new(),
+                timed_out: false,
+                cancelled: false,
+                output_exceeded: false,
+            }),
+            attempts: Vec::new(),
+            cache_key: None,
+            cached: true,
+        };
+    }
+
+    let mut attempts = Vec::new();
+    let mut number = 1;
+    let mut output = loop {
+        let (attempt, output) = run.attempt(&started.program, number).await;
+        let failed = attempt.status == "failed";
+        let error = attempt.error.clone();
+        attempts.push(attempt);
+        if !failed || number > policy.max_retries {
+            break output;
+        }
+
+        let delay = policy::retry_delay(policy, number);
+        number += 1;
+        warn!(
+            "Step {} of execution {} failed, retrying in {:?}",
+            run.step, run.execution_id, delay
+        );
+        run.events.publish(
+            &run.execution_id,
+            events::STEP_RETRYING,
+            json!({
+                "step": run.step,
+                "attempt": number,
+                "program_id": started.program.program_id,
+                "delay_ms": delay.as_millis() as u64,
+                "error": error,
+            }),
+        );
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = run.token.cancelled() => break Ok(RunOutput::cancelled()),
+        }
+    };
+
+    let mut filename = started.program.filename;
+    let mut output_type = started.program.output_type;
+    let mut cache_key = started.cache_key;
+    let failed = !run.token.is_cancelled()
+        && attempts
+            .last()
+            .is_some_and(|attempt| attempt.status == "failed");
+    if let Some(fallback) = started.fallback.filter(|_| failed) {
+        number += 1;
+        warn!(
+            "Step {} of execution {} failed, running fallback {}",
+            run.step, run.execution_id, fallback.filename
+        );
+        run.events.publish(
+            &run.execution_id,
+            events::STEP_RETRYING,
+            json!({
+                "step": run.step,
+                "attempt": number,
+                "program_id": fallback.program_id,
+                "delay_ms": 0,
+                "error": attempts.last().and_then(|attempt| attempt.error.clone()),
+            }),
+        );
+        let (attempt, fallback_output) = run.attempt(&fallback, number).await;
+        attempts.push(attempt);
+        output = fallback_output;
+        filename = fallback.filename;
+        output_type = fallback.output_type;
+        // The output is not the step program's.
+        cache_key = None;
+    }
+
+    FinishedStep {
+        index: started.index,
+        filename,
+        content_type: media_type::content_type(&output_type),
+        output,
+        attempts,
+        cache_key,
+        cached: false,
+    }
+}
+
+/// Exit code or timeout, and what the program wrote on stderr.
+fn failure_reason(output: &RunOutput) -> String {
+    let reason = match output.exit_code {
+        Some(code) => format!("exit code {}", code),
+        None if output.timed_out => "timeout".to_string(),
+        None if output.output_exceeded => "output limit".to_string(),
+        None => "signal".to_string(),
+    };
+    format!(
+        "{}: {}",
+        reason,
+        String::from_utf8_lossy(&output.stderr).trim()
+    )
+}
+
+/// Longest output preview kept in an execution record, in bytes.
+const PREVIEW_LIMIT: usize = 4096;
+
+/// The start of `output` as text, `None` when it is not text.
+fn preview(output: &[u8]) -> Option<String> {
+    let head = &output[..output.len().min(PREVIEW_LIMIT)];
+    match std::str::from_utf8(head) {
+        Ok(text) => Some(text.to_string()),
+        // Only the last character was cut by the limit.
+        Err(e) if e.error_len().is_none() => {
+            Some(String::from_utf8_lossy(&head[..e.valid_up_to()]).into_owned())
+        }
+        Err(_) => None,
+    }
+}
+
+/// The last `limit` bytes of `text` or a bit less, on a character boundary.
+fn tail(text: &str, limit: usize) -> &str {
+    let mut start = text.len().saturating_sub(limit);
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    &text[start..]
+}
+
+/// Records how a step ended, caches its stdout if it succeeded and hands it
+/// back if it succeeded, or failed with `on_failure` set to `continue`.
+async fn finish_step(
+    db: &DatabaseConnection,
+    storage: &Storage,
+    events: &EventHub,
+    plan: &Plan,
+    record: &mut ExecutionRecord,
+    finished: FinishedStep,
+) -> Result<(usize, Vec<u8>)> {
+    let keep_going = plan.policies[finished.index].on_failure == "continue";
+    if finished.cached {
+        record.cache_hits += 1;
+    }
+    let step = &mut record.steps[finished.index];
+    step.end_time = Some(Utc::now());
+    step.attempts = finished.attempts;
+    step.cached = finished.cached;
+    let output = match finished.output {
+        Ok(output) => output,
+        Err(e) => {
+            step.status = "failed".to_string();
+            events.publish(
+                &record.id,
+                events::STEP_FINISHED,
+                json!({"step": step.step, "status": "failed", "exit_code": null}),
+            );
+            if keep_going {
+                warn!("Step {} failed, continuing: {}", step.step, e);
+                return Ok((finished.index, Vec::new()));
+            }
+            return Err(e);
+        }
+    };
+    step.exit_code = output.exit_code;
+    step.stdout_size = output.stdout.len() as i64;
+    step.stderr_size = output.stderr.len() as i64;
+    step.status = if output.success() {
+        "success"
+    } else if output.cancelled {
+        "cancelled"
+    } else {
+        "failed"
+    }
+    .to_string();
+    store_step_output(storage, &record.id, step, &output, &finished.content_type).await;
+    events.publish(
+        &record.id,
+        events::STEP_FINISHED,
+        json!({
+            "step": step.step,
+            "status": step.status,
+            "exit_code": step.exit_code,
+            "cached": step.cached,
+        }),
+    );
+
+    if output.cancelled {
+        return Err(Error::msg("Cancelled"));
+    }
+    if !output.success() {
+        let error = format!(
+            "Step {} ({}) failed with {}",
+            step.step,
+            finished.filename,
+            failure_reason(&output)
+        );
+        if keep_going {
+            warn!("{}, continuing", error);
+            return Ok((finished.index, output.stdout));
+        }
+        return Err(Error::msg(error));
+    }
+    if let (Some(key), Some(ttl)) = (&finished.cache_key, plan.cache_ttl) {
+        result_cache::save(db, storage, key, &step.program_id, &output.stdout, ttl).await;
+    }
+    Ok((finished.index, output.stdout))
+}
+
+/// Keeps the stdout of a step in the blob store as `content_type`, and a
+/// preview of it in the record. A failure here only loses the step's output
+/// blob, not the execution.
+async fn store_step_output(
+    storage: &Storage,
+    execution_id: &ObjectId,
+    step: &mut StepRecord,
+    output: &RunOutput,
+    content_type: &str,
+) {
+    step.preview = preview(&output.stdout);
+    let key = step_output_key(execution_id, step.step);
+    if let Err(e) = storage.put(&key, content_type, output.stdout.clone()).await {
+        warn!("Could not store output of step {}: {}", step.step, e);
+        return;
+    }
+    step.content_type = Some(content_type.to_string());
+    // Served by the API whatever the backend, so the link does not expire.
+    step.output_url = Some(format!(
+        "/v1/executions/{}/steps/{}/output",
+        execution_id, step.step
+    ));
+    step.output_path = Some(key);
+}
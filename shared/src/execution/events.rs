@@ -0,0 +1,442 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use bson::oid::ObjectId;
+use chrono::Utc;
+use futures::stream::{self, Stream};
+use log::warn;
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, mpsc};
+
+use super::engine;
+use crate::database::db_interface::DatabaseConnection;
+use crate::database::execution_event_repository::{ExecutionEventRepository, StoredEvent};
+use crate::database::execution_repository::ExecutionRepository;
+
+pub const STEP_STARTED: &str = "step-started";
+pub const STDOUT: &str = "stdout";
+pub const STEP_RETRYING: &str = "step-retrying";
+pub const STEP_FINISHED: &str = "step-finished";
+pub const EXECUTION_FINISHED: &str = "execution-finished";
+const EVENTS: [&str; 5] = [
+    STEP_STARTED,
+    STDOUT,
+    STEP_RETRYING,
+    STEP_FINISHED,
+    EXECUTION_FINISHED,
+];
+
+/// Events kept in memory per execution for the clients of this server;
+/// older ones are read back from the database.
+const REPLAY_LIMIT: usize = 10_000;
+const CHANNEL_CAPACITY: usize = 1024;
+/// How long the events of a finished execution stay in memory.
+const RETENTION: Duration = Duration::from_secs(10 * 60);
+/// Most events stored, or read back, at once.
+const BATCH_SIZE: usize = 500;
+/// How often the database is checked for the events of an execution running
+/// on another server.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How many times to wait for events still on their way to the database.
+const CATCH_UP_POLLS: u32 = 20;
+
+/// Something that happened during an execution. `id` grows by one per event
+/// of the same execution, starting at 1.
+#[derive(Debug, Clone)]
+pub struct ExecutionEvent {
+    pub id: u64,
+    pub event: &'static str,
+    pub data: Value,
+}
+
+impl ExecutionEvent {
+    fn stored(&self, execution_id: ObjectId) -> StoredEvent {
+        StoredEvent {
+            execution_id,
+            id: self.id as i64,
+            event: self.event.to_string(),
+            data: self.data.clone(),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// `None` for events this version does not know.
+    fn from_stored(stored: StoredEvent) -> Option<Self> {
+        let event = EVENTS.into_iter().find(|event| *event == stored.event)?;
+        Some(ExecutionEvent {
+            id: stored.id as u64,
+            event,
+            data: stored.data,
+        })
+    }
+}
+
+/// The events already sent for an execution, and the live ones unless the
+/// execution is finished.
+pub struct Subscription {
+    pub replay: Vec<ExecutionEvent>,
+    pub live: Option<broadcast::Receiver<ExecutionEvent>>,
+}
+
+struct EventLog {
+    events: VecDeque<ExecutionEvent>,
+    next_id: u64,
+    sender: broadcast::Sender<ExecutionEvent>,
+    /// Feeds `store_events` until the execution is finished.
+    store: Option<mpsc::UnboundedSender<StoredEvent>>,
+    finished: bool,
+}
+
+/// Fan-out of the events of the executions running in this process, which
+/// also stores them so that a client can follow an execution through any
+/// server.
+#[derive(Clone)]
+pub struct EventHub {
+    logs: Arc<Mutex<HashMap<ObjectId, EventLog>>>,
+    db: DatabaseConnection,
+}
+
+impl EventHub {
+    pub fn new(db: DatabaseConnection) -> Self {
+        EventHub {
+            logs: Arc::default(),
+            db,
+        }
+    }
+
+    fn logs(&self) -> MutexGuard<'_, HashMap<ObjectId, EventLog>> {
+        // A panicking publisher cannot leave a log half-updated.
+        self.logs.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Starts collecting the events of `execution_id`.
+    pub fn open(&self, execution_id: ObjectId) {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let (store, queue) = mpsc::unbounded_channel();
+        tokio::spawn(store_events(self.db.clone(), queue));
+        self.logs().insert(
+            execution_id,
+            EventLog {
+                events: VecDeque::new(),
+                next_id: 1,
+                sender,
+                store: Some(store),
+                finished: false,
+            },
+        );
+    }
+
+    pub fn publish(&self, execution_id: &ObjectId, event: &'static str, data: Value) {
+        let mut logs = self.logs();
+        let Some(log) = logs.get_mut(execution_id) else {
+            return;
+        };
+        let event = ExecutionEvent {
+            id: log.next_id,
+            event,
+            data,
+        };
+        log.next_id += 1;
+        if let Some(store) = &log.store {
+            let _ = store.send(event.stored(*execution_id));
+        }
+        if log.events.len() == REPLAY_LIMIT {
+            log.events.pop_front();
+        }
+        log.events.push_back(event.clone());
+        // Nobody listening is fine.
+        let _ = log.sender.send(event);
+    }
+
+    /// Marks the execution as done; its events are forgotten after a while,
+    /// once they are stored.
+    pub fn finish(&self, execution_id: &ObjectId) {
+        if let Some(log) = self.logs().get_mut(execution_id) {
+            log.finished = true;
+            log.store = None;
+        }
+        let hub = self.clone();
+        let execution_id = *execution_id;
+        tokio::spawn(async move {
+            tokio::time::sleep(RETENTION).await;
+            hub.logs().remove(&execution_id);
+        });
+    }
+
+    /// The events after `last_event_id` and a receiver for the ones still to
+    /// come, or `None` if this process knows nothing about the execution.
+    pub fn subscribe(&self, execution_id: &ObjectId, last_event_id: u64) -> Option<Subscription> {
+        let logs = self.logs();
+        let log = logs.get(execution_id)?;
+        Some(Subscription {
+            replay: log
+                .events
+                .iter()
+                .filter(|event| event.id > last_event_id)
+                .cloned()
+                .collect(),
+            live: (!log.finished).then(|| log.sender.subscribe()),
+        })
+    }
+
+    /// The events of `execution_id` after `last_event_id`, up to the end of
+    /// the execution: from this process while it runs the execution and
+    /// keeps them, from the database otherwise. A finished execution whose
+    /// events are gone ends with an `execution-finished` event numbered 0.
+    pub fn follow(
+        &self,
+        execution_id: ObjectId,
+        last_event_id: u64,
+    ) -> impl Stream<Item = ExecutionEvent> + Send + 'static {
+        let follower = Follower {
+            hub: self.clone(),
+            execution_id,
+            last_id: last_event_id,
+            pending: VecDeque::new(),
+            source: Source::Start,
+        };
+        stream::unfold(follower, |mut follower| async move {
+            let event = follower.next().await?;
+            Some((event, follower))
+        })
+    }
+}
+
+/// Stores the events of an execution in the order they were published, so
+/// that the stored ones are always the first ones.
+async fn store_events(db: DatabaseConnection, mut queue: mpsc::UnboundedReceiver<StoredEvent>) {
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while queue.recv_many(&mut batch, BATCH_SIZE).await > 0 {
+        if let Err(e) = db.insert_execution_events(&batch).await {
+            warn!("Could not store {} execution events: {}", batch.len(), e);
+        }
+        batch.clear();
+    }
+}
+
+enum Source {
+    /// Where the events come from is not known yet.
+    Start,
+    Live(broadcast::Receiver<ExecutionEvent>),
+    Database,
+    Done,
+}
+
+/// State of `EventHub::follow`.
+struct Follower {
+    hub: EventHub,
+    execution_id: ObjectId,
+    last_id: u64,
+    pending: VecDeque<ExecutionEvent>,
+    source: Source,
+}
+
+impl Follower {
+    async fn next(&mut self) -> Option<ExecutionEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                self.last_id = self.last_id.max(event.id);
+                if event.event == EXECUTION_FINISHED {
+                    self.pending.clear();
+                    self.source = Source::Done;
+                }
+                return Some(event);
+            }
+            match &mut self.source {
+                Source::Start => self.attach().await,
+                Source::Live(receiver) => match receiver.recv().await {
+                    Ok(event) if event.id > self.last_id => self.pending.push_back(event),
+                    Ok(_) => {}
+                    // Missed some, or the log is gone: start over from the
+                    // last event sent.
+                    Err(_) => self.source = Source::Start,
+                },
+                Source::Database => self.poll().await,
+                Source::Done => return None,
+            }
+        }
+    }
+
+    /// Follows the events in memory if this process has them, the ones that
+    /// no longer fit coming from the database, or the stored ones.
+    async fn attach(&mut self) {
+        let Some(subscription) = self.hub.subscribe(&self.execution_id, self.last_id) else {
+            self.source = Source::Database;
+            return;
+        };
+        if let Some(first) = subscription.replay.first() {
+            if first.id > self.last_id.saturating_add(1) {
+                self.catch_up(first.id).await;
+                if let Source::Done = self.source {
+                    return;
+                }
+            }
+        }
+        self.pending.extend(subscription.replay);
+        self.source = match subscription.live {
+            Some(receiver) => Source::Live(receiver),
+            None => Source::Done,
+        };
+    }
+
+    /// Reads the stored events before the one numbered `until`, waiting a
+    /// little for the ones not stored yet.
+    async fn catch_up(&mut self, until: u64) {
+        let mut after = self.last_id;
+        let mut polls = 0;
+        while after.saturating_add(1) < until && polls < CATCH_UP_POLLS {
+            let events = match self.read(after).await {
+                Some(events) => events,
+                None => return,
+            };
+            if events.is_empty() {
+                polls += 1;
+                tokio::time::sleep(POLL_INTERVAL).await;
+                continue;
+            }
+            for event in events.into_iter().filter(|event| event.id < until) {
+                after = event.id;
+                self.pending.push_back(event);
+            }
+        }
+    }
+
+    /// Waits for the next stored events of an execution running elsewhere,
+    /// or for it to end.
+    async fn poll(&mut self) {
+        loop {
+            match self.read(self.last_id).await {
+                Some(events) if !events.is_empty() => {
+                    self.pending.extend(events);
+                    return;
+                }
+                Some(_) => {}
+                None => return,
+            }
+            let execution = match self.hub.db.find_execution(&self.execution_id).await {
+                Ok(execution) => execution,
+                Err(e) => {
+                    warn!("Could not read execution {}: {}", self.execution_id, e);
+                    self.source = Source::Done;
+                    return;
+                }
+            };
+            match execution {
+                Some(execution) if execution.status == "running" => {
+                    // A server that stopped will not send anything more.
+                    if !engine::owner_alive(&execution) {
+                        self.source = Source::Done;
+                        return;
+                    }
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+                Some(execution) => {
+                    // Events stored while the execution was being updated.
+                    match self.read(self.last_id).await {
+                        Some(events) if !events.is_empty() => self.pending.extend(events),
+                        Some(_) => self.pending.push_back(ExecutionEvent {
+                            id: 0,
+                            event: EXECUTION_FINISHED,
+                            data: json!({"status": execution.status, "output": execution.output}),
+                        }),
+                        None => {}
+                    }
+                    return;
+                }
+                None => {
+                    self.source = Source::Done;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Stored events after the one numbered `after`; `None`, ending the
+    /// stream, when the database fails.
+    async fn read(&mut self, after: u64) -> Option<Vec<ExecutionEvent>> {
+        match self
+            .hub
+            .db
+            .find_execution_events(&self.execution_id, after, BATCH_SIZE)
+            .await
+        {
+            Ok(events) => Some(
+                events
+                    .into_iter()
+                    .filter_map(ExecutionEvent::from_stored)
+                    .collect(),
+            ),
+            Err(e) => {
+                warn!(
+                    "Could not read the events of execution {}: {}",
+                    self.execution_id, e
+                );
+                self.source = Source::Done;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::mock_db::MockDb;
+    use futures::StreamExt;
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_subscribe_replays_after_last_event_id() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let hub = EventHub::new(DatabaseConnection::Mock(MockDb::default()));
+            let id = ObjectId::new();
+            assert!(hub.subscribe(&id, 0).is_none());
+
+            hub.open(id);
+            hub.publish(&id, STEP_STARTED, json!({"step": 1}));
+            hub.publish(&id, STDOUT, json!({"step": 1, "line": "hello"}));
+
+            let mut subscription = hub.subscribe(&id, 1).unwrap();
+            let replayed: Vec<u64> = subscription.replay.iter().map(|e| e.id).collect();
+            assert_eq!(replayed, vec![2]);
+
+            hub.publish(&id, EXECUTION_FINISHED, json!({"status": "success"}));
+            let live = subscription.live.as_mut().unwrap().recv().await.unwrap();
+            assert_eq!(live.id, 3);
+            assert_eq!(live.event, EXECUTION_FINISHED);
+
+            hub.finish(&id);
+            let finished = hub.subscribe(&id, 0).unwrap();
+            assert_eq!(finished.replay.len(), 3);
+            assert!(finished.live.is_none());
+        });
+    }
+
+    #[test]
+    fn test_follow_replays_stored_events_on_another_server() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let db = DatabaseConnection::Mock(MockDb::default());
+            let running = EventHub::new(db.clone());
+            let id = ObjectId::new();
+            running.open(id);
+            running.publish(&id, STEP_STARTED, json!({"step": 1}));
+            running.publish(&id, STDOUT, json!({"step": 1, "line": "hello"}));
+            running.publish(&id, EXECUTION_FINISHED, json!({"status": "success"}));
+            running.finish(&id);
+            while db.find_execution_events(&id, 0, 10).await.unwrap().len() < 3 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+
+            let other = EventHub::new(db);
+            assert!(other.subscribe(&id, 0).is_none());
+            let followed: Vec<ExecutionEvent> = other.follow(id, 1).collect().await;
+            let ids: Vec<u64> = followed.iter().map(|e| e.id).collect();
+            assert_eq!(ids, vec![2, 3]);
+            assert_eq!(followed[1].event, EXECUTION_FINISHED);
+        });
+    }
+}
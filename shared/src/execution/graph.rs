@@ -0,0 +1,209 @@
+use std::collections::BTreeSet;
+
+use anyhow::{Error, Result};
+
+use super::media_type;
+use crate::models::pipeline::{Pipeline, PipelineEdge, TypeMismatch};
+use crate::models::program::Program;
+
+/// The steps of a pipeline as a directed acyclic graph. Steps are indexed
+/// from 0 here, while edges and records number them from 1.
+#[derive(Debug)]
+pub struct PipelineGraph {
+    inputs: Vec<Vec<usize>>,
+    outputs: Vec<Vec<usize>>,
+}
+
+impl PipelineGraph {
+    /// Checks the edges of `pipeline`: they must connect existing steps,
+    /// appear once and not form a cycle.
+    pub fn of(pipeline: &Pipeline) -> Result<Self> {
+        Self::new(pipeline.steps.len(), pipeline.edges.as_deref())
+    }
+
+    /// Without `edges` the steps form a chain.
+    pub fn new(step_count: usize, edges: Option<&[PipelineEdge]>) -> Result<Self> {
+        let mut graph = PipelineGraph {
+            inputs: vec![Vec::new(); step_count],
+            outputs: vec![Vec::new(); step_count],
+        };
+        let Some(edges) = edges else {
+            for step in 1..step_count {
+                graph.inputs[step].push(step - 1);
+                graph.outputs[step - 1].push(step);
+            }
+            return Ok(graph);
+        };
+
+        let index = |step: i32| -> Result<usize> {
+            usize::try_from(step)
+                .ok()
+                .filter(|step| (1..=step_count).contains(step))
+                .map(|step| step - 1)
+                .ok_or_else(|| {
+                    Error::msg(format!(
+                        "Edge refers to step {}, the pipeline has {} steps",
+                        step, step_count
+                    ))
+                })
+        };
+        for edge in edges {
+            let (from, to) = (index(edge.from)?, index(edge.to)?);
+            if from == to {
+                return Err(Error::msg(format!("Step {} cannot feed itself", edge.from)));
+            }
+            if graph.outputs[from].contains(&to) {
+                return Err(Error::msg(format!(
+                    "Duplicate edge from step {} to step {}",
+                    edge.from, edge.to
+                )));
+            }
+            graph.inputs[to].push(from);
+            graph.outputs[from].push(to);
+        }
+        for inputs in graph.inputs.iter_mut() {
+            inputs.sort_unstable();
+        }
+
+        let order = graph.topological_order();
+        if order.len() < step_count {
+            let cycle: Vec<String> = (0..step_count)
+                .filter(|step| !order.contains(step))
+                .map(|step| (step + 1).to_string())
+                .collect();
+            return Err(Error::msg(format!(
+                "Pipeline steps {} form a cycle",
+                cycle.join(", ")
+            )));
+        }
+        Ok(graph)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Steps whose output makes up the input of `step`, in step order.
+    pub fn inputs(&self, step: usize) -> &[usize] {
+        &self.inputs[step]
+    }
+
+    /// Steps reading the output of `step`.
+    pub fn outputs(&self, step: usize) -> &[usize] {
+        &self.outputs[step]
+    }
+
+    /// Steps nobody reads from; together their output is the pipeline's.
+    pub fn sinks(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len()).filter(|step| self.outputs[*step].is_empty())
+    }
+
+    /// Edges along which the output type of a program is not accepted by
+    /// the next one, and, flagged `unknown`, those where a type cannot be
+    /// told. `programs` holds the program of every step, in order; edges
+    /// touching an unknown program are not checked.
+    pub fn type_mismatches(&self, programs: &[Option<Program>]) -> Vec<TypeMismatch> {
+        let mut mismatches = Vec::new();
+        for (to, inputs) in self.inputs.iter().enumerate() {
+            for from in inputs {
+                let (Some(source), Some(target)) = (&programs[*from], &programs[to]) else {
+                    continue;
+                };
+                let (output_type, input_type) = (&source.output_type, &target.input_type);
+                let compatible = media_type::compatible(output_type, input_type);
+                if compatible != Some(true) {
+                    mismatches.push(TypeMismatch {
+                        from_step: *from as i32 + 1,
+                        to_step: to as i32 + 1,
+                        output_type: output_type.clone(),
+                        input_type: input_type.clone(),
+                        unknown: compatible.is_none(),
+                    });
+                }
+            }
+        }
+        mismatches.sort_by_key(|mismatch| (mismatch.from_step, mismatch.to_step));
+        mismatches
+    }
+
+    /// How many steps have to finish before each step can start, along the
+    /// longest chain of inputs; steps of the same stage can run together.
+    pub fn stages(&self) -> Vec<usize> {
+        let mut stages = vec![0; self.len()];
+        for step in self.topological_order() {
+            stages[step] = self.inputs[step]
+                .iter()
+                .map(|input| stages[*input] + 1)
+                .max()
+                .unwrap_or(0);
+        }
+        stages
+    }
+
+    /// An order in which every step comes after its inputs, preferring
+    /// lower step numbers. Steps on a cycle are left out.
+    pub fn topological_order(&self) -> Vec<usize> {
+        let mut waiting: Vec<usize> = self.inputs.iter().map(Vec::len).collect();
+        let mut ready: BTreeSet<usize> =
+            (0..self.len()).filter(|step| waiting[*step] == 0).collect();
+        let mut order = Vec::with_capacity(self.len());
+        while let Some(step) = ready.pop_first() {
+            order.push(step);
+            for next in &self.outputs[step] {
+                waiting[*next] -= 1;
+                if waiting[*next] == 0 {
+                    ready.insert(*next);
+                }
+            }
+        }
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edges(pairs: &[(i32, i32)]) -> Vec<PipelineEdge> {
+        pairs
+            .iter()
+            .map(|(from, to)| PipelineEdge {
+                from: *from,
+                to: *to,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chain_without_edges() {
+        let graph = PipelineGraph::new(3, None).unwrap();
+        assert_eq!(graph.inputs(2), &[1]);
+        assert_eq!(graph.sinks().collect::<Vec<_>>(), vec![2]);
+        assert_eq!(graph.topological_order(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_fan_out_and_fan_in() {
+        let edges = edges(&[(1, 3), (1, 2), (2, 4), (3, 4)]);
+        let graph = PipelineGraph::new(4, Some(&edges)).unwrap();
+        assert_eq!(graph.inputs(3), &[1, 2]);
+        assert_eq!(graph.topological_order(), vec![0, 1, 2, 3]);
+        assert_eq!(graph.stages(), vec![0, 1, 1, 2]);
+        assert_eq!(graph.sinks().collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn test_rejects_invalid_edges() {
+        let cycle = edges(&[(1, 2), (2, 3), (3, 2)]);
+        let error = PipelineGraph::new(3, Some(&cycle)).unwrap_err();
+        assert_eq!(error.to_string(), "Pipeline steps 2, 3 form a cycle");
+
+        assert!(PipelineGraph::new(2, Some(&edges(&[(1, 3)]))).is_err());
+        assert!(PipelineGraph::new(2, Some(&edges(&[(1, 1)]))).is_err());
+        assert!(PipelineGraph::new(2, Some(&edges(&[(1, 2), (1, 2)]))).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+/// Media types of the file extensions programs declare as `output_type`.
+const EXTENSIONS: [(&str, &str); 14] = [
+    ("txt", "text/plain"),
+    ("csv", "text/csv"),
+    ("html", "text/html"),
+    ("md", "text/markdown"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("pdf", "application/pdf"),
+    ("bin", "application/octet-stream"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+];
+
+/// `type/subtype` for a media type or a file extension, without parameters,
+/// or `None` when it cannot be told.
+pub fn normalize(value: &str) -> Option<String> {
+    let value = value.split(';').next()?.trim().to_lowercase();
+    if value.is_empty() {
+        return None;
+    }
+    if value.contains('/') {
+        return Some(value);
+    }
+    let extension = value.trim_start_matches('.');
+    EXTENSIONS
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map(|(_, media_type)| media_type.to_string())
+}
+
+/// Media type to serve data of type `output_type` with, the generic binary
+/// type when it cannot be told or is a wildcard.
+pub fn content_type(output_type: &str) -> String {
+    normalize(output_type)
+        .filter(|media_type| !media_type.contains('*'))
+        .unwrap_or_else(|| "application/octet-stream".to_string())
+}
+
+/// The usual file extension of a media type, without the dot.
+pub fn extension(media_type: &str) -> Option<&'static str> {
+    let media_type = normalize(media_type)?;
+    EXTENSIONS
+        .iter()
+        .find(|(_, known)| *known == media_type)
+        .map(|(extension, _)| *extension)
+}
+
+/// Whether data of type `output` may be fed to a program accepting `input`,
+/// or `None` when either type cannot be told. Wildcards in `input` (`*/*`,
+/// `text/*`) accept more; `*/*` accepts even an unknown `output`.
+pub fn compatible(output: &str, input: &str) -> Option<bool> {
+    let input = normalize(input)?;
+    if input == "*/*" {
+        return Some(true);
+    }
+    let output = normalize(output)?;
+    let (output_type, output_subtype) = output.split_once('/').unwrap_or((&output, "*"));
+    let (input_type, input_subtype) = input.split_once('/').unwrap_or((&input, "*"));
+    Some(
+        (input_type == "*" || input_type == output_type)
+            && (input_subtype == "*" || input_subtype == output_subtype),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compatible() {
+        assert_eq!(compatible(".txt", "text/plain"), Some(true));
+        assert_eq!(compatible("text/csv; charset=utf-8", "text/*"), Some(true));
+        assert_eq!(compatible("application/json", "*/*"), Some(true));
+        assert_eq!(compatible(".unknown", "application/json"), None);
+        assert_eq!(compatible(".unknown", "*/*"), Some(true));
+        assert_eq!(compatible(".txt", ""), None);
+        assert_eq!(compatible(".json", "text/*"), Some(false));
+        assert_eq!(compatible("text/*", "text/plain"), Some(false));
+    }
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(content_type(".png"), "image/png");
+        assert_eq!(content_type("text/csv; charset=utf-8"), "text/csv");
+        assert_eq!(content_type("text/*"), "application/octet-stream");
+        assert_eq!(content_type(".unknown"), "application/octet-stream");
+        assert_eq!(extension("image/jpeg"), Some("jpg"));
+        assert_eq!(extension("application/x-unknown"), None);
+    }
+}
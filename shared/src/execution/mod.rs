@@ -0,0 +1,12 @@
+pub mod cancel;
+pub mod engine;
+pub mod events;
+pub mod graph;
+pub mod media_type;
+pub mod parameters;
+pub mod policy;
+pub mod result_cache;
+pub mod runner;
+pub mod runtime;
+pub mod sandbox;
+pub mod scheduler;
@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+use crate::models::pipeline::{Pipeline, PipelineParameter};
+
+const KINDS: [&str; 4] = ["string", "integer", "number", "boolean"];
+
+/// Checks that parameter names are usable as environment variables and
+/// unique, that their types are known and that defaults match them.
+pub fn check(parameters: &[PipelineParameter]) -> Result<()> {
+    let mut names = HashSet::new();
+    for parameter in parameters {
+        let name = &parameter.name;
+        let valid_name = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(anyhow!(
+                "Invalid parameter name {:?}: use letters, digits and underscores",
+                name
+            ));
+        }
+        if !names.insert(name.to_lowercase()) {
+            return Err(anyhow!("Parameter {} is declared twice", name));
+        }
+        if !KINDS.contains(&parameter.kind.as_str()) {
+            return Err(anyhow!(
+                "Parameter {} has unknown type {:?}, expected one of {}",
+                name,
+                parameter.kind,
+                KINDS.join(", ")
+            ));
+        }
+        if let Some(default) = &parameter.default {
+            to_argument(parameter, default)?;
+        }
+    }
+    Ok(())
+}
+
+/// Same as `check`, and since scheduled runs use the defaults, a scheduled
+/// pipeline needs one for every parameter.
+pub fn check_pipeline(pipeline: &Pipeline) -> Result<()> {
+    check(&pipeline.parameters)?;
+    if pipeline.schedule.is_some() {
+        if let Some(required) = pipeline.parameters.iter().find(|p| p.default.is_none()) {
+            return Err(anyhow!(
+                "Parameter {} needs a default for the pipeline to be scheduled",
+                required.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The value of every parameter for one run, as the steps will see it:
+/// `values` where given, the defaults otherwise.
+pub fn resolve(
+    parameters: &[PipelineParameter],
+    values: &Map<String, Value>,
+) -> Result<BTreeMap<String, String>> {
+    if let Some(unknown) = values
+        .keys()
+        .find(|name| !parameters.iter().any(|p| &p.name == *name))
+    {
+        return Err(anyhow!("Unknown parameter {}", unknown));
+    }
+
+    let mut resolved = BTreeMap::new();
+    for parameter in parameters {
+        let value = values
+            .get(&parameter.name)
+            .or(parameter.default.as_ref())
+            .ok_or_else(|| anyhow!("Missing value for parameter {}", parameter.name))?;
+        resolved.insert(parameter.name.clone(), to_argument(parameter, value)?);
+    }
+    Ok(resolved)
+}
+
+/// Name of the environment variable holding parameter `name`.
+pub fn env_name(name: &str) -> String {
+    format!("PARAM_{}", name.to_uppercase())
+}
+
+fn to_argument(parameter: &PipelineParameter, value: &Value) -> Result<String> {
+    let argument = match (parameter.kind.as_str(), value) {
+        ("string", Value::String(value)) => Some(value.clone()),
+        ("integer", Value::Number(value)) if value.is_i64() || value.is_u64() => {
+            Some(value.to_string())
+        }
+        ("number", Value::Number(value)) => Some(value.to_string()),
+        ("boolean", Value::Bool(value)) => Some(value.to_string()),
+        _ => None,
+    };
+    argument.ok_or_else(|| {
+        anyhow!(
+            "Parameter {} must be of type {}, got {}",
+            parameter.name,
+            parameter.kind,
+            value
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parameter(name: &str, kind: &str, default: Option<Value>) -> PipelineParameter {
+        PipelineParameter {
+            name: name.to_string(),
+            kind: kind.to_string(),
+            default,
+            description: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_checks_types_and_applies_defaults() {
+        let parameters = vec![
+            parameter("limit", "integer", Some(json!(10))),
+            parameter("label", "string", None),
+            parameter("verbose", "boolean", Some(json!(false))),
+        ];
+        check(&parameters).unwrap();
+
+        let values = json!({"label": "daily", "verbose": true});
+        let resolved = resolve(&parameters, values.as_object().unwrap()).unwrap();
+        assert_eq!(resolved["limit"], "10");
+        assert_eq!(resolved["label"], "daily");
+        assert_eq!(resolved["verbose"], "true");
+
+        assert!(resolve(&parameters, &Map::new()).is_err());
+        let wrong_type = json!({"label": "daily", "limit": 2.5});
+        assert!(resolve(&parameters, wrong_type.as_object().unwrap()).is_err());
+        let unknown = json!({"label": "daily", "other": 1});
+        assert!(resolve(&parameters, unknown.as_object().unwrap()).is_err());
+
+        assert!(check(&[parameter("bad-name", "string", None)]).is_err());
+        assert!(check(&[parameter("n", "integer", Some(json!("ten")))]).is_err());
+        assert!(check(&[parameter("n", "date", None)]).is_err());
+    }
+}
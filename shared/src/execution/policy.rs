@@ -0,0 +1,200 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use bson::oid::ObjectId;
+
+use super::runner::Limits;
+use crate::models::pipeline::{Pipeline, StepPolicy};
+
+const ON_FAILURE: [&str; 3] = ["abort", "continue", "fallback"];
+/// Keeps a broken program from holding an execution for hours.
+const MAX_RETRIES: u32 = 10;
+const MB: u64 = 1024 * 1024;
+
+/// Checks that every policy names an existing step, at most once, and that
+/// its settings make sense. Policies may lower the server's `limits` but not
+/// raise them. Whether fallback programs exist is left to the caller.
+pub fn check(pipeline: &Pipeline, limits: &Limits) -> Result<()> {
+    let step_count = pipeline.steps.len() as i32;
+    let mut steps = HashSet::new();
+    for policy in &pipeline.policies {
+        let step = policy.step;
+        if step < 1 || step > step_count {
+            return Err(anyhow!(
+                "Policy refers to step {}, the pipeline has {} steps",
+                step,
+                step_count
+            ));
+        }
+        if !steps.insert(step) {
+            return Err(anyhow!("Step {} has several policies", step));
+        }
+        if policy.max_retries > MAX_RETRIES {
+            return Err(anyhow!(
+                "Step {} asks for {} retries, at most {} are allowed",
+                step,
+                policy.max_retries,
+                MAX_RETRIES
+            ));
+        }
+        if policy.timeout_seconds == Some(0) || policy.memory_limit_mb == Some(0) {
+            return Err(anyhow!("Step {} has a limit of 0", step));
+        }
+        let max_timeout = limits.timeout.as_secs();
+        if policy
+            .timeout_seconds
+            .is_some_and(|seconds| seconds > max_timeout)
+        {
+            return Err(anyhow!(
+                "Step {} has a timeout above the server's {} seconds",
+                step,
+                max_timeout
+            ));
+        }
+        if let Some(mb) = policy.memory_limit_mb {
+            let max_memory = limits.memory_bytes.unwrap_or(u64::MAX);
+            let within = matches!(mb.checked_mul(MB), Some(bytes) if bytes <= max_memory);
+            if !within {
+                return Err(anyhow!(
+                    "Step {} has a memory limit above the server's {} MB",
+                    step,
+                    max_memory / MB
+                ));
+            }
+        }
+        if !ON_FAILURE.contains(&policy.on_failure.as_str()) {
+            return Err(anyhow!(
+                "Step {} has unknown on_failure {:?}, expected one of {}",
+                step,
+                policy.on_failure,
+                ON_FAILURE.join(", ")
+            ));
+        }
+        match (&policy.fallback, policy.on_failure == "fallback") {
+            (Some(fallback), true) => {
+                ObjectId::parse_str(fallback.trim()).map_err(|e| {
+                    anyhow!(
+                        "Invalid fallback program ID {} of step {}: {}",
+                        fallback,
+                        step,
+                        e
+                    )
+                })?;
+            }
+            (None, true) => return Err(anyhow!("Step {} has no fallback program", step)),
+            (Some(_), false) => {
+                return Err(anyhow!(
+                    "Step {} has a fallback program but on_failure is {}",
+                    step,
+                    policy.on_failure
+                ))
+            }
+            (None, false) => {}
+        }
+    }
+    Ok(())
+}
+
+/// The policy of every step, in step order.
+pub fn for_steps(pipeline: &Pipeline) -> Vec<StepPolicy> {
+    (1..=pipeline.steps.len() as i32)
+        .map(|step| {
+            pipeline
+                .policies
+                .iter()
+                .find(|policy| policy.step == step)
+                .cloned()
+                .unwrap_or_else(|| StepPolicy::new(step))
+        })
+        .collect()
+}
+
+/// Limits of each run of the step, `defaults` unless the policy sets lower
+/// ones.
+pub fn limits(policy: &StepPolicy, defaults: &Limits) -> Limits {
+    let memory_bytes = policy.memory_limit_mb.and_then(|mb| mb.checked_mul(MB));
+    Limits {
+        timeout: policy
+            .timeout_seconds
+            .map(Duration::from_secs)
+            .map_or(defaults.timeout, |timeout| timeout.min(defaults.timeout)),
+        memory_bytes: match (memory_bytes, defaults.memory_bytes) {
+            (Some(bytes), Some(max)) => Some(bytes.min(max)),
+            (bytes, max) => bytes.or(max),
+        },
+        ..*defaults
+    }
+}
+
+/// How long to wait before retry number `retry`, starting at 1.
+pub fn retry_delay(policy: &StepPolicy, retry: u32) -> Duration {
+    let factor = 1u64 << (retry.saturating_sub(1)).min(MAX_RETRIES);
+    Duration::from_millis(policy.retry_delay_ms.saturating_mul(factor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::pipeline::CreatePipeline;
+
+    fn pipeline(policies: Vec<StepPolicy>) -> Pipeline {
+        CreatePipeline {
+            owner_id: 1,
+            name: "pipeline".to_string(),
+            description: String::new(),
+            steps: vec![ObjectId::new().to_hex(), ObjectId::new().to_hex()],
+            edges: None,
+            schedule: None,
+            parameters: Vec::new(),
+            policies,
+            cache_results: true,
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_check_policies() {
+        let defaults = Limits {
+            memory_bytes: Some(512 * MB),
+            ..Limits::new(Duration::from_secs(60))
+        };
+        let check = |pipeline: &Pipeline| check(pipeline, &defaults);
+        let mut retried = StepPolicy::new(2);
+        retried.max_retries = 3;
+        let pipeline_ok = pipeline(vec![retried.clone()]);
+        check(&pipeline_ok).unwrap();
+        let policies = for_steps(&pipeline_ok);
+        assert_eq!(policies, vec![StepPolicy::new(1), retried.clone()]);
+        assert_eq!(retry_delay(&retried, 3), Duration::from_secs(4));
+
+        assert!(check(&pipeline(vec![StepPolicy::new(3)])).is_err());
+        assert!(check(&pipeline(vec![StepPolicy::new(1), StepPolicy::new(1)])).is_err());
+
+        let mut fallback = StepPolicy::new(1);
+        fallback.on_failure = "fallback".to_string();
+        assert!(check(&pipeline(vec![fallback.clone()])).is_err());
+        fallback.fallback = Some(ObjectId::new().to_hex());
+        check(&pipeline(vec![fallback.clone()])).unwrap();
+        fallback.on_failure = "continue".to_string();
+        assert!(check(&pipeline(vec![fallback])).is_err());
+
+        let mut limited = StepPolicy::new(1);
+        limited.timeout_seconds = Some(30);
+        limited.memory_limit_mb = Some(256);
+        check(&pipeline(vec![limited.clone()])).unwrap();
+        let step_limits = limits(&limited, &defaults);
+        assert_eq!(step_limits.timeout, Duration::from_secs(30));
+        assert_eq!(step_limits.memory_bytes, Some(256 * MB));
+        limited.timeout_seconds = Some(61);
+        assert!(check(&pipeline(vec![limited.clone()])).is_err());
+        limited.timeout_seconds = Some(30);
+        limited.memory_limit_mb = Some(u64::MAX);
+        assert!(check(&pipeline(vec![limited.clone()])).is_err());
+        // Stored before the server lowered its limits.
+        limited.timeout_seconds = Some(3600);
+        let step_limits = limits(&limited, &defaults);
+        assert_eq!(step_limits.timeout, Duration::from_secs(60));
+        assert_eq!(step_limits.memory_bytes, Some(512 * MB));
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::{debug, error, info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::database::{
+    db_interface::DatabaseConnection,
+    step_result_repository::{StepResult, StepResultRepository},
+};
+use crate::storage::blob_store::{BlobStore, Storage};
+
+const DEFAULT_RESULT_CACHE_TTL_SECS: u64 = 24 * 60 * 60;
+const SWEEP_TICK: Duration = Duration::from_secs(10 * 60);
+
+/// How long a step output can be reused, from `RESULT_CACHE_TTL` (seconds).
+/// `0` turns the cache off.
+pub fn cache_ttl() -> Duration {
+    let seconds = env::var("RESULT_CACHE_TTL")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_RESULT_CACHE_TTL_SECS);
+    Duration::from_secs(seconds)
+}
+
+/// Identifies a run by everything that decides its output: the program,
+/// by content hash and by extension since that picks the interpreter, the
+/// hash of its stdin and the parameters.
+pub fn key(
+    program_hash: &str,
+    filename: &str,
+    input: &[u8],
+    parameters: &BTreeMap<String, String>,
+) -> String {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase())
+        .unwrap_or_default();
+    let mut hasher = Sha256::new();
+    // Every field is length-prefixed so that no two runs hash the same text.
+    let mut field = |value: &[u8]| {
+        hasher.update((value.len() as u64).to_be_bytes());
+        hasher.update(value);
+    };
+    field(program_hash.as_bytes());
+    field(extension.as_bytes());
+    field(&Sha256::digest(input));
+    for (name, value) in parameters {
+        field(name.as_bytes());
+        field(value.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Content hash of a program stored without a `file_hash`.
+pub fn program_hash(code: &[u8]) -> String {
+    hex::encode(Sha256::digest(code))
+}
+
+/// Storage key of the output cached under `key`.
+pub fn result_output_key(key: &str) -> String {
+    format!("results/{}.out", key)
+}
+
+/// The stdout of an earlier run with the same `key`, if one is still
+/// cached. The cache is only a shortcut, so failing to read it is a miss.
+pub async fn lookup(db: &DatabaseConnection, storage: &Storage, key: &str) -> Option<Vec<u8>> {
+    let result = match db.find_step_result(key).await {
+        Ok(result) => result?,
+        Err(e) => {
+            warn!("Could not look up cached result {}: {}", key, e);
+            return None;
+        }
+    };
+    match storage.get(&result.output_path).await {
+        Ok(output) => {
+            debug!("Reusing cached result {}", key);
+            Some(output)
+        }
+        Err(e) => {
+            warn!("Could not read cached result {}: {}", key, e);
+            None
+        }
+    }
+}
+
+/// Keeps `output` for later runs with the same `key` until `ttl` from now.
+pub async fn save(
+    db: &DatabaseConnection,
+    storage: &Storage,
+    key: &str,
+    program_id: &str,
+    output: &[u8],
+    ttl: Duration,
+) {
+    let output_path = result_output_key(key);
+    if let Err(e) = storage
+        .put(&output_path, "application/octet-stream", output.to_vec())
+        .await
+    {
+        warn!("Could not store cached result {}: {}", key, e);
+        return;
+    }
+    let created_at = Utc::now();
+    let result = StepResult {
+        key: key.to_string(),
+        program_id: program_id.to_string(),
+        output_path,
+        output_size: output.len() as i64,
+        created_at,
+        expires_at: chrono::Duration::from_std(ttl)
+            .ok()
+            .and_then(|ttl| created_at.checked_add_signed(ttl))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC),
+    };
+    if let Err(e) = db.save_step_result(&result).await {
+        warn!("Could not record cached result {}: {}", key, e);
+    }
+}
+
+/// Deletes expired results and their outputs, forever.
+pub async fn run_result_sweep(db: DatabaseConnection, storage: Storage) {
+    loop {
+        if let Err(e) = sweep(&db, &storage).await {
+            error!("Could not sweep cached results: {:?}", e);
+        }
+        tokio::time::sleep(SWEEP_TICK).await;
+    }
+}
+
+/// Deletes the results expired by now along with their outputs, and returns
+/// how many there were.
+pub async fn sweep(db: &DatabaseConnection, storage: &Storage) -> anyhow::Result<usize> {
+    let mut swept = 0;
+    for result in db.find_expired_step_results(Utc::now()).await? {
+        // The output goes first: the record is how it is found. An output
+        // already gone is fine.
+        if let Err(e) = storage.delete(&result.output_path).await {
+            if storage.exists(&result.output_path).await.unwrap_or(true) {
+                warn!(
+                    "Could not delete cached output {}: {}",
+                    result.output_path, e
+                );
+                continue;
+            }
+        }
+        if db
+            .delete_step_result(&result.key, result.expires_at)
+            .await?
+        {
+            swept += 1;
+        }
+    }
+    if swept > 0 {
+        info!("Swept {} expired cached results", swept);
+    }
+    Ok(swept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::mock_db::MockDb;
+    use crate::storage::local::LocalStore;
+
+    #[test]
+    fn test_key_depends_on_everything_that_decides_the_output() {
+        let parameters = BTreeMap::from([("limit".to_string(), "10".to_string())]);
+        let base = key("abc", "main.py", b"input", &parameters);
+        assert_eq!(base, key("abc", "other.PY", b"input", &parameters));
+
+        assert_ne!(base, key("abd", "main.py", b"input", &parameters));
+        assert_ne!(base, key("abc", "main.sh", b"input", &parameters));
+        assert_ne!(base, key("abc", "main.py", b"input2", &parameters));
+        assert_ne!(base, key("abc", "main.py", b"input", &BTreeMap::new()));
+        let other = BTreeMap::from([("limit".to_string(), "1".to_string())]);
+        assert_ne!(base, key("abc", "main.py", b"input", &other));
+    }
+
+    #[test]
+    fn test_sweep_deletes_expired_outputs() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let root = tempfile::tempdir().unwrap();
+            let storage = Storage::Local(LocalStore::new(root.path()));
+            let db = DatabaseConnection::Mock(MockDb::default());
+
+            save(&db, &storage, "old", "p", b"old", Duration::ZERO).await;
+            save(&db, &storage, "new", "p", b"new", Duration::from_secs(60)).await;
+
+            assert_eq!(sweep(&db, &storage).await.unwrap(), 1);
+            assert!(!storage.exists(&result_output_key("old")).await.unwrap());
+            assert!(lookup(&db, &storage, "new").await.is_some());
+            assert_eq!(sweep(&db, &storage).await.unwrap(), 0);
+        });
+    }
+}
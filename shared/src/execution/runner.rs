@@ -0,0 +1,593 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::future::Future;
+use std::process::Stdio;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use log::{debug, info, warn};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+
+use super::parameters;
+use super::sandbox::Sandbox;
+
+const DEFAULT_STEP_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_OUTPUT_LIMIT: u64 = 64 * 1024 * 1024;
+const DEFAULT_CONCURRENCY: usize = 16;
+
+static SLOTS: OnceLock<Semaphore> = OnceLock::new();
+
+/// The programs this server may run at once, ad-hoc runs and pipeline steps
+/// together: `EXECUTION_CONCURRENCY`, 16 by default. Hold a permit for as
+/// long as `run` goes.
+pub fn slots() -> &'static Semaphore {
+    SLOTS.get_or_init(|| {
+        let permits = env_number("EXECUTION_CONCURRENCY")
+            .and_then(|permits| usize::try_from(permits).ok())
+            .filter(|&permits| permits > 0)
+            .unwrap_or(DEFAULT_CONCURRENCY);
+        Semaphore::new(permits.min(Semaphore::MAX_PERMITS))
+    })
+}
+
+/// What a finished program left behind.
+#[derive(Debug)]
+pub struct RunOutput {
+    /// `None` when the process was killed by a signal, timed out or was
+    /// cancelled.
+    pub exit_code: Option<i32>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub timed_out: bool,
+    pub cancelled: bool,
+    /// Killed for writing more than `Limits::output_bytes`.
+    pub output_exceeded: bool,
+}
+
+impl RunOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// A run stopped before it finished.
+    pub fn cancelled() -> Self {
+        RunOutput {
+            exit_code: None,
+            stdout: Vec::new(),
+            stderr: b"Cancelled".to_vec(),
+            timed_out: false,
+            cancelled: true,
+            output_exceeded: false,
+        }
+    }
+}
+
+/// How far a single run may go. The limits left to `None` do not apply.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Wall-clock time.
+    pub timeout: Duration,
+    pub cpu_seconds: Option<u64>,
+    /// Address space of the process.
+    pub memory_bytes: Option<u64>,
+    /// Size of stdout and of stderr, each, and of every file the program
+    /// writes.
+    pub output_bytes: Option<u64>,
+}
+
+impl Limits {
+    pub fn new(timeout: Duration) -> Self {
+        Limits {
+            timeout,
+            cpu_seconds: None,
+            memory_bytes: None,
+            output_bytes: None,
+        }
+    }
+
+    /// The limits of a step whose policy sets none: `EXECUTION_STEP_TIMEOUT`
+    /// (seconds, 60 by default), `EXECUTION_CPU_LIMIT` (seconds),
+    /// `EXECUTION_MEMORY_LIMIT_MB` and `EXECUTION_OUTPUT_LIMIT` (bytes,
+    /// 64 MiB by default).
+    pub fn from_env() -> Self {
+        Limits {
+            timeout: Duration::from_secs(
+                env_number("EXECUTION_STEP_TIMEOUT").unwrap_or(DEFAULT_STEP_TIMEOUT_SECS),
+            ),
+            cpu_seconds: env_number("EXECUTION_CPU_LIMIT"),
+            memory_bytes: env_number("EXECUTION_MEMORY_LIMIT_MB")
+                .and_then(|mb| mb.checked_mul(1024 * 1024)),
+            output_bytes: Some(
+                env_number("EXECUTION_OUTPUT_LIMIT").unwrap_or(DEFAULT_OUTPUT_LIMIT),
+            ),
+        }
+    }
+}
+
+fn env_number(name: &str) -> Option<u64> {
+    env::var(name).ok().and_then(|value| value.parse().ok())
+}
+
+/// Raised while reading a program's output once it is too large.
+#[derive(Debug)]
+struct OutputLimitExceeded;
+
+impl std::fmt::Display for OutputLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "output limit exceeded")
+    }
+}
+
+impl std::error::Error for OutputLimitExceeded {}
+
+/// Runs `code` in a throw-away working directory with `input` on its stdin,
+/// handing every line of its stdout to `on_line` as soon as it is written.
+/// The runtime comes from the sandbox's table, by the extension of
+/// `filename`, and the sandbox confines the process.
+///
+/// The process gets an empty environment apart from `PATH`, `HOME` and
+/// `TMPDIR` pointing at the working directory and one `PARAM_<NAME>`
+/// variable per parameter, which it also gets as `--<name>=<value>`
+/// arguments. It is killed once `limits.timeout` elapses, it writes more
+/// than `limits.output_bytes` or `cancelled` completes.
+#[allow(clippy::too_many_arguments)]
+pub async fn run(
+    sandbox: &Sandbox,
+    filename: &str,
+    code: &[u8],
+    input: &[u8],
+    parameters: &BTreeMap<String, String>,
+    limits: &Limits,
+    mut on_line: impl FnMut(&str) + Send,
+    cancelled: impl Future<Output = ()>,
+) -> Result<RunOutput> {
+    let runtime = sandbox
+        .runtimes
+        .runtime_for(filename)
+        .ok_or_else(|| Error::msg(format!("No runtime for {}", filename)))?;
+    let workdir = tempfile::tempdir()?;
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, extension)| format!(".{}", extension))
+        .unwrap_or_default();
+    let script = workdir.path().join(format!("main{}", extension));
+    tokio::fs::write(&script, code).await?;
+
+    let mut command = Command::new(&runtime.command[0]);
+    command.args(&runtime.command[1..]).arg(&script);
+    command
+        .current_dir(workdir.path())
+        .env_clear()
+        .env("PATH", env::var("PATH").unwrap_or_default())
+        .env("HOME", workdir.path())
+        .env("TMPDIR", workdir.path())
+        .envs(
+            parameters
+                .iter()
+                .map(|(name, value)| (parameters::env_name(name), value)),
+        )
+        .args(
+            parameters
+                .iter()
+                .map(|(name, value)| format!("--{}={}", name, value)),
+        )
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    own_process_group(&mut command);
+    sandbox.confine(&mut command, workdir.path(), limits)?;
+
+    let mut child = command
+        .spawn()
+        .map_err(|e| Error::msg(format!("Failed to start {}: {}", filename, e)))?;
+    let pid = child.id();
+    debug!("Started {} (pid {:?})", filename, pid);
+    // `kill_on_drop` only reaches the program itself; whatever it started
+    // goes when this is dropped, however `run` is left.
+    let _group = ProcessGroupGuard(pid);
+
+    // Feed stdin from its own task so that a program writing a lot before
+    // reading cannot dead-lock with us.
+    let mut stdin = child.stdin.take();
+    let input = input.to_vec();
+    let feeder = tokio::spawn(async move {
+        if let Some(stdin) = stdin.as_mut() {
+            if let Err(e) = stdin.write_all(&input).await {
+                // The program may exit without reading its input.
+                debug!("Could not write program input: {}", e);
+            }
+        }
+    });
+
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let output_limit = limits.output_bytes.unwrap_or(u64::MAX);
+    let outputs = async {
+        tokio::try_join!(
+            read_lines(stdout, &mut on_line, output_limit),
+            read_to_end(stderr, output_limit),
+            async { Ok(child.wait().await?) }
+        )
+    };
+    // Leaving early drops `child` and `_group`, which kill the processes.
+    let timeout = limits.timeout;
+    let (stdout, stderr, status) = tokio::select! {
+        outputs = tokio::time::timeout(timeout, outputs) => match outputs {
+            Ok(Err(e)) if e.is::<OutputLimitExceeded>() => {
+                warn!("{} exceeded the output limit", filename);
+                feeder.abort();
+                return Ok(RunOutput {
+                    exit_code: None,
+                    stdout: Vec::new(),
+                    stderr: format!("Wrote more than {} bytes", output_limit).into_bytes(),
+                    timed_out: false,
+                    cancelled: false,
+                    output_exceeded: true,
+                });
+            }
+            Ok(outputs) => outputs?,
+            Err(_) => {
+                warn!("{} timed out after {:?}", filename, timeout);
+                feeder.abort();
+                return Ok(RunOutput {
+                    exit_code: None,
+                    stdout: Vec::new(),
+                    stderr: format!("Timed out after {} seconds", timeout.as_secs())
+                        .into_bytes(),
+                    timed_out: true,
+                    cancelled: false,
+                    output_exceeded: false,
+                });
+            }
+        },
+        _ = cancelled => {
+            info!("{} cancelled", filename);
+            feeder.abort();
+            return Ok(RunOutput::cancelled());
+        }
+    };
+    let _ = feeder.await;
+
+    let mut stderr = stderr;
+    if cpu_limit_exceeded(&status) {
+        stderr.extend_from_slice(b"\nCPU time limit exceeded");
+    }
+    Ok(RunOutput {
+        exit_code: status.code(),
+        stdout,
+        stderr,
+        timed_out: false,
+        cancelled: false,
+        output_exceeded: false,
+    })
+}
+
+/// Collects everything `reader` produces, calling `on_line` for each line
+/// without its line ending. Fails with `OutputLimitExceeded` after `limit`
+/// bytes.
+async fn read_lines(
+    reader: Option<impl AsyncRead + Unpin>,
+    on_line: &mut impl FnMut(&str),
+    limit: u64,
+) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    let Some(reader) = reader else {
+        return Ok(output);
+    };
+    // One byte more than allowed tells a full output from a too large one.
+    let mut reader = BufReader::new(reader.take(limit.saturating_add(1)));
+    loop {
+        let start = output.len();
+        if reader.read_until(b'\n', &mut output).await? == 0 {
+            return Ok(output);
+        }
+        if output.len() as u64 > limit {
+            return Err(OutputLimitExceeded.into());
+        }
+        let line = String::from_utf8_lossy(&output[start..]);
+        on_line(line.trim_end_matches(['\n', '\r']));
+    }
+}
+
+async fn read_to_end(reader: Option<impl AsyncRead + Unpin>, limit: u64) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    if let Some(reader) = reader {
+        reader
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut output)
+            .await?;
+    }
+    if output.len() as u64 > limit {
+        return Err(OutputLimitExceeded.into());
+    }
+    Ok(output)
+}
+
+/// Whether the program was stopped by `Limits::cpu_seconds`.
+#[cfg(unix)]
+fn cpu_limit_exceeded(status: &std::process::ExitStatus) -> bool {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal() == Some(libc::SIGXCPU)
+}
+
+#[cfg(not(unix))]
+fn cpu_limit_exceeded(_status: &std::process::ExitStatus) -> bool {
+    false
+}
+
+/// Puts the program in a process group of its own, so that whatever it
+/// started can be killed along with it.
+#[cfg(unix)]
+fn own_process_group(command: &mut Command) {
+    command.process_group(0);
+}
+
+#[cfg(not(unix))]
+fn own_process_group(_command: &mut Command) {}
+
+/// Kills the program's process group when dropped.
+struct ProcessGroupGuard(Option<u32>);
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        kill_process_group(self.0);
+    }
+}
+
+#[cfg(unix)]
+fn kill_process_group(pid: Option<u32>) {
+    if let Some(pid) = pid {
+        // SAFETY: killpg only sends a signal; the group was created for the
+        // program by `own_process_group`.
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(_pid: Option<u32>) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::runtime::RuntimeTable;
+    use crate::execution::sandbox::Isolation;
+    use tokio::runtime::Runtime;
+
+    fn sandbox() -> Sandbox {
+        Sandbox::new(RuntimeTable::builtin(), Isolation::None)
+    }
+
+    #[test]
+    fn test_run_pipes_input_to_output() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut lines = Vec::new();
+            let output = run(
+                &sandbox(),
+                "upper.sh",
+                b"tr a-z A-Z",
+                b"hello\nworld",
+                &BTreeMap::new(),
+                &Limits::new(Duration::from_secs(10)),
+                |line| lines.push(line.to_string()),
+                std::future::pending(),
+            )
+            .await
+            .unwrap();
+            assert!(output.success());
+            assert_eq!(output.stdout, b"HELLO\nWORLD");
+            assert_eq!(lines, vec!["HELLO", "WORLD"]);
+        });
+    }
+
+    #[test]
+    fn test_run_passes_parameters() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let parameters = BTreeMap::from([("limit".to_string(), "10".to_string())]);
+            let output = run(
+                &sandbox(),
+                "params.sh",
+                b"echo \"$PARAM_LIMIT $1\"",
+                b"",
+                &parameters,
+                &Limits::new(Duration::from_secs(10)),
+                |_| {},
+                std::future::pending(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(output.stdout, b"10 --limit=10\n");
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_memory_limit() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let limits = Limits {
+                memory_bytes: Some(256 * 1024 * 1024),
+                ..Limits::new(Duration::from_secs(10))
+            };
+            let output = run(
+                &sandbox(),
+                "grow.py",
+                b"data = bytearray(1024 * 1024 * 1024)",
+                b"",
+                &BTreeMap::new(),
+                &limits,
+                |_| {},
+                std::future::pending(),
+            )
+            .await
+            .unwrap();
+            assert!(!output.success());
+            assert!(String::from_utf8_lossy(&output.stderr).contains("MemoryError"));
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_cpu_limit() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let limits = Limits {
+                cpu_seconds: Some(1),
+                ..Limits::new(Duration::from_secs(10))
+            };
+            let output = run(
+                &sandbox(),
+                "spin.sh",
+                b"while :; do :; done",
+                b"",
+                &BTreeMap::new(),
+                &limits,
+                |_| {},
+                std::future::pending(),
+            )
+            .await
+            .unwrap();
+            assert!(!output.success());
+            assert!(!output.timed_out);
+            assert!(String::from_utf8_lossy(&output.stderr).contains("CPU time limit exceeded"));
+        });
+    }
+
+    #[test]
+    fn test_run_output_limit() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let limits = Limits {
+                output_bytes: Some(1000),
+                ..Limits::new(Duration::from_secs(10))
+            };
+            let output = run(
+                &sandbox(),
+                "yes.sh",
+                b"yes",
+                b"",
+                &BTreeMap::new(),
+                &limits,
+                |_| {},
+                std::future::pending(),
+            )
+            .await
+            .unwrap();
+            assert!(output.output_exceeded);
+            assert!(!output.success());
+        });
+    }
+
+    #[test]
+    fn test_run_times_out() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let output = run(
+                &sandbox(),
+                "sleep.sh",
+                b"sleep 5",
+                b"",
+                &BTreeMap::new(),
+                &Limits::new(Duration::from_millis(200)),
+                |_| {},
+                std::future::pending(),
+            )
+            .await
+            .unwrap();
+            assert!(output.timed_out);
+            assert!(!output.success());
+        });
+    }
+
+    #[test]
+    fn test_run_cancelled() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let output = run(
+                &sandbox(),
+                "sleep.sh",
+                b"sleep 5",
+                b"",
+                &BTreeMap::new(),
+                &Limits::new(Duration::from_secs(10)),
+                |_| {},
+                tokio::time::sleep(Duration::from_millis(200)),
+            )
+            .await
+            .unwrap();
+            assert!(output.cancelled);
+            assert!(!output.timed_out);
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_dropped_kills_started_processes() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let child = std::sync::Mutex::new(String::new());
+            let sandbox = sandbox();
+            let limits = Limits::new(Duration::from_secs(10));
+            let parameters = BTreeMap::new();
+            let running = run(
+                &sandbox,
+                "spawn.sh",
+                b"sleep 30 &\necho $!\nwait",
+                b"",
+                &parameters,
+                &limits,
+                |line| *child.lock().unwrap() = line.to_string(),
+                std::future::pending(),
+            );
+            let running = tokio::time::timeout(Duration::from_millis(500), running);
+            assert!(running.await.is_err());
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let pid = child.lock().unwrap().clone();
+            assert!(!pid.is_empty());
+            // Gone, or dead and waiting for whoever inherited it to reap it.
+            let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+            let state = stat.rsplit_once(") ").map(|(_, rest)| &rest[..1]);
+            assert!(matches!(state, None | Some("Z")), "{}", stat);
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_in_namespaces_cannot_write_to_the_host() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            if !crate::execution::sandbox::namespaces_available().await {
+                return;
+            }
+            let sandbox = Sandbox::new(RuntimeTable::builtin(), Isolation::Namespaces);
+            // Each line writes back what is there, should it get through.
+            let script = "touch /dev/shm/sandbox-test 2>/dev/null || echo shm\n\
+                (mkdir /sys/fs/cgroup/sandbox-test && rmdir /sys/fs/cgroup/sandbox-test) 2>/dev/null || echo sys\n\
+                cat /proc/sys/kernel/domainname > /proc/sys/kernel/domainname 2>/dev/null || echo proc-sys";
+            let output = run(
+                &sandbox,
+                "escape.sh",
+                script.as_bytes(),
+                b"",
+                &BTreeMap::new(),
+                &Limits::new(Duration::from_secs(10)),
+                |_| {},
+                std::future::pending(),
+            )
+            .await
+            .unwrap();
+            let _ = std::fs::remove_file("/dev/shm/sandbox-test");
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let lines: Vec<&str> = stdout.lines().collect();
+            assert_eq!(lines, vec!["shm", "sys", "proc-sys"]);
+        });
+    }
+}
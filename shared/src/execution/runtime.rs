@@ -0,0 +1,122 @@
+use std::env;
+
+use anyhow::{anyhow, Result};
+
+/// Runtimes available without configuration.
+const BUILTIN: [(&str, &str); 5] = [
+    ("py", "python3"),
+    ("js", "node"),
+    ("lua", "lua"),
+    ("sh", "sh"),
+    ("rb", "ruby"),
+];
+
+/// The command programs with a given file extension run with; the program
+/// file is its last argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Runtime {
+    /// Lowercase, without the dot.
+    pub extension: String,
+    pub command: Vec<String>,
+}
+
+/// Which runtime runs which program. Files whose extension is not in the
+/// table are not run at all.
+#[derive(Debug, Clone)]
+pub struct RuntimeTable {
+    runtimes: Vec<Runtime>,
+}
+
+impl RuntimeTable {
+    pub fn builtin() -> Self {
+        let runtimes = BUILTIN
+            .iter()
+            .map(|(extension, interpreter)| Runtime {
+                extension: extension.to_string(),
+                command: vec![interpreter.to_string()],
+            })
+            .collect();
+        RuntimeTable { runtimes }
+    }
+
+    /// The built-in runtimes, with the ones listed in `EXECUTION_RUNTIMES`
+    /// added or replacing them, see `parse`.
+    pub fn from_env() -> Result<Self> {
+        let mut table = RuntimeTable::builtin();
+        if let Ok(spec) = env::var("EXECUTION_RUNTIMES") {
+            for runtime in parse(&spec)? {
+                table = table.with(runtime);
+            }
+        }
+        Ok(table)
+    }
+
+    /// Adds `runtime`, replacing the runtime of the same extension if any.
+    pub fn with(mut self, runtime: Runtime) -> Self {
+        self.runtimes
+            .retain(|known| known.extension != runtime.extension);
+        self.runtimes.push(runtime);
+        self
+    }
+
+    pub fn runtime_for(&self, filename: &str) -> Option<&Runtime> {
+        let (_, extension) = filename.rsplit_once('.')?;
+        let extension = extension.to_lowercase();
+        self.runtimes
+            .iter()
+            .find(|runtime| runtime.extension == extension)
+    }
+
+    pub fn runtimes(&self) -> &[Runtime] {
+        &self.runtimes
+    }
+}
+
+/// Reads runtimes written as `extension=command`, separated by `;`, such as
+/// `py=python3 -I;ts=deno run`.
+pub fn parse(spec: &str) -> Result<Vec<Runtime>> {
+    spec.split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (extension, command) = entry.split_once('=').ok_or_else(|| {
+                anyhow!("Invalid runtime {:?}, expected extension=command", entry)
+            })?;
+            let extension = extension.trim().trim_start_matches('.').to_lowercase();
+            let command: Vec<String> = command.split_whitespace().map(String::from).collect();
+            if extension.is_empty() || command.is_empty() {
+                return Err(anyhow!(
+                    "Invalid runtime {:?}, expected extension=command",
+                    entry
+                ));
+            }
+            Ok(Runtime { extension, command })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_configured_runtimes_replace_builtin_ones() {
+        let mut table = RuntimeTable::builtin();
+        for runtime in parse("py=python3 -I; .TS=deno run").unwrap() {
+            table = table.with(runtime);
+        }
+        assert_eq!(
+            table.runtime_for("main.PY").unwrap().command,
+            vec!["python3", "-I"]
+        );
+        assert_eq!(
+            table.runtime_for("main.ts").unwrap().command,
+            vec!["deno", "run"]
+        );
+        assert_eq!(table.runtime_for("main.sh").unwrap().command, vec!["sh"]);
+        assert!(table.runtime_for("main").is_none());
+
+        assert!(parse("py").is_err());
+        assert!(parse("py=").is_err());
+    }
+}
@@ -0,0 +1,667 @@
+use std::env;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use tokio::process::Command;
+use tokio::sync::OnceCell;
+
+use super::runner::Limits;
+use super::runtime::RuntimeTable;
+
+/// How far programs are kept from the host on top of their resource
+/// limits, throw-away working directory and empty environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Isolation {
+    None,
+    /// No network, read-only file systems, with the working directory as
+    /// the only writable place, and no view of other processes, through new
+    /// Linux network, mount and PID namespaces. Programs get no
+    /// capabilities, and run as `nobody` when the server runs as root. A
+    /// seccomp filter denies the system calls that could undo this or reach
+    /// into the kernel, such as `mount`, `ptrace` or `bpf`.
+    Namespaces,
+}
+
+/// Where and how programs run.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    pub runtimes: RuntimeTable,
+    pub isolation: Isolation,
+}
+
+/// The shared sandbox, or why there is none.
+static SHARED: OnceCell<Result<Sandbox, String>> = OnceCell::const_new();
+
+impl Sandbox {
+    pub fn new(runtimes: RuntimeTable, isolation: Isolation) -> Self {
+        Sandbox {
+            runtimes,
+            isolation,
+        }
+    }
+
+    /// The sandbox configured by `EXECUTION_RUNTIMES`, see
+    /// `RuntimeTable::from_env`, and `EXECUTION_SANDBOX`: `namespaces`
+    /// (`auto`, the default, is the same) or `none`. Fails when the host
+    /// does not allow namespaces, unless `none` was asked for. This is
+    /// settled once, so that such a host is not probed on every run.
+    pub async fn shared() -> Result<&'static Sandbox> {
+        SHARED
+            .get_or_init(|| async { Sandbox::from_env().await.map_err(|e| e.to_string()) })
+            .await
+            .as_ref()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    async fn from_env() -> Result<Self> {
+        let runtimes = RuntimeTable::from_env()?;
+        let isolation = match env::var("EXECUTION_SANDBOX").unwrap_or_default().as_str() {
+            "" | "auto" | "namespaces" if namespaces_available().await => Isolation::Namespaces,
+            "" | "auto" | "namespaces" => {
+                return Err(anyhow!(
+                    "Namespaces are not available on this host; set EXECUTION_SANDBOX=none to run programs without isolation"
+                ))
+            }
+            "none" => {
+                warn!("Programs can reach the network and write outside their working directory");
+                Isolation::None
+            }
+            other => {
+                return Err(anyhow!(
+                    "Unknown EXECUTION_SANDBOX {:?}, expected auto, namespaces or none",
+                    other
+                ))
+            }
+        };
+        info!("Programs run with isolation {:?}", isolation);
+        Ok(Sandbox::new(runtimes, isolation))
+    }
+
+    /// Makes the process `command` starts respect `limits` and, as far as
+    /// `isolation` goes, only write to `workdir`.
+    #[cfg(unix)]
+    pub fn confine(&self, command: &mut Command, workdir: &Path, limits: &Limits) -> Result<()> {
+        let rlimits = unix::Rlimits::of(limits);
+        let namespaces = match self.isolation {
+            Isolation::Namespaces => Some(unix::Namespaces::prepare(workdir)?),
+            Isolation::None => None,
+        };
+        // SAFETY: the closure only makes system calls, which are
+        // async-signal-safe, between fork and exec; everything it needs
+        // was allocated before.
+        unsafe {
+            command.pre_exec(move || {
+                rlimits.apply()?;
+                if let Some(namespaces) = &namespaces {
+                    namespaces.enter()?;
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn confine(&self, _command: &mut Command, _workdir: &Path, limits: &Limits) -> Result<()> {
+        if self.isolation == Isolation::Namespaces {
+            return Err(anyhow!("Namespaces are not supported on this platform"));
+        }
+        if limits.cpu_seconds.is_some() || limits.memory_bytes.is_some() {
+            warn!("CPU and memory limits are not supported on this platform");
+        }
+        Ok(())
+    }
+}
+
+/// Whether processes can be started in new namespaces on this host; they
+/// are usually not allowed in containers.
+pub async fn namespaces_available() -> bool {
+    let Ok(workdir) = tempfile::tempdir() else {
+        return false;
+    };
+    let sandbox = Sandbox::new(RuntimeTable::builtin(), Isolation::Namespaces);
+    let mut command = Command::new("true");
+    if sandbox
+        .confine(
+            &mut command,
+            workdir.path(),
+            &Limits::new(Duration::from_secs(10)),
+        )
+        .is_err()
+    {
+        return false;
+    }
+    command.status().await.is_ok_and(|status| status.success())
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::io;
+
+    use super::Limits;
+
+    fn check(result: libc::c_int) -> io::Result<()> {
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn rlimit(value: u64) -> libc::rlimit {
+        libc::rlimit {
+            rlim_cur: value as libc::rlim_t,
+            rlim_max: value as libc::rlim_t,
+        }
+    }
+
+    pub(super) struct Rlimits {
+        cpu_seconds: Option<u64>,
+        memory_bytes: Option<u64>,
+        file_bytes: Option<u64>,
+    }
+
+    impl Rlimits {
+        pub(super) fn of(limits: &Limits) -> Self {
+            Rlimits {
+                cpu_seconds: limits.cpu_seconds,
+                memory_bytes: limits.memory_bytes,
+                // Files the program writes may not outgrow its output.
+                file_bytes: limits.output_bytes,
+            }
+        }
+
+        pub(super) unsafe fn apply(&self) -> io::Result<()> {
+            check(libc::setrlimit(libc::RLIMIT_CORE, &rlimit(0)))?;
+            if let Some(seconds) = self.cpu_seconds {
+                // SIGXCPU at the soft limit, SIGKILL a second later.
+                let limit = libc::rlimit {
+                    rlim_cur: seconds as libc::rlim_t,
+                    rlim_max: seconds.saturating_add(1) as libc::rlim_t,
+                };
+                check(libc::setrlimit(libc::RLIMIT_CPU, &limit))?;
+            }
+            if let Some(bytes) = self.memory_bytes {
+                check(libc::setrlimit(libc::RLIMIT_AS, &rlimit(bytes)))?;
+            }
+            if let Some(bytes) = self.file_bytes {
+                check(libc::setrlimit(libc::RLIMIT_FSIZE, &rlimit(bytes)))?;
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(super) use linux::Namespaces;
+
+    #[cfg(not(target_os = "linux"))]
+    pub(super) struct Namespaces;
+
+    #[cfg(not(target_os = "linux"))]
+    impl Namespaces {
+        pub(super) fn prepare(_workdir: &std::path::Path) -> anyhow::Result<Self> {
+            Err(anyhow::anyhow!(
+                "Namespaces are not supported on this platform"
+            ))
+        }
+
+        pub(super) unsafe fn enter(&self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    mod linux {
+        use std::ffi::{CStr, CString};
+        use std::io;
+        use std::os::unix::ffi::OsStrExt;
+        use std::path::Path;
+        use std::ptr;
+
+        use super::check;
+
+        /// Seccomp filters check the architecture of the system call, since
+        /// the numbers depend on it.
+        #[cfg(target_arch = "x86_64")]
+        const AUDIT_ARCH: u32 = 0xc000_003e;
+        #[cfg(target_arch = "aarch64")]
+        const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+        /// Who programs run as when the server runs as root: `nobody`.
+        const UNPRIVILEGED_ID: u32 = 65534;
+
+        /// System calls a program gets `EPERM` for.
+        const DENIED_SYSCALLS: [libc::c_long; 26] = [
+            libc::SYS_mount,
+            libc::SYS_umount2,
+            libc::SYS_pivot_root,
+            libc::SYS_chroot,
+            libc::SYS_unshare,
+            libc::SYS_setns,
+            libc::SYS_open_tree,
+            libc::SYS_move_mount,
+            libc::SYS_fsopen,
+            libc::SYS_fsconfig,
+            libc::SYS_fsmount,
+            libc::SYS_fspick,
+            libc::SYS_mount_setattr,
+            libc::SYS_ptrace,
+            libc::SYS_process_vm_readv,
+            libc::SYS_process_vm_writev,
+            libc::SYS_bpf,
+            libc::SYS_perf_event_open,
+            libc::SYS_userfaultfd,
+            libc::SYS_keyctl,
+            libc::SYS_add_key,
+            libc::SYS_request_key,
+            libc::SYS_kexec_load,
+            libc::SYS_init_module,
+            libc::SYS_finit_module,
+            libc::SYS_open_by_handle_at,
+        ];
+
+        /// What entering the namespaces takes, worked out before the fork.
+        pub(in super::super) struct Namespaces {
+            workdir: CString,
+            /// `uid_map` and `gid_map` when not running as root, which
+            /// needs a user namespace to create the others. The program
+            /// keeps its user, so it still owns its working directory, and
+            /// loses the capabilities of the namespace when it starts.
+            user_maps: Option<(Vec<u8>, Vec<u8>)>,
+            /// Whether to give up root, which owns the host's files and can
+            /// write to `/proc` and `/sys`, for `UNPRIVILEGED_ID`.
+            drop_root: bool,
+            filter: Vec<libc::sock_filter>,
+        }
+
+        impl Namespaces {
+            pub(in super::super) fn prepare(workdir: &Path) -> anyhow::Result<Self> {
+                // SAFETY: both calls always succeed.
+                let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+                let drop_root = uid == 0;
+                if drop_root {
+                    std::os::unix::fs::chown(
+                        workdir,
+                        Some(UNPRIVILEGED_ID),
+                        Some(UNPRIVILEGED_ID),
+                    )?;
+                }
+                let workdir = CString::new(workdir.as_os_str().as_bytes())?;
+                let user_maps = (!drop_root).then(|| {
+                    (
+                        format!("{} {} 1", uid, uid).into_bytes(),
+                        format!("{} {} 1", gid, gid).into_bytes(),
+                    )
+                });
+                Ok(Namespaces {
+                    workdir,
+                    user_maps,
+                    drop_root,
+                    filter: seccomp_filter()?,
+                })
+            }
+
+            pub(in super::super) unsafe fn enter(&self) -> io::Result<()> {
+                let mut flags = libc::CLONE_NEWNS | libc::CLONE_NEWNET | libc::CLONE_NEWPID;
+                if self.user_maps.is_some() {
+                    flags |= libc::CLONE_NEWUSER;
+                }
+                check(libc::unshare(flags))?;
+                if let Some((uid_map, gid_map)) = &self.user_maps {
+                    write_file(c"/proc/self/setgroups", b"deny")?;
+                    write_file(c"/proc/self/uid_map", uid_map)?;
+                    write_file(c"/proc/self/gid_map", gid_map)?;
+                }
+
+                // Keep the mounts below from reaching the host.
+                check(libc::mount(
+                    ptr::null(),
+                    c"/".as_ptr(),
+                    ptr::null(),
+                    libc::MS_REC | libc::MS_PRIVATE,
+                    ptr::null(),
+                ))?;
+                // A mount of its own keeps the working directory writable
+                // once everything else is not, `/dev/shm`, `/sys` and the
+                // other mounts below the root included.
+                check(libc::mount(
+                    self.workdir.as_ptr(),
+                    self.workdir.as_ptr(),
+                    ptr::null(),
+                    libc::MS_BIND | libc::MS_REC,
+                    ptr::null(),
+                ))?;
+                set_read_only(c"/", true)?;
+                set_read_only(&self.workdir, false)?;
+                // The current directory was entered before the bind mount
+                // and still points below it.
+                check(libc::chdir(self.workdir.as_ptr()))?;
+
+                enter_pid_namespace()?;
+                drop_privileges(self.drop_root)?;
+
+                let program = libc::sock_fprog {
+                    len: self.filter.len() as libc::c_ushort,
+                    filter: self.filter.as_ptr() as *mut libc::sock_filter,
+                };
+                check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+                check(libc::prctl(
+                    libc::PR_SET_SECCOMP,
+                    libc::SECCOMP_MODE_FILTER,
+                    &program as *const libc::sock_fprog,
+                ))
+            }
+        }
+
+        /// Only children join a new PID namespace, and its first process
+        /// ignores the signals it has no handler for, such as the `SIGXCPU`
+        /// of the CPU limit. So the program runs two processes down: this
+        /// process stays behind as the one the runner knows, a child is the
+        /// first process of the namespace and mounts its `/proc`, and the
+        /// program is the grandchild. Both wait for it and this process
+        /// exits the way the program did.
+        unsafe fn enter_pid_namespace() -> io::Result<()> {
+            // How the program ended, from the first process to this one.
+            let mut status_pipe = [0; 2];
+            check(libc::pipe2(status_pipe.as_mut_ptr(), libc::O_CLOEXEC))?;
+            let [read_end, write_end] = status_pipe;
+            match libc::fork() {
+                -1 => return Err(io::Error::last_os_error()),
+                0 => libc::close(read_end),
+                child => {
+                    close_files_but(read_end);
+                    let mut status = wait_for(child);
+                    let mut program_status: libc::c_int = 0;
+                    let size = std::mem::size_of::<libc::c_int>();
+                    if libc::read(
+                        read_end,
+                        (&mut program_status as *mut libc::c_int).cast(),
+                        size,
+                    ) == size as isize
+                    {
+                        status = program_status;
+                    }
+                    exit_like(status)
+                }
+            };
+
+            // Dies with the process the runner kills, and takes the
+            // namespace down with it.
+            check(libc::prctl(
+                libc::PR_SET_PDEATHSIG,
+                libc::SIGKILL as libc::c_ulong,
+            ))?;
+            // A `/proc` of the new PID namespace, so that other processes,
+            // the server's environment among them, are out of sight.
+            check(libc::mount(
+                c"proc".as_ptr(),
+                c"/proc".as_ptr(),
+                c"proc".as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                ptr::null(),
+            ))?;
+            // Kernel settings stay out of reach.
+            check(libc::mount(
+                c"/proc/sys".as_ptr(),
+                c"/proc/sys".as_ptr(),
+                ptr::null(),
+                libc::MS_BIND | libc::MS_REC,
+                ptr::null(),
+            ))?;
+            set_read_only(c"/proc/sys", true)?;
+            match libc::fork() {
+                -1 => Err(io::Error::last_os_error()),
+                0 => {
+                    libc::close(write_end);
+                    Ok(())
+                }
+                program => {
+                    close_files_but(write_end);
+                    let status = wait_for(program);
+                    libc::write(
+                        write_end,
+                        (&status as *const libc::c_int).cast(),
+                        std::mem::size_of::<libc::c_int>(),
+                    );
+                    libc::_exit(0)
+                }
+            }
+        }
+
+        /// Makes the mount at `path` and every mount below it read-only, or
+        /// writable again.
+        unsafe fn set_read_only(path: &CStr, read_only: bool) -> io::Result<()> {
+            let (attr_set, attr_clr) = match read_only {
+                true => (libc::MOUNT_ATTR_RDONLY, 0),
+                false => (0, libc::MOUNT_ATTR_RDONLY),
+            };
+            let attr = libc::mount_attr {
+                attr_set,
+                attr_clr,
+                propagation: 0,
+                userns_fd: 0,
+            };
+            let result = libc::syscall(
+                libc::SYS_mount_setattr,
+                libc::AT_FDCWD,
+                path.as_ptr(),
+                libc::AT_RECURSIVE,
+                &attr as *const libc::mount_attr,
+                std::mem::size_of::<libc::mount_attr>(),
+            );
+            if result != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        /// Empties the capability bounding set, so that nothing the program
+        /// runs can get capabilities back, and gives up root if `drop_root`.
+        /// Changing from root to another user clears the capabilities.
+        unsafe fn drop_privileges(drop_root: bool) -> io::Result<()> {
+            // Fails past the last capability the kernel knows.
+            let mut capability = 0;
+            while libc::prctl(libc::PR_CAPBSET_DROP, capability, 0, 0, 0) == 0 {
+                capability += 1;
+            }
+            if capability == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if drop_root {
+                check(libc::setgroups(0, ptr::null()))?;
+                check(libc::setresgid(
+                    UNPRIVILEGED_ID,
+                    UNPRIVILEGED_ID,
+                    UNPRIVILEGED_ID,
+                ))?;
+                check(libc::setresuid(
+                    UNPRIVILEGED_ID,
+                    UNPRIVILEGED_ID,
+                    UNPRIVILEGED_ID,
+                ))?;
+            }
+            Ok(())
+        }
+
+        /// Closes every file but `fd`: nothing is left to read or write, and
+        /// the pipes have to close with the program, the one the spawning
+        /// process waits on to see it started included.
+        unsafe fn close_files_but(fd: libc::c_int) {
+            let fd = fd as libc::c_uint;
+            let closed = (fd == 0 || libc::syscall(libc::SYS_close_range, 0, fd - 1, 0) == 0)
+                && libc::syscall(libc::SYS_close_range, fd + 1, libc::c_uint::MAX, 0) == 0;
+            if !closed {
+                for other in (0..1024).filter(|other| *other != fd) {
+                    libc::close(other as libc::c_int);
+                }
+            }
+        }
+
+        unsafe fn wait_for(child: libc::pid_t) -> libc::c_int {
+            let mut status = 0;
+            while libc::waitpid(child, &mut status, 0) == -1 {
+                if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                    return 1 << 8;
+                }
+            }
+            status
+        }
+
+        /// Exits with the wait `status` of another process.
+        unsafe fn exit_like(status: libc::c_int) -> ! {
+            if libc::WIFSIGNALED(status) {
+                let signal = libc::WTERMSIG(status);
+                libc::signal(signal, libc::SIG_DFL);
+                libc::kill(libc::getpid(), signal);
+            }
+            libc::_exit(if libc::WIFEXITED(status) {
+                libc::WEXITSTATUS(status)
+            } else {
+                1
+            })
+        }
+
+        /// A classic BPF program failing `DENIED_SYSCALLS` with `EPERM` and
+        /// killing the program on system calls of another architecture.
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        fn seccomp_filter() -> anyhow::Result<Vec<libc::sock_filter>> {
+            // Offsets in `struct seccomp_data`.
+            const NR: u32 = 0;
+            const ARCH: u32 = 4;
+            let load = (libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16;
+            let jump_equal = (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16;
+            let ret = (libc::BPF_RET | libc::BPF_K) as u16;
+            let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+            let statement = |code, k| libc::sock_filter {
+                code,
+                jt: 0,
+                jf: 0,
+                k,
+            };
+            // Skips the next instruction unless equal.
+            let if_equal = |k| libc::sock_filter {
+                code: jump_equal,
+                jt: 0,
+                jf: 1,
+                k,
+            };
+
+            let mut filter = vec![
+                statement(load, ARCH),
+                libc::sock_filter {
+                    code: jump_equal,
+                    jt: 1,
+                    jf: 0,
+                    k: AUDIT_ARCH,
+                },
+                statement(ret, libc::SECCOMP_RET_KILL_PROCESS),
+                statement(load, NR),
+            ];
+            // x32 system calls share the architecture, with a high bit set.
+            #[cfg(target_arch = "x86_64")]
+            filter.extend([
+                libc::sock_filter {
+                    code: (libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K) as u16,
+                    jt: 0,
+                    jf: 1,
+                    k: 0x4000_0000,
+                },
+                statement(ret, deny),
+            ]);
+            for syscall in DENIED_SYSCALLS {
+                filter.extend([if_equal(syscall as u32), statement(ret, deny)]);
+            }
+            filter.push(statement(ret, libc::SECCOMP_RET_ALLOW));
+            Ok(filter)
+        }
+
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        fn seccomp_filter() -> anyhow::Result<Vec<libc::sock_filter>> {
+            Err(anyhow::anyhow!(
+                "Seccomp filters are not supported on this architecture"
+            ))
+        }
+
+        unsafe fn write_file(path: &CStr, data: &[u8]) -> io::Result<()> {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let written = libc::write(fd, data.as_ptr().cast(), data.len());
+            let error = io::Error::last_os_error();
+            libc::close(fd);
+            if written != data.len() as isize {
+                return Err(error);
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::execution::runner;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_namespaces_keep_programs_off_the_network_root_and_other_processes() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            if !namespaces_available().await {
+                return;
+            }
+            let sandbox = Sandbox::new(RuntimeTable::builtin(), Isolation::Namespaces);
+            let script = format!(
+                "touch /sandbox-test 2>/dev/null || echo read-only\n\
+                touch here && echo writable\n\
+                tail -n +3 /proc/net/dev | cut -d: -f1\n\
+                echo $$\n\
+                test -e /proc/{} || echo hidden\n\
+                mount -t tmpfs none here 2>/dev/null || echo no-mount",
+                std::process::id()
+            );
+            let output = runner::run(
+                &sandbox,
+                "probe.sh",
+                script.as_bytes(),
+                b"",
+                &BTreeMap::new(),
+                &Limits::new(Duration::from_secs(10)),
+                |_| {},
+                std::future::pending(),
+            )
+            .await
+            .unwrap();
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let lines: Vec<&str> = stdout.lines().map(str::trim).collect();
+            assert_eq!(
+                lines,
+                vec!["read-only", "writable", "lo", "2", "hidden", "no-mount"]
+            );
+
+            // The program is not the first process of its namespace, which
+            // would ignore the signal of the CPU limit.
+            let limits = Limits {
+                cpu_seconds: Some(1),
+                ..Limits::new(Duration::from_secs(10))
+            };
+            let output = runner::run(
+                &sandbox,
+                "spin.sh",
+                b"while :; do :; done",
+                b"",
+                &BTreeMap::new(),
+                &limits,
+                |_| {},
+                std::future::pending(),
+            )
+            .await
+            .unwrap();
+            assert!(String::from_utf8_lossy(&output.stderr).contains("CPU time limit exceeded"));
+        });
+    }
+}
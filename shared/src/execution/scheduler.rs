@@ -0,0 +1,231 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use croner::Cron;
+use log::{error, info, warn};
+
+use super::cancel::{instance_id, CancelRegistry};
+use super::engine::{self, ExecutionInput};
+use super::events::EventHub;
+use super::parameters;
+use crate::database::{
+    db_interface::DatabaseConnection, lease_repository::LeaseRepository,
+    pipeline_repository::PipelineRepository,
+};
+use crate::models::pipeline::{Pipeline, PipelineSchedule};
+use crate::storage::blob_store::Storage;
+
+/// Only the server holding this lease looks for due pipelines.
+const LEASE: &str = "scheduler";
+const LEASE_TTL: Duration = Duration::from_secs(60);
+/// Longest the scheduler sleeps, so that new schedules are picked up.
+const TICK: Duration = Duration::from_secs(15);
+
+fn parse(schedule: &PipelineSchedule) -> Result<(Cron, Tz)> {
+    let cron = schedule
+        .cron
+        .parse::<Cron>()
+        .map_err(|e| anyhow!("Invalid cron expression {:?}: {}", schedule.cron, e))?;
+    let timezone = schedule
+        .timezone
+        .parse::<Tz>()
+        .map_err(|_| anyhow!("Unknown time zone {:?}", schedule.timezone))?;
+    Ok((cron, timezone))
+}
+
+/// The next `count` times the schedule fires after `after`, whether it is
+/// enabled or not.
+pub fn next_runs(
+    schedule: &PipelineSchedule,
+    after: DateTime<Utc>,
+    count: usize,
+) -> Result<Vec<DateTime<Utc>>> {
+    let (cron, timezone) = parse(schedule)?;
+    let mut runs = Vec::with_capacity(count);
+    let mut last = after.with_timezone(&timezone);
+    while runs.len() < count {
+        last = match cron.find_next_occurrence(&last, false) {
+            Ok(next) => next,
+            // Patterns such as `0 0 30 2 *` never match.
+            Err(_) => break,
+        };
+        runs.push(last.with_timezone(&Utc));
+    }
+    Ok(runs)
+}
+
+/// Checks the schedule and sets its `next_run` to the first run after
+/// `now`, or clears it if the schedule is paused.
+pub fn prepare(schedule: &mut PipelineSchedule, now: DateTime<Utc>) -> Result<()> {
+    let next = next_runs(schedule, now, 1)?.pop();
+    if schedule.enabled && next.is_none() {
+        return Err(anyhow!("Cron expression {:?} never matches", schedule.cron));
+    }
+    schedule.next_run = next.filter(|_| schedule.enabled);
+    Ok(())
+}
+
+/// Upcoming runs of an enabled schedule: its `next_run`, then the following
+/// ones, `count` in all.
+pub fn upcoming_runs(schedule: &PipelineSchedule, count: usize) -> Result<Vec<DateTime<Utc>>> {
+    let Some(next_run) = schedule.next_run.filter(|_| schedule.enabled) else {
+        return Ok(Vec::new());
+    };
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let mut runs = vec![next_run];
+    runs.extend(next_runs(schedule, next_run, count - 1)?);
+    Ok(runs)
+}
+
+/// Starts the pipelines whose schedule is due, forever.
+///
+/// With several servers only the one holding the `scheduler` lease looks
+/// for due pipelines, and each run is claimed by moving the pipeline's
+/// `next_run` forward before it starts, so a run never starts twice even if
+/// the lease changes hands meanwhile. Runs missed while no server was
+/// scheduling are not made up for; the schedule resumes with the next one.
+pub async fn run_scheduler(
+    db: DatabaseConnection,
+    storage: Storage,
+    events: EventHub,
+    cancels: CancelRegistry,
+) {
+    let holder = instance_id();
+    info!("Scheduler started as {}", holder);
+
+    loop {
+        let sleep = match db.acquire_lease(LEASE, holder, LEASE_TTL).await {
+            Ok(true) => match start_due_pipelines(&db, &storage, &events, &cancels).await {
+                Ok(next_run) => next_run
+                    .and_then(|next_run| (next_run - Utc::now()).to_std().ok())
+                    .map_or(TICK, |until| until.min(TICK)),
+                Err(e) => {
+                    error!("Could not start scheduled pipelines: {:?}", e);
+                    TICK
+                }
+            },
+            Ok(false) => TICK,
+            Err(e) => {
+                error!("Could not acquire the scheduler lease: {:?}", e);
+                TICK
+            }
+        };
+        tokio::time::sleep(sleep).await;
+    }
+}
+
+/// Starts every due pipeline and returns the earliest run still to come.
+async fn start_due_pipelines(
+    db: &DatabaseConnection,
+    storage: &Storage,
+    events: &EventHub,
+    cancels: &CancelRegistry,
+) -> Result<Option<DateTime<Utc>>> {
+    let now = Utc::now();
+    let mut earliest: Option<DateTime<Utc>> = None;
+    for pipeline in db.find_scheduled_pipelines().await? {
+        let Some(schedule) = &pipeline.schedule else {
+            continue;
+        };
+        let Some(due) = schedule.next_run else {
+            continue;
+        };
+        if due > now {
+            earliest = Some(earliest.map_or(due, |earliest| earliest.min(due)));
+            continue;
+        }
+
+        let next = match next_runs(schedule, now, 1) {
+            Ok(mut runs) => runs.pop(),
+            Err(e) => {
+                warn!("Pipeline {} has a broken schedule: {}", pipeline.id, e);
+                None
+            }
+        };
+        if !db.claim_scheduled_run(&pipeline.id, due, next).await? {
+            continue;
+        }
+        if let Some(next) = next {
+            earliest = Some(earliest.map_or(next, |earliest| earliest.min(next)));
+        }
+        start(db, storage, events, cancels, &pipeline, due).await;
+    }
+    Ok(earliest)
+}
+
+async fn start(
+    db: &DatabaseConnection,
+    storage: &Storage,
+    events: &EventHub,
+    cancels: &CancelRegistry,
+    pipeline: &Pipeline,
+    due: DateTime<Utc>,
+) {
+    // Scheduled runs use the parameter defaults.
+    let started = match parameters::resolve(&pipeline.parameters, &Default::default()) {
+        Ok(parameters) => {
+            let input = ExecutionInput {
+                parameters,
+                file: None,
+            };
+            engine::start_execution(db, storage, events, cancels, pipeline, input).await
+        }
+        Err(e) => Err(e),
+    };
+    match started {
+        Ok(record) => info!(
+            "Scheduled run of pipeline {} due at {} started as execution {}",
+            pipeline.id, due, record.id
+        ),
+        Err(e) => error!(
+            "Scheduled run of pipeline {} due at {} could not start: {:?}",
+            pipeline.id, due, e
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn schedule(cron: &str, timezone: &str) -> PipelineSchedule {
+        PipelineSchedule {
+            cron: cron.to_string(),
+            timezone: timezone.to_string(),
+            enabled: true,
+            next_run: None,
+        }
+    }
+
+    #[test]
+    fn test_next_runs_follow_the_time_zone() {
+        let after = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let runs = next_runs(&schedule("30 2 * * *", "Europe/Paris"), after, 2).unwrap();
+        assert_eq!(
+            runs,
+            vec![
+                Utc.with_ymd_and_hms(2024, 1, 1, 1, 30, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 1, 2, 1, 30, 0).unwrap(),
+            ]
+        );
+
+        let mut paused = schedule("*/10 * * * * *", "UTC");
+        paused.enabled = false;
+        prepare(&mut paused, after).unwrap();
+        assert_eq!(paused.next_run, None);
+        paused.enabled = true;
+        prepare(&mut paused, after).unwrap();
+        assert_eq!(
+            paused.next_run,
+            Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 10).unwrap())
+        );
+
+        assert!(prepare(&mut schedule("not cron", "UTC"), after).is_err());
+        assert!(prepare(&mut schedule("* * * * *", "Mars/Olympus"), after).is_err());
+    }
+}
@@ -1,3 +1,5 @@
 pub mod database;
+pub mod execution;
 pub mod models;
 pub mod serializers;
+pub mod storage;
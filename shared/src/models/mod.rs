@@ -1,3 +1,5 @@
 pub mod pipeline;
 pub mod program;
+pub mod program_version;
 pub mod upload_file;
+pub mod upload_session;
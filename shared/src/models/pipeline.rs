@@ -1,9 +1,13 @@
+use std::collections::BTreeMap;
+
+use crate::serializers::{bson_datetime_serializer, optional_bson_datetime_serializer};
 use bson::oid::ObjectId;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Pipeline {
     #[serde(rename = "_id")]
     #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
@@ -25,11 +29,209 @@ pub struct Pipeline {
     #[schema(example = json!(vec![ObjectId::new().to_string(), ObjectId::new().to_string()]))]
     pub steps: Vec<String>,
 
+    /// Which step feeds which, by step number. Without edges the steps form
+    /// a chain in the order they are listed.
+    #[serde(rename = "edges", default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = json!([{"from": 1, "to": 2}]))]
+    pub edges: Option<Vec<PipelineEdge>>,
+
+    #[serde(rename = "schedule", default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<PipelineSchedule>,
+
+    /// Values each run can be given, passed to every step.
+    #[serde(rename = "parameters", default)]
+    pub parameters: Vec<PipelineParameter>,
+
+    /// How steps are retried, limited and what happens when they fail;
+    /// steps without a policy get `StepPolicy::new`.
+    #[serde(rename = "policies", default)]
+    pub policies: Vec<StepPolicy>,
+
+    /// Whether steps may reuse the output of an earlier run of the same
+    /// program on the same input instead of running again.
+    #[serde(rename = "cache_results", default = "default_cache_results")]
+    #[schema(example = true)]
+    pub cache_results: bool,
+
     #[serde(rename = "created_date")]
     #[schema(example = json!(DateTime::<Utc>::from(Utc::now())))]
     pub created_date: String,
 }
 
+/// The output of step `from` is part of the input of step `to`. Steps are
+/// numbered from 1 in the order of `Pipeline.steps`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PipelineEdge {
+    #[serde(rename = "from")]
+    #[schema(example = 1)]
+    pub from: i32,
+
+    #[serde(rename = "to")]
+    #[schema(example = 2)]
+    pub to: i32,
+}
+
+/// When a pipeline runs on its own.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PipelineSchedule {
+    /// Cron expression with five fields, or six with seconds first.
+    #[serde(rename = "cron")]
+    #[schema(example = "0 2 * * *")]
+    pub cron: String,
+
+    /// IANA time zone the expression is read in.
+    #[serde(rename = "timezone", default = "default_timezone")]
+    #[schema(example = "Europe/Paris")]
+    pub timezone: String,
+
+    #[serde(rename = "enabled", default = "default_enabled")]
+    #[schema(example = true)]
+    pub enabled: bool,
+
+    /// When the scheduler starts the pipeline next; maintained by the
+    /// server, `None` while the schedule is paused.
+    #[serde(
+        rename = "next_run",
+        with = "optional_bson_datetime_serializer",
+        default
+    )]
+    #[schema(example = "2024-08-02T00:00:00Z")]
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// A named value given to a run of a pipeline. Steps get it as the
+/// `PARAM_<NAME>` environment variable and a `--<name>=<value>` argument.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PipelineParameter {
+    #[serde(rename = "name")]
+    #[schema(example = "limit")]
+    pub name: String,
+
+    /// One of `string`, `integer`, `number` or `boolean`.
+    #[serde(rename = "type")]
+    #[schema(example = "integer")]
+    pub kind: String,
+
+    /// Used when a run does not set the parameter; without one the
+    /// parameter is required.
+    #[serde(rename = "default", default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>, example = json!(10))]
+    pub default: Option<Value>,
+
+    #[serde(rename = "description", default)]
+    #[schema(example = "How many rows to keep")]
+    pub description: String,
+}
+
+/// What the engine does with one step besides running it once.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct StepPolicy {
+    /// The step this applies to, numbered from 1.
+    #[serde(rename = "step")]
+    #[schema(example = 2)]
+    pub step: i32,
+
+    /// How many times a failed run is started again.
+    #[serde(rename = "max_retries", default)]
+    #[schema(example = 3)]
+    pub max_retries: u32,
+
+    /// Wait before the first retry, doubled before each following one.
+    #[serde(rename = "retry_delay_ms", default = "default_retry_delay_ms")]
+    #[schema(example = 1000)]
+    pub retry_delay_ms: u64,
+
+    /// Wall-clock limit of each run, `EXECUTION_STEP_TIMEOUT` by default.
+    #[serde(rename = "timeout_seconds", default)]
+    #[schema(example = 30)]
+    pub timeout_seconds: Option<u64>,
+
+    /// Address space limit of each run.
+    #[serde(rename = "memory_limit_mb", default)]
+    #[schema(example = 512)]
+    pub memory_limit_mb: Option<u64>,
+
+    /// One of `abort` (the default), `continue`, where the steps it feeds
+    /// get whatever it wrote, or `fallback`, where `fallback` runs instead.
+    #[serde(rename = "on_failure", default = "default_on_failure")]
+    #[schema(example = "fallback")]
+    pub on_failure: String,
+
+    /// Program run once with the same input when every attempt failed.
+    #[serde(rename = "fallback", default)]
+    #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
+    pub fallback: Option<String>,
+}
+
+impl StepPolicy {
+    /// Run once, abort the execution on failure.
+    pub fn new(step: i32) -> Self {
+        StepPolicy {
+            step,
+            max_retries: 0,
+            retry_delay_ms: default_retry_delay_ms(),
+            timeout_seconds: None,
+            memory_limit_mb: None,
+            on_failure: default_on_failure(),
+            fallback: None,
+        }
+    }
+}
+
+fn default_retry_delay_ms() -> u64 {
+    1000
+}
+
+fn default_on_failure() -> String {
+    "abort".to_string()
+}
+
+fn default_cache_results() -> bool {
+    true
+}
+
+/// A step whose output the step it feeds does not accept, or, when
+/// `unknown` is set, may not accept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TypeMismatch {
+    #[serde(rename = "from_step")]
+    #[schema(example = 1)]
+    pub from_step: i32,
+
+    #[serde(rename = "to_step")]
+    #[schema(example = 2)]
+    pub to_step: i32,
+
+    #[serde(rename = "output_type")]
+    #[schema(example = "application/json")]
+    pub output_type: String,
+
+    #[serde(rename = "input_type")]
+    #[schema(example = "text/csv")]
+    pub input_type: String,
+
+    /// One of the types cannot be told, so the edge could not be checked.
+    /// Only a warning: it does not make the pipeline invalid.
+    #[serde(rename = "unknown", default)]
+    #[schema(example = false)]
+    pub unknown: bool,
+}
+
+impl TypeMismatch {
+    /// Whether this mismatch makes the pipeline invalid.
+    pub fn is_error(&self) -> bool {
+        !self.unknown
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CreatePipeline {
     #[serde(rename = "owner_id")]
@@ -47,6 +249,30 @@ pub struct CreatePipeline {
     #[serde(rename = "steps")]
     #[schema(example = json!(vec![ObjectId::new().to_string(), ObjectId::new().to_string()]))]
     pub steps: Vec<String>,
+
+    /// Which step feeds which, by step number. Without edges the steps form
+    /// a chain in the order they are listed.
+    #[serde(rename = "edges", default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = json!([{"from": 1, "to": 2}]))]
+    pub edges: Option<Vec<PipelineEdge>>,
+
+    #[serde(rename = "schedule", default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<PipelineSchedule>,
+
+    /// Values each run can be given, passed to every step.
+    #[serde(rename = "parameters", default)]
+    pub parameters: Vec<PipelineParameter>,
+
+    /// How steps are retried, limited and what happens when they fail;
+    /// steps without a policy get `StepPolicy::new`.
+    #[serde(rename = "policies", default)]
+    pub policies: Vec<StepPolicy>,
+
+    /// Whether steps may reuse the output of an earlier run of the same
+    /// program on the same input instead of running again.
+    #[serde(rename = "cache_results", default = "default_cache_results")]
+    #[schema(example = true)]
+    pub cache_results: bool,
 }
 
 impl From<CreatePipeline> for Pipeline {
@@ -57,6 +283,11 @@ impl From<CreatePipeline> for Pipeline {
             name: create.name,
             description: create.description,
             steps: create.steps,
+            edges: create.edges,
+            schedule: create.schedule,
+            parameters: create.parameters,
+            policies: create.policies,
+            cache_results: create.cache_results,
             created_date: Utc::now().to_string(),
         }
     }
@@ -75,6 +306,32 @@ pub struct UpdatePipeline {
     #[serde(rename = "steps")]
     #[schema(example = json!(vec![ObjectId::new().to_string(), ObjectId::new().to_string()]))]
     pub steps: Vec<String>,
+
+    /// Which step feeds which, by step number. Without edges the steps form
+    /// a chain in the order they are listed.
+    #[serde(rename = "edges", default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = json!([{"from": 1, "to": 2}]))]
+    pub edges: Option<Vec<PipelineEdge>>,
+
+    #[serde(rename = "schedule", default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<PipelineSchedule>,
+
+    #[serde(
+        rename = "parameters",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub parameters: Option<Vec<PipelineParameter>>,
+
+    #[serde(rename = "policies", default, skip_serializing_if = "Option::is_none")]
+    pub policies: Option<Vec<StepPolicy>>,
+
+    #[serde(
+        rename = "cache_results",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub cache_results: Option<bool>,
 }
 
 impl UpdatePipeline {
@@ -93,11 +350,76 @@ impl UpdatePipeline {
             update_document.insert("steps", self.steps.clone());
         }
 
+        if let Some(edges) = &self.edges {
+            let edges: Vec<bson::Document> = edges
+                .iter()
+                .map(|edge| bson::doc! {"from": edge.from, "to": edge.to})
+                .collect();
+            update_document.insert("edges", edges);
+        }
+
+        if let Some(schedule) = &self.schedule {
+            if let Ok(schedule) = bson::to_bson(schedule) {
+                update_document.insert("schedule", schedule);
+            }
+        }
+
+        if let Some(parameters) = &self.parameters {
+            if let Ok(parameters) = bson::to_bson(parameters) {
+                update_document.insert("parameters", parameters);
+            }
+        }
+
+        if let Some(policies) = &self.policies {
+            if let Ok(policies) = bson::to_bson(policies) {
+                update_document.insert("policies", policies);
+            }
+        }
+
+        if let Some(cache_results) = self.cache_results {
+            update_document.insert("cache_results", cache_results);
+        }
+
         update_document
     }
+
+    /// Same rules as `build_update_document`: empty fields are left untouched.
+    pub fn apply(&self, pipeline: &mut Pipeline) {
+        if !self.name.is_empty() {
+            pipeline.name = self.name.clone();
+        }
+
+        if !self.description.is_empty() {
+            pipeline.description = self.description.clone();
+        }
+
+        if !self.steps.is_empty() {
+            pipeline.steps = self.steps.clone();
+        }
+
+        if self.edges.is_some() {
+            pipeline.edges = self.edges.clone();
+        }
+
+        if self.schedule.is_some() {
+            pipeline.schedule = self.schedule.clone();
+        }
+
+        if let Some(parameters) = &self.parameters {
+            pipeline.parameters = parameters.clone();
+        }
+
+        if let Some(policies) = &self.policies {
+            pipeline.policies = policies.clone();
+        }
+
+        if let Some(cache_results) = self.cache_results {
+            pipeline.cache_results = cache_results;
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ExecutionRecord {
     #[serde(rename = "_id")]
     #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
@@ -107,15 +429,214 @@ pub struct ExecutionRecord {
     #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
     pub pipeline_id: ObjectId,
 
-    #[serde(rename = "execution_time")]
-    #[schema(example = json!(DateTime::<Utc>::from(Utc::now())))]
+    #[serde(rename = "execution_time", with = "bson_datetime_serializer")]
+    #[schema(example = "2024-08-01T12:34:56Z")]
     pub execution_time: DateTime<Utc>,
 
+    /// One of `running`, `success`, `failed` or `cancelled`.
     #[serde(rename = "status")]
     #[schema(example = "success")]
     pub status: String,
 
+    /// The start of the output, at most 4 KiB, when it is text; the error
+    /// when the execution failed. The whole output is in the artifacts of
+    /// the steps nobody reads from.
     #[serde(rename = "output")]
     #[schema(example = "output")]
     pub output: String,
+
+    /// Size of the whole output in bytes.
+    #[serde(rename = "output_size", default)]
+    #[schema(example = 6)]
+    pub output_size: i64,
+
+    #[serde(
+        rename = "finished_time",
+        with = "optional_bson_datetime_serializer",
+        default
+    )]
+    #[schema(example = "2024-08-01T12:35:02Z")]
+    pub finished_time: Option<DateTime<Utc>>,
+
+    #[serde(rename = "steps", default)]
+    pub steps: Vec<StepRecord>,
+
+    /// Parameter values the steps were given, defaults included.
+    #[serde(rename = "parameters", default)]
+    #[schema(example = json!({"limit": "10"}))]
+    pub parameters: BTreeMap<String, String>,
+
+    /// Storage key of the file given to the steps without inputs on their
+    /// stdin, if the run was started with one.
+    #[serde(rename = "input_path", default)]
+    #[schema(example = "executions/60f7b3b3d4b3f3b3f3b3f3b3/input")]
+    pub input_path: Option<String>,
+
+    #[serde(rename = "input_filename", default)]
+    #[schema(example = "data.csv")]
+    pub input_filename: Option<String>,
+
+    /// Number of steps whose output came from the result cache.
+    #[serde(rename = "cache_hits", default)]
+    #[schema(example = 1)]
+    pub cache_hits: i32,
+
+    /// The server running the execution, see `cancel::instance_id`.
+    #[serde(rename = "instance", default)]
+    #[schema(example = "api-1-60f7b3b3d4b3f3b3f3b3f3b3")]
+    pub instance: String,
+
+    /// Last time that server said it was still running the execution.
+    #[serde(
+        rename = "heartbeat",
+        with = "optional_bson_datetime_serializer",
+        default
+    )]
+    #[schema(example = "2024-08-01T12:35:00Z")]
+    pub heartbeat: Option<DateTime<Utc>>,
+
+    /// Another server was asked to cancel the execution; its owner stops it
+    /// on its next heartbeat.
+    #[serde(rename = "cancel_requested", default)]
+    #[schema(example = false)]
+    pub cancel_requested: bool,
+}
+
+/// What happened to one step of an execution.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StepRecord {
+    /// Position of the step in the pipeline, starting at 1.
+    #[serde(rename = "step")]
+    #[schema(example = 1)]
+    pub step: i32,
+
+    #[serde(rename = "program_id")]
+    #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
+    pub program_id: String,
+
+    #[serde(rename = "filename")]
+    #[schema(example = "example.py")]
+    pub filename: String,
+
+    /// One of `pending`, `running`, `success`, `failed`, `cancelled` or
+    /// `skipped`.
+    #[serde(rename = "status")]
+    #[schema(example = "success")]
+    pub status: String,
+
+    #[serde(
+        rename = "start_time",
+        with = "optional_bson_datetime_serializer",
+        default
+    )]
+    #[schema(example = "2024-08-01T12:34:56Z")]
+    pub start_time: Option<DateTime<Utc>>,
+
+    #[serde(
+        rename = "end_time",
+        with = "optional_bson_datetime_serializer",
+        default
+    )]
+    #[schema(example = "2024-08-01T12:34:57Z")]
+    pub end_time: Option<DateTime<Utc>>,
+
+    #[serde(rename = "exit_code")]
+    #[schema(example = 0)]
+    pub exit_code: Option<i32>,
+
+    #[serde(rename = "stdout_size")]
+    #[schema(example = 12)]
+    pub stdout_size: i64,
+
+    #[serde(rename = "stderr_size")]
+    #[schema(example = 0)]
+    pub stderr_size: i64,
+
+    /// Storage key of the step's stdout.
+    #[serde(rename = "output_path")]
+    #[schema(example = "executions/60f7b3b3d4b3f3b3f3b3f3b3/1.out")]
+    pub output_path: Option<String>,
+
+    #[serde(rename = "output_url")]
+    #[schema(example = "/v1/executions/60f7b3b3d4b3f3b3f3b3f3b3/steps/1/output")]
+    pub output_url: Option<String>,
+
+    /// Media type of the stdout, from the program's `output_type`.
+    #[serde(rename = "content_type", default)]
+    #[schema(example = "text/plain")]
+    pub content_type: Option<String>,
+
+    /// The start of the stdout, at most 4 KiB, when it is text.
+    #[serde(rename = "preview", default)]
+    #[schema(example = "hello")]
+    pub preview: Option<String>,
+
+    /// Every run of the step, retries and fallback included; the fields
+    /// above describe the last one.
+    #[serde(rename = "attempts", default)]
+    pub attempts: Vec<StepAttempt>,
+
+    /// The output was reused from an earlier run instead of running the
+    /// program; `attempts` is then empty.
+    #[serde(rename = "cached", default)]
+    #[schema(example = false)]
+    pub cached: bool,
+}
+
+/// One run of a step's program.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StepAttempt {
+    /// Starting at 1.
+    #[serde(rename = "attempt")]
+    #[schema(example = 1)]
+    pub attempt: u32,
+
+    /// The step's program, or its fallback.
+    #[serde(rename = "program_id")]
+    #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
+    pub program_id: String,
+
+    /// One of `success`, `failed` or `cancelled`.
+    #[serde(rename = "status")]
+    #[schema(example = "failed")]
+    pub status: String,
+
+    #[serde(rename = "start_time", with = "bson_datetime_serializer")]
+    #[schema(example = "2024-08-01T12:34:56Z")]
+    pub start_time: DateTime<Utc>,
+
+    #[serde(rename = "end_time", with = "bson_datetime_serializer")]
+    #[schema(example = "2024-08-01T12:34:57Z")]
+    pub end_time: DateTime<Utc>,
+
+    #[serde(rename = "exit_code")]
+    #[schema(example = 1)]
+    pub exit_code: Option<i32>,
+
+    /// Why the attempt failed.
+    #[serde(rename = "error")]
+    #[schema(example = "exit code 1: connection refused")]
+    pub error: Option<String>,
+}
+
+impl StepRecord {
+    pub fn pending(step: i32, program_id: &str) -> Self {
+        StepRecord {
+            step,
+            program_id: program_id.to_string(),
+            filename: String::new(),
+            status: "pending".to_string(),
+            start_time: None,
+            end_time: None,
+            exit_code: None,
+            stdout_size: 0,
+            stderr_size: 0,
+            output_path: None,
+            output_url: None,
+            content_type: None,
+            preview: None,
+            attempts: Vec::new(),
+            cached: false,
+        }
+    }
 }
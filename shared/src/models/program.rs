@@ -1,10 +1,10 @@
 use crate::serializers::bson_datetime_serializer;
-use bson::oid::ObjectId;
+use bson::{oid::ObjectId, Document};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Program {
     #[serde(rename = "_id")]
     #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
@@ -24,10 +24,10 @@ pub struct Program {
     #[serde(rename = "file_size")]
     #[schema(example = "1024")]
     pub file_size: i64,
-    /* At the moment, we don't have these fields in the model
-    #[serde(rename = "input_type")]
+    /// Media type the program reads on stdin, possibly with wildcards.
+    #[serde(rename = "input_type", default = "any_media_type")]
     #[schema(example = "text/plain")]
-    pub input_type: String,*/
+    pub input_type: String,
     #[serde(rename = "output_type")]
     #[schema(example = "text/plain")]
     pub output_type: String,
@@ -41,8 +41,122 @@ pub struct Program {
     #[schema(example = "/path/to/save/example.py")]
     pub file_path: String,
     #[serde(rename = "file_hash")]
-    #[schema(example = "example_hash")]
+    #[schema(example = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08")]
     pub file_hash: String,
+    /// Number of the `ProgramVersion` the program currently points at; `0`
+    /// for programs uploaded before version history existed.
+    #[serde(rename = "current_version", default)]
+    #[schema(example = 1)]
+    pub current_version: i32,
+}
+
+impl Program {
+    /// Where the current file of a program is downloaded from. The link
+    /// goes through the API whatever the storage backend, so it does not
+    /// expire.
+    pub fn code_url_for(program_id: &ObjectId) -> String {
+        format!("/v1/content/{}/raw", program_id)
+    }
+}
+
+/// Programs accept any input unless told otherwise.
+pub fn any_media_type() -> String {
+    "*/*".to_string()
+}
+
+#[derive(Default, Serialize, Deserialize, ToSchema)]
+pub struct UpdateProgramDto {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "example.py")]
+    pub filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "https://example.com/example.py")]
+    pub code_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "text/plain")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "1024")]
+    pub file_size: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "text/plain")]
+    pub input_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "text/plain")]
+    pub output_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "https://example.com/example.py")]
+    pub file_path: Option<String>,
+}
+
+impl UpdateProgramDto {
+    pub fn build_update_document(&self) -> Document {
+        let mut update_document = Document::new();
+
+        if let Some(filename) = &self.filename {
+            update_document.insert("filename", filename);
+        }
+
+        if let Some(code_url) = &self.code_url {
+            update_document.insert("code_url", code_url);
+        }
+
+        if let Some(content_type) = &self.content_type {
+            update_document.insert("content_type", content_type);
+        }
+
+        if let Some(file_size) = &self.file_size {
+            update_document.insert("file_size", file_size);
+        }
+
+        if let Some(input_type) = &self.input_type {
+            update_document.insert("input_type", input_type);
+        }
+
+        if let Some(output_type) = &self.output_type {
+            update_document.insert("output_type", output_type);
+        }
+
+        if let Some(file_path) = &self.file_path {
+            update_document.insert("file_path", file_path);
+        }
+
+        update_document
+    }
+
+    /// Same rules as `build_update_document`, for stores that hold `Program`s
+    /// rather than documents.
+    pub fn apply(&self, program: &mut Program) {
+        if let Some(filename) = &self.filename {
+            program.filename = filename.clone();
+        }
+
+        if let Some(code_url) = &self.code_url {
+            program.code_url = code_url.clone();
+        }
+
+        if let Some(content_type) = &self.content_type {
+            program.content_type = content_type.clone();
+        }
+
+        if let Some(file_size) = self.file_size {
+            program.file_size = file_size;
+        }
+
+        if let Some(input_type) = &self.input_type {
+            program.input_type = input_type.clone();
+        }
+
+        if let Some(output_type) = &self.output_type {
+            program.output_type = output_type.clone();
+        }
+
+        if let Some(file_path) = &self.file_path {
+            program.file_path = file_path.clone();
+        }
+
+        program.update_time = Utc::now();
+    }
 }
 
 // TODO: other models (Pipeline, ExecutionRecord, etc)
@@ -0,0 +1,84 @@
+use crate::models::program::Program;
+use crate::serializers::bson_datetime_serializer;
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// An immutable snapshot of the file behind a `Program`, written on every
+/// upload that changes its content.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ProgramVersion {
+    #[serde(rename = "_id")]
+    #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
+    pub id: ObjectId,
+    #[serde(rename = "program_id")]
+    #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
+    pub program_id: ObjectId,
+    #[serde(rename = "version")]
+    #[schema(example = 1)]
+    pub version: i32,
+    #[serde(rename = "filename")]
+    #[schema(example = "example.py")]
+    pub filename: String,
+    #[serde(rename = "content_type")]
+    #[schema(example = "text/plain")]
+    pub content_type: String,
+    #[serde(rename = "file_hash")]
+    #[schema(example = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08")]
+    pub file_hash: String,
+    #[serde(rename = "file_size")]
+    #[schema(example = "1024")]
+    pub file_size: i64,
+    #[serde(rename = "file_path")]
+    #[schema(example = "content/121/example.py")]
+    pub file_path: String,
+    #[serde(rename = "code_url")]
+    #[schema(example = "https://example.com/example.py")]
+    pub code_url: String,
+    #[serde(rename = "uploaded_by")]
+    #[schema(example = "121")]
+    pub uploaded_by: i32,
+    #[serde(rename = "upload_time", with = "bson_datetime_serializer")]
+    #[schema(example = "2024-08-01T12:34:56Z")]
+    pub upload_time: DateTime<Utc>,
+    /// Every time this version was made current again, oldest first.
+    #[serde(rename = "restorations", default)]
+    pub restorations: Vec<Restoration>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Restoration {
+    #[serde(rename = "restored_by")]
+    #[schema(example = "121")]
+    pub restored_by: i32,
+    #[serde(rename = "restored_time", with = "bson_datetime_serializer")]
+    #[schema(example = "2024-08-01T12:34:56Z")]
+    pub restored_time: DateTime<Utc>,
+}
+
+impl ProgramVersion {
+    /// Where the file of one version is downloaded from, see
+    /// `Program::code_url_for`.
+    pub fn code_url_for(program_id: &ObjectId, version: i32) -> String {
+        format!("/v1/content/{}/versions/{}/raw", program_id, version)
+    }
+
+    /// Snapshot of the file a program currently points at.
+    pub fn from_program(program: &Program, version: i32) -> Self {
+        ProgramVersion {
+            id: ObjectId::new(),
+            program_id: program.id,
+            version,
+            filename: program.filename.clone(),
+            content_type: program.content_type.clone(),
+            file_hash: program.file_hash.clone(),
+            file_size: program.file_size,
+            file_path: program.file_path.clone(),
+            code_url: ProgramVersion::code_url_for(&program.id, version),
+            uploaded_by: program.owner_id,
+            upload_time: program.update_time,
+            restorations: Vec::new(),
+        }
+    }
+}
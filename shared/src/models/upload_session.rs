@@ -0,0 +1,100 @@
+use crate::serializers::bson_datetime_serializer;
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// State of a resumable upload. Each received chunk is stored as a separate
+/// blob (`parts`) until the session is finalized into a `Program`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UploadSession {
+    #[serde(rename = "_id")]
+    #[schema(example = "60f7b3b3d4b3f3b3f3b3f3b3")]
+    pub id: ObjectId,
+    #[serde(rename = "owner_id")]
+    #[schema(example = "121")]
+    pub owner_id: i32,
+    #[serde(rename = "filename")]
+    #[schema(example = "example.py")]
+    pub filename: String,
+    #[serde(rename = "content_type")]
+    #[schema(example = "text/plain")]
+    pub content_type: String,
+    #[serde(rename = "output_extension")]
+    #[schema(example = ".txt")]
+    pub output_extension: String,
+    /// Total size announced when the session was created.
+    #[serde(rename = "upload_length")]
+    #[schema(example = "1048576")]
+    pub upload_length: i64,
+    /// Number of bytes received so far.
+    #[serde(rename = "offset")]
+    #[schema(example = "524288")]
+    pub offset: i64,
+    #[serde(rename = "parts")]
+    pub parts: Vec<UploadPart>,
+    /// `open` while chunks are accepted, `finalizing` while one request
+    /// turns it into a program, `completed` once finalized.
+    #[serde(rename = "status")]
+    #[schema(example = "open")]
+    pub status: String,
+    #[serde(rename = "created_time", with = "bson_datetime_serializer")]
+    #[schema(example = "2024-08-01T12:34:56Z")]
+    pub created_time: DateTime<Utc>,
+    #[serde(rename = "update_time", with = "bson_datetime_serializer")]
+    #[schema(example = "2024-08-01T12:34:56Z")]
+    pub update_time: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UploadPart {
+    #[serde(rename = "key")]
+    #[schema(example = "uploads/60f7b3b3d4b3f3b3f3b3f3b3/00000000000000000000")]
+    pub key: String,
+    #[serde(rename = "offset")]
+    #[schema(example = "0")]
+    pub offset: i64,
+    #[serde(rename = "size")]
+    #[schema(example = "524288")]
+    pub size: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateUploadSession {
+    #[serde(rename = "owner_id")]
+    #[schema(example = "121")]
+    pub owner_id: i32,
+    #[serde(rename = "filename")]
+    #[schema(example = "example.py")]
+    pub filename: String,
+    #[serde(rename = "content_type")]
+    #[schema(example = "text/plain")]
+    pub content_type: Option<String>,
+    #[serde(rename = "output_extension")]
+    #[schema(example = ".txt")]
+    pub output_extension: Option<String>,
+    #[serde(rename = "upload_length")]
+    #[schema(example = "1048576")]
+    pub upload_length: i64,
+}
+
+impl From<CreateUploadSession> for UploadSession {
+    fn from(create: CreateUploadSession) -> Self {
+        let now = Utc::now();
+        UploadSession {
+            id: ObjectId::new(),
+            owner_id: create.owner_id,
+            filename: create.filename,
+            content_type: create
+                .content_type
+                .unwrap_or_else(|| "application/octet-stream".to_string()),
+            output_extension: create.output_extension.unwrap_or_default(),
+            upload_length: create.upload_length,
+            offset: 0,
+            parts: Vec::new(),
+            status: "open".to_string(),
+            created_time: now,
+            update_time: now,
+        }
+    }
+}
@@ -20,3 +20,23 @@ pub mod bson_datetime_serializer {
         Ok(bson_date.to_chrono())
     }
 }
+
+pub mod optional_bson_datetime_serializer {
+    use super::*;
+
+    pub fn serialize<S>(date: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        date.map(mongodb::bson::DateTime::from_chrono)
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let bson_date = Option::<mongodb::bson::DateTime>::deserialize(deserializer)?;
+        Ok(bson_date.map(|date| date.to_chrono()))
+    }
+}
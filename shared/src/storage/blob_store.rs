@@ -0,0 +1,160 @@
+use std::env;
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use bytes::Bytes;
+use futures::Stream;
+use log::info;
+
+use super::{firebase::FirebaseStore, local::LocalStore, s3::S3Store};
+
+/// Lifetime of the URLs clients are redirected to when they download a
+/// blob. They are signed on every download, never stored, so they can be
+/// short.
+pub const SIGNED_URL_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Storage for the raw bytes of uploaded content.
+///
+/// Keys are plain `/`-separated paths such as `content/121/example.py`; each
+/// backend is responsible for encoding them the way its API expects.
+pub trait BlobStore {
+    fn init() -> impl std::future::Future<Output = Result<Self, Error>> + Send
+    where
+        Self: Sized;
+    fn put(
+        &self,
+        key: &str,
+        content_type: &str,
+        data: Vec<u8>,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+    /// Stores `content_length` bytes read from `stream` without holding them
+    /// all in memory.
+    fn put_stream<S>(
+        &self,
+        key: &str,
+        content_type: &str,
+        content_length: u64,
+        stream: S,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send
+    where
+        S: Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static;
+    fn get(&self, key: &str) -> impl std::future::Future<Output = Result<Vec<u8>, Error>> + Send;
+    fn delete(&self, key: &str) -> impl std::future::Future<Output = Result<(), Error>> + Send;
+    fn exists(&self, key: &str) -> impl std::future::Future<Output = Result<bool, Error>> + Send;
+    fn list(
+        &self,
+        prefix: &str,
+    ) -> impl std::future::Future<Output = Result<Vec<String>, Error>> + Send;
+    fn signed_url(
+        &self,
+        key: &str,
+        expires_in: Duration,
+    ) -> impl std::future::Future<Output = Result<String, Error>> + Send;
+}
+
+/// The storage backend selected at startup through `STORAGE_BACKEND`
+/// (`firebase`, `local` or `s3`). When unset, Firebase is used if
+/// `FIREBASE_STORAGE_BUCKET` is configured and the local disk otherwise.
+#[derive(Clone)]
+pub enum Storage {
+    Firebase(FirebaseStore),
+    Local(LocalStore),
+    S3(S3Store),
+}
+
+impl BlobStore for Storage {
+    async fn init() -> Result<Self> {
+        let backend = env::var("STORAGE_BACKEND").unwrap_or_else(|_| {
+            if env::var("FIREBASE_STORAGE_BUCKET").is_ok_and(|bucket| !bucket.is_empty()) {
+                "firebase".to_string()
+            } else {
+                "local".to_string()
+            }
+        });
+        info!("Using {} storage backend", backend);
+
+        match backend.to_lowercase().as_str() {
+            "firebase" => Ok(Storage::Firebase(FirebaseStore::init().await?)),
+            "local" => Ok(Storage::Local(LocalStore::init().await?)),
+            "s3" => Ok(Storage::S3(S3Store::init().await?)),
+            other => Err(Error::msg(format!("Unknown storage backend: {}", other))),
+        }
+    }
+
+    async fn put(&self, key: &str, content_type: &str, data: Vec<u8>) -> Result<(), Error> {
+        match self {
+            Storage::Firebase(store) => store.put(key, content_type, data).await,
+            Storage::Local(store) => store.put(key, content_type, data).await,
+            Storage::S3(store) => store.put(key, content_type, data).await,
+        }
+    }
+
+    async fn put_stream<S>(
+        &self,
+        key: &str,
+        content_type: &str,
+        content_length: u64,
+        stream: S,
+    ) -> Result<(), Error>
+    where
+        S: Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static,
+    {
+        match self {
+            Storage::Firebase(store) => {
+                store
+                    .put_stream(key, content_type, content_length, stream)
+                    .await
+            }
+            Storage::Local(store) => {
+                store
+                    .put_stream(key, content_type, content_length, stream)
+                    .await
+            }
+            Storage::S3(store) => {
+                store
+                    .put_stream(key, content_type, content_length, stream)
+                    .await
+            }
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
+        match self {
+            Storage::Firebase(store) => store.get(key).await,
+            Storage::Local(store) => store.get(key).await,
+            Storage::S3(store) => store.get(key).await,
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        match self {
+            Storage::Firebase(store) => store.delete(key).await,
+            Storage::Local(store) => store.delete(key).await,
+            Storage::S3(store) => store.delete(key).await,
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, Error> {
+        match self {
+            Storage::Firebase(store) => store.exists(key).await,
+            Storage::Local(store) => store.exists(key).await,
+            Storage::S3(store) => store.exists(key).await,
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        match self {
+            Storage::Firebase(store) => store.list(prefix).await,
+            Storage::Local(store) => store.list(prefix).await,
+            Storage::S3(store) => store.list(prefix).await,
+        }
+    }
+
+    async fn signed_url(&self, key: &str, expires_in: Duration) -> Result<String, Error> {
+        match self {
+            Storage::Firebase(store) => store.signed_url(key, expires_in).await,
+            Storage::Local(store) => store.signed_url(key, expires_in).await,
+            Storage::S3(store) => store.signed_url(key, expires_in).await,
+        }
+    }
+}
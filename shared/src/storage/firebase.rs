@@ -0,0 +1,206 @@
+use std::env;
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use bytes::Bytes;
+use futures::Stream;
+use log::{debug, info};
+use reqwest::{Body, Client, Response, StatusCode};
+use serde_json::Value;
+
+use super::{blob_store::BlobStore, uri_encode};
+
+const FIREBASE_STORAGE_URL: &str = "https://firebasestorage.googleapis.com/v0/b";
+
+/// Firebase Storage accessed through its public REST API.
+#[derive(Clone)]
+pub struct FirebaseStore {
+    client: Client,
+    bucket: String,
+}
+
+impl FirebaseStore {
+    pub fn new<S: Into<String>>(bucket: S) -> Self {
+        FirebaseStore {
+            client: Client::new(),
+            bucket: bucket.into(),
+        }
+    }
+
+    /// Object names are sent URL-encoded, `/` included. Keys stored already
+    /// encoded are decoded at startup, see `Db::migrate_encoded_file_paths`.
+    fn object_name(key: &str) -> String {
+        uri_encode(key, true)
+    }
+
+    fn object_url(&self, key: &str) -> String {
+        format!(
+            "{}/{}/o/{}",
+            FIREBASE_STORAGE_URL,
+            self.bucket,
+            Self::object_name(key)
+        )
+    }
+
+    async fn upload(
+        &self,
+        key: &str,
+        content_type: &str,
+        content_length: u64,
+        body: Body,
+    ) -> Result<(), Error> {
+        let upload_url = format!(
+            "{}/{}/o?name={}",
+            FIREBASE_STORAGE_URL,
+            self.bucket,
+            Self::object_name(key)
+        );
+        let response = self
+            .client
+            .post(&upload_url)
+            .header("Content-Type", content_type)
+            .header("Content-Length", content_length)
+            .body(body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Self::error_from("uploading", response).await);
+        }
+        debug!("File uploaded to: {:?}", key);
+        Ok(())
+    }
+
+    async fn error_from(action: &str, response: Response) -> Error {
+        let status = response.status();
+        let message = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Error::msg(format!("Error {} file ({}): {}", action, status, message))
+    }
+}
+
+impl BlobStore for FirebaseStore {
+    async fn init() -> Result<Self> {
+        let bucket = env::var("FIREBASE_STORAGE_BUCKET")
+            .ok()
+            .filter(|bucket| !bucket.is_empty())
+            .ok_or_else(|| Error::msg("FIREBASE_STORAGE_BUCKET must be set"))?;
+        info!("Firebase storage bucket: {}", bucket);
+        Ok(FirebaseStore::new(bucket))
+    }
+
+    async fn put(&self, key: &str, content_type: &str, data: Vec<u8>) -> Result<(), Error> {
+        self.upload(key, content_type, data.len() as u64, Body::from(data))
+            .await
+    }
+
+    async fn put_stream<S>(
+        &self,
+        key: &str,
+        content_type: &str,
+        content_length: u64,
+        stream: S,
+    ) -> Result<(), Error>
+    where
+        S: Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static,
+    {
+        self.upload(key, content_type, content_length, Body::wrap_stream(stream))
+            .await
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
+        let response = self
+            .client
+            .get(format!("{}?alt=media", self.object_url(key)))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Self::error_from("downloading", response).await);
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        let response = self.client.delete(self.object_url(key)).send().await?;
+
+        if !response.status().is_success() {
+            return Err(Self::error_from("deleting", response).await);
+        }
+        info!("File deleted from: {:?}", key);
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, Error> {
+        let response = self.client.get(self.object_url(key)).send().await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(true),
+            StatusCode::NOT_FOUND => Ok(false),
+            _ => Err(Self::error_from("checking", response).await),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let mut keys = Vec::new();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut list_url = format!(
+                "{}/{}/o?prefix={}",
+                FIREBASE_STORAGE_URL,
+                self.bucket,
+                uri_encode(prefix, true)
+            );
+            if let Some(token) = &page_token {
+                list_url.push_str(&format!("&pageToken={}", uri_encode(token, true)));
+            }
+
+            let response = self.client.get(&list_url).send().await?;
+            if !response.status().is_success() {
+                return Err(Self::error_from("listing", response).await);
+            }
+
+            let body: Value = response.json().await?;
+            if let Some(items) = body.get("items").and_then(Value::as_array) {
+                keys.extend(
+                    items
+                        .iter()
+                        .filter_map(|item| item.get("name").and_then(Value::as_str))
+                        .map(str::to_string),
+                );
+            }
+
+            page_token = body
+                .get("nextPageToken")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            if page_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Firebase download URLs are governed by the bucket's security rules
+    /// rather than by a signature, so `expires_in` is not used here.
+    async fn signed_url(&self, key: &str, _expires_in: Duration) -> Result<String, Error> {
+        Ok(format!("{}?alt=media", self.object_url(key)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_name_encodes_percent_signs() {
+        assert_eq!(
+            FirebaseStore::object_name("content/1/100%.py"),
+            "content%2F1%2F100%25.py"
+        );
+    }
+}
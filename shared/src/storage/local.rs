@@ -0,0 +1,243 @@
+use std::env;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use bson::oid::ObjectId;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use log::{debug, info};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use super::blob_store::BlobStore;
+
+const DEFAULT_LOCAL_ROOT: &str = "storage";
+const TEMP_DIR: &str = ".tmp";
+
+/// Blobs stored as plain files under a root directory.
+///
+/// Keys of the form `<area>/<owner>/<name>` are sharded on disk as
+/// `<area>/<shard>/<owner>/<name>`, where `<shard>` is derived from the owner,
+/// so that no single directory ends up holding every owner. Writes go to a
+/// temporary file first and are renamed into place, so readers never observe
+/// a partially written blob.
+#[derive(Clone)]
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        LocalStore { root: root.into() }
+    }
+
+    /// Maps a key onto a path below the root, refusing anything that could
+    /// escape it or clash with the store's own temporary directory.
+    fn path_for(&self, key: &str) -> Result<PathBuf, Error> {
+        let segments: Vec<&str> = Path::new(key)
+            .components()
+            .map(|component| match component {
+                Component::Normal(segment) => segment.to_str().filter(|s| !s.starts_with('.')),
+                _ => None,
+            })
+            .collect::<Option<_>>()
+            .filter(|segments: &Vec<&str>| !segments.is_empty())
+            .ok_or_else(|| Error::msg(format!("Invalid storage key: {}", key)))?;
+
+        let mut path = self.root.clone();
+        if let [area, owner, rest @ ..] = segments.as_slice() {
+            if !rest.is_empty() {
+                path.push(area);
+                path.push(shard(owner));
+                path.push(owner);
+                path.extend(rest);
+                return Ok(path);
+            }
+        }
+        path.extend(segments);
+        Ok(path)
+    }
+
+    /// Inverse of `path_for`: turns a file below the root back into its key.
+    fn key_for(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let mut segments: Vec<String> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        if segments.len() > 3 {
+            segments.remove(1);
+        }
+        Some(segments.join("/"))
+    }
+
+    async fn collect_files(&self, keys: &mut Vec<String>) -> Result<(), Error> {
+        let mut pending = vec![self.root.clone()];
+        while let Some(dir) = pending.pop() {
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if entry.file_name() == TEMP_DIR {
+                    continue;
+                }
+                if entry.file_type().await?.is_dir() {
+                    pending.push(path);
+                } else if let Some(key) = self.key_for(&path) {
+                    keys.push(key);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Two hex digits taken from the hash of the owner segment.
+fn shard(owner: &str) -> String {
+    format!("{:02x}", Sha256::digest(owner.as_bytes())[0])
+}
+
+impl BlobStore for LocalStore {
+    async fn init() -> Result<Self> {
+        let root = env::var("STORAGE_LOCAL_ROOT")
+            .ok()
+            .filter(|root| !root.is_empty())
+            .unwrap_or_else(|| DEFAULT_LOCAL_ROOT.into());
+        fs::create_dir_all(Path::new(&root).join(TEMP_DIR)).await?;
+        info!("Local storage root: {}", root);
+        Ok(LocalStore::new(root))
+    }
+
+    async fn put(&self, key: &str, content_type: &str, data: Vec<u8>) -> Result<(), Error> {
+        let length = data.len() as u64;
+        let stream = futures::stream::once(async move { Ok(Bytes::from(data)) });
+        self.put_stream(key, content_type, length, stream).await
+    }
+
+    async fn put_stream<S>(
+        &self,
+        key: &str,
+        _content_type: &str,
+        _content_length: u64,
+        stream: S,
+    ) -> Result<(), Error>
+    where
+        S: Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static,
+    {
+        let path = self.path_for(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let temp_dir = self.root.join(TEMP_DIR);
+        fs::create_dir_all(&temp_dir).await?;
+        let temp_path = temp_dir.join(ObjectId::new().to_hex());
+
+        let written = async {
+            let mut file = fs::File::create(&temp_path).await?;
+            let mut stream = Box::pin(stream);
+            while let Some(chunk) = stream.next().await {
+                file.write_all(&chunk?).await?;
+            }
+            file.flush().await?;
+            fs::rename(&temp_path, &path).await
+        }
+        .await;
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp_path).await;
+            return Err(e.into());
+        }
+
+        debug!("File written to: {:?}", path);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
+        Ok(fs::read(self.path_for(key)?).await?)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        let path = self.path_for(key)?;
+        fs::remove_file(&path).await?;
+        info!("File deleted from: {:?}", path);
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, Error> {
+        Ok(fs::try_exists(self.path_for(key)?).await?)
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let mut keys = Vec::new();
+        self.collect_files(&mut keys).await?;
+        keys.retain(|key| key.starts_with(prefix));
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// There is nothing to sign on a local disk; the URL points at the file
+    /// itself. Clients are expected to go through the API to download it.
+    async fn signed_url(&self, key: &str, _expires_in: Duration) -> Result<String, Error> {
+        let path = self.path_for(key)?;
+        let root = fs::canonicalize(&self.root).await?;
+        let relative = path.strip_prefix(&self.root)?;
+        Ok(format!("file://{}", root.join(relative).to_string_lossy()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::runtime::Runtime;
+
+    fn temp_store(name: &str) -> LocalStore {
+        let root =
+            env::temp_dir().join(format!("content_crafters_{}_{}", name, std::process::id()));
+        LocalStore::new(root)
+    }
+
+    #[test]
+    fn test_put_get_delete_roundtrip() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let store = temp_store("roundtrip");
+            store
+                .put("content/1/a.py", "text/plain", b"print(1)".to_vec())
+                .await
+                .unwrap();
+
+            assert!(store.exists("content/1/a.py").await.unwrap());
+            assert!(store
+                .root
+                .join("content")
+                .join(shard("1"))
+                .join("1/a.py")
+                .exists());
+            assert_eq!(store.get("content/1/a.py").await.unwrap(), b"print(1)");
+            assert_eq!(
+                store.list("content/1/").await.unwrap(),
+                vec!["content/1/a.py".to_string()]
+            );
+
+            store.delete("content/1/a.py").await.unwrap();
+            assert!(!store.exists("content/1/a.py").await.unwrap());
+            fs::remove_dir_all(&store.root).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_rejects_keys_escaping_root() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let store = temp_store("escape");
+            assert!(store.get("../etc/passwd").await.is_err());
+            assert!(store.get("/etc/passwd").await.is_err());
+            assert!(store.get(".tmp/anything").await.is_err());
+        });
+    }
+}
@@ -0,0 +1,21 @@
+pub mod blob_store;
+pub mod firebase;
+pub mod local;
+pub mod s3;
+pub mod upload_expiry;
+
+/// Percent-encodes everything but RFC 3986 unreserved characters, optionally
+/// keeping `/` so that keys stay readable as paths.
+pub(crate) fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
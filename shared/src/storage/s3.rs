@@ -0,0 +1,383 @@
+use std::env;
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use hmac::{Hmac, Mac};
+use log::{debug, info};
+use reqwest::{Body, Client, Method, RequestBuilder, Response, StatusCode, Url};
+use sha2::{Digest, Sha256};
+
+use super::{blob_store::BlobStore, uri_encode};
+
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// Any S3-compatible object store (AWS S3, MinIO, Ceph, ...), addressed
+/// path-style and authenticated with AWS Signature Version 4.
+#[derive(Clone)]
+pub struct S3Store {
+    client: Client,
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+}
+
+impl S3Store {
+    pub fn new(
+        endpoint: Url,
+        bucket: String,
+        region: String,
+        access_key_id: String,
+        secret_access_key: String,
+    ) -> Self {
+        S3Store {
+            client: Client::new(),
+            endpoint,
+            bucket,
+            region,
+            access_key_id,
+            secret_access_key,
+        }
+    }
+
+    fn canonical_uri(&self, key: &str) -> String {
+        let base = self.endpoint.path().trim_end_matches('/');
+        if key.is_empty() {
+            format!("{}/{}", base, uri_encode(&self.bucket, true))
+        } else {
+            format!(
+                "{}/{}/{}",
+                base,
+                uri_encode(&self.bucket, true),
+                uri_encode(key, false)
+            )
+        }
+    }
+
+    fn host(&self) -> String {
+        let host = self.endpoint.host_str().unwrap_or_default();
+        match self.endpoint.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        }
+    }
+
+    fn credential_scope(&self, now: &DateTime<Utc>) -> String {
+        format!("{}/{}/s3/aws4_request", now.format("%Y%m%d"), self.region)
+    }
+
+    fn signature(&self, now: &DateTime<Utc>, canonical_request: &str) -> String {
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            now.format("%Y%m%dT%H%M%SZ"),
+            self.credential_scope(now),
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let date_key = hmac_sha256(
+            format!("AWS4{}", self.secret_access_key).as_bytes(),
+            now.format("%Y%m%d").to_string().as_bytes(),
+        );
+        let region_key = hmac_sha256(&date_key, self.region.as_bytes());
+        let service_key = hmac_sha256(&region_key, b"s3");
+        let signing_key = hmac_sha256(&service_key, b"aws4_request");
+
+        hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()))
+    }
+
+    /// Builds a request carrying an `Authorization` header for `key`.
+    fn signed_request(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, String)],
+        payload_hash: &str,
+    ) -> RequestBuilder {
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let canonical_uri = self.canonical_uri(key);
+        let canonical_query = canonical_query(query);
+        let host = self.host();
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method.as_str(),
+            canonical_uri,
+            canonical_query,
+            host,
+            payload_hash,
+            amz_date,
+            signed_headers,
+            payload_hash
+        );
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id,
+            self.credential_scope(&now),
+            signed_headers,
+            self.signature(&now, &canonical_request)
+        );
+
+        let mut url = self.endpoint.clone();
+        url.set_path(&canonical_uri);
+        url.set_query(if canonical_query.is_empty() {
+            None
+        } else {
+            Some(&canonical_query)
+        });
+
+        self.client
+            .request(method, url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("Authorization", authorization)
+    }
+
+    async fn error_from(action: &str, response: Response) -> Error {
+        let status = response.status();
+        let message = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Error::msg(format!("Error {} file ({}): {}", action, status, message))
+    }
+}
+
+impl BlobStore for S3Store {
+    async fn init() -> Result<Self> {
+        let bucket = env::var("S3_BUCKET").map_err(|_| Error::msg("S3_BUCKET must be set"))?;
+        let region = env::var("S3_REGION").unwrap_or_else(|_| DEFAULT_REGION.to_string());
+        let endpoint = env::var("S3_ENDPOINT")
+            .unwrap_or_else(|_| format!("https://s3.{}.amazonaws.com", region));
+        let access_key_id =
+            env::var("S3_ACCESS_KEY_ID").map_err(|_| Error::msg("S3_ACCESS_KEY_ID must be set"))?;
+        let secret_access_key = env::var("S3_SECRET_ACCESS_KEY")
+            .map_err(|_| Error::msg("S3_SECRET_ACCESS_KEY must be set"))?;
+
+        info!("S3 storage: {} (bucket {})", endpoint, bucket);
+        Ok(S3Store::new(
+            Url::parse(&endpoint)?,
+            bucket,
+            region,
+            access_key_id,
+            secret_access_key,
+        ))
+    }
+
+    async fn put(&self, key: &str, content_type: &str, data: Vec<u8>) -> Result<(), Error> {
+        let payload_hash = hex::encode(Sha256::digest(&data));
+        let response = self
+            .signed_request(Method::PUT, key, &[], &payload_hash)
+            .header("Content-Type", content_type)
+            .body(data)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Self::error_from("uploading", response).await);
+        }
+        debug!("File uploaded to: {:?}", key);
+        Ok(())
+    }
+
+    /// The payload is sent unsigned since hashing it up front would mean
+    /// reading the stream twice; S3 still needs the length in advance.
+    async fn put_stream<S>(
+        &self,
+        key: &str,
+        content_type: &str,
+        content_length: u64,
+        stream: S,
+    ) -> Result<(), Error>
+    where
+        S: Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static,
+    {
+        let response = self
+            .signed_request(Method::PUT, key, &[], UNSIGNED_PAYLOAD)
+            .header("Content-Type", content_type)
+            .header("Content-Length", content_length)
+            .body(Body::wrap_stream(stream))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Self::error_from("uploading", response).await);
+        }
+        debug!("File uploaded to: {:?}", key);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
+        let response = self
+            .signed_request(Method::GET, key, &[], UNSIGNED_PAYLOAD)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Self::error_from("downloading", response).await);
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        let response = self
+            .signed_request(Method::DELETE, key, &[], UNSIGNED_PAYLOAD)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Self::error_from("deleting", response).await);
+        }
+        info!("File deleted from: {:?}", key);
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, Error> {
+        let response = self
+            .signed_request(Method::HEAD, key, &[], UNSIGNED_PAYLOAD)
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(true),
+            StatusCode::NOT_FOUND => Ok(false),
+            _ => Err(Self::error_from("checking", response).await),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let mut keys = Vec::new();
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let mut query = vec![
+                ("list-type", "2".to_string()),
+                ("prefix", prefix.to_string()),
+            ];
+            if let Some(token) = &continuation_token {
+                query.push(("continuation-token", token.clone()));
+            }
+
+            let response = self
+                .signed_request(Method::GET, "", &query, UNSIGNED_PAYLOAD)
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return Err(Self::error_from("listing", response).await);
+            }
+
+            let body = response.text().await?;
+            keys.extend(xml_values(&body, "Key"));
+
+            continuation_token = xml_values(&body, "NextContinuationToken").pop();
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(keys)
+    }
+
+    async fn signed_url(&self, key: &str, expires_in: Duration) -> Result<String, Error> {
+        let now = Utc::now();
+        let canonical_uri = self.canonical_uri(key);
+        let query = [
+            ("X-Amz-Algorithm", "AWS4-HMAC-SHA256".to_string()),
+            (
+                "X-Amz-Credential",
+                format!("{}/{}", self.access_key_id, self.credential_scope(&now)),
+            ),
+            ("X-Amz-Date", now.format("%Y%m%dT%H%M%SZ").to_string()),
+            ("X-Amz-Expires", expires_in.as_secs().to_string()),
+            ("X-Amz-SignedHeaders", "host".to_string()),
+        ];
+        let canonical_query = canonical_query(&query);
+        let canonical_request = format!(
+            "GET\n{}\n{}\nhost:{}\n\nhost\n{}",
+            canonical_uri,
+            canonical_query,
+            self.host(),
+            UNSIGNED_PAYLOAD
+        );
+
+        let mut url = self.endpoint.clone();
+        url.set_path(&canonical_uri);
+        url.set_query(Some(&format!(
+            "{}&X-Amz-Signature={}",
+            canonical_query,
+            self.signature(&now, &canonical_request)
+        )));
+        Ok(url.to_string())
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn canonical_query(query: &[(&str, String)]) -> String {
+    let mut pairs: Vec<(String, String)> = query
+        .iter()
+        .map(|(name, value)| (uri_encode(name, true), uri_encode(value, true)))
+        .collect();
+    pairs.sort();
+    pairs
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Extracts the text of every `<tag>` element of an S3 XML response.
+fn xml_values(body: &str, tag: &str) -> Vec<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    body.split(&open)
+        .skip(1)
+        .filter_map(|rest| rest.split_once(&close).map(|(value, _)| value))
+        .map(|value| {
+            value
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xml_values_extracts_keys() {
+        let body = "<ListBucketResult><Contents><Key>content/1/a.py</Key></Contents>\
+                    <Contents><Key>content/1/b&amp;c.py</Key></Contents></ListBucketResult>";
+        assert_eq!(
+            xml_values(body, "Key"),
+            vec!["content/1/a.py".to_string(), "content/1/b&c.py".to_string()]
+        );
+        assert!(xml_values(body, "NextContinuationToken").is_empty());
+    }
+
+    #[test]
+    fn test_canonical_query_is_sorted_and_encoded() {
+        let query = [
+            ("prefix", "content/1 a".to_string()),
+            ("list-type", "2".to_string()),
+        ];
+        assert_eq!(
+            canonical_query(&query),
+            "list-type=2&prefix=content%2F1%20a"
+        );
+    }
+}
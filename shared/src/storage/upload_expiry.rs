@@ -0,0 +1,117 @@
+use std::env;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use log::{error, info, warn};
+
+use super::blob_store::{BlobStore, Storage};
+use crate::database::{
+    db_interface::DatabaseConnection, upload_session_repository::UploadSessionRepository,
+};
+use crate::models::upload_session::UploadSession;
+
+const DEFAULT_UPLOAD_SESSION_TTL_SECS: u64 = 24 * 60 * 60;
+const TICK: Duration = Duration::from_secs(10 * 60);
+
+/// How long an upload session may go without a chunk before it is dropped,
+/// from `UPLOAD_SESSION_TTL` (seconds).
+pub fn session_ttl() -> Duration {
+    let seconds = env::var("UPLOAD_SESSION_TTL")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_UPLOAD_SESSION_TTL_SECS);
+    Duration::from_secs(seconds)
+}
+
+/// Drops the upload sessions left alone for `session_ttl`, forever.
+///
+/// This is not left to a TTL index on the collection, which would delete
+/// the sessions but leave their parts in the storage backend.
+pub async fn run_upload_expiry(db: DatabaseConnection, storage: Storage) {
+    loop {
+        if let Err(e) = expire_sessions(&db, &storage, session_ttl()).await {
+            error!("Could not expire upload sessions: {:?}", e);
+        }
+        tokio::time::sleep(TICK).await;
+    }
+}
+
+/// Deletes the sessions not updated for `ttl` and their parts, and returns
+/// how many there were.
+pub async fn expire_sessions(
+    db: &DatabaseConnection,
+    storage: &Storage,
+    ttl: Duration,
+) -> Result<usize> {
+    let before = Utc::now() - chrono::Duration::from_std(ttl)?;
+    let mut expired = 0;
+    for session in db.find_stale_upload_sessions(before).await? {
+        // Parts go first: a session without parts may be deleted again, parts
+        // without a session would stay forever.
+        delete_parts(storage, &session).await;
+        if db.delete_upload_session(&session.id).await? {
+            expired += 1;
+        }
+    }
+    if expired > 0 {
+        info!("Expired {} upload sessions", expired);
+    }
+    Ok(expired)
+}
+
+/// Deletes the stored chunks of `session`; failures are only logged.
+pub async fn delete_parts(storage: &Storage, session: &UploadSession) {
+    for part in &session.parts {
+        if let Err(e) = storage.delete(&part.key).await {
+            warn!("Error deleting upload part {}: {:?}", part.key, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::mock_db::MockDb;
+    use crate::models::upload_session::{CreateUploadSession, UploadPart};
+    use crate::storage::local::LocalStore;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_stale_sessions_are_dropped_with_their_parts() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let root = tempfile::tempdir().unwrap();
+            let storage = Storage::Local(LocalStore::new(root.path()));
+            let db = DatabaseConnection::Mock(MockDb::default());
+
+            let mut session: UploadSession = CreateUploadSession {
+                owner_id: 1,
+                filename: "a.py".to_string(),
+                content_type: None,
+                output_extension: None,
+                upload_length: 2,
+            }
+            .into();
+            let part = UploadPart {
+                key: format!("uploads/{}/{:020}", session.id, 0),
+                offset: 0,
+                size: 2,
+            };
+            storage
+                .put(&part.key, "application/octet-stream", b"hi".to_vec())
+                .await
+                .unwrap();
+            session.parts.push(part.clone());
+            session.update_time = Utc::now() - chrono::Duration::hours(2);
+            db.insert_upload_session(&session).await.unwrap();
+
+            let ttl = Duration::from_secs(3 * 60 * 60);
+            assert_eq!(expire_sessions(&db, &storage, ttl).await.unwrap(), 0);
+            let ttl = Duration::from_secs(60 * 60);
+            assert_eq!(expire_sessions(&db, &storage, ttl).await.unwrap(), 1);
+            assert!(db.find_upload_session(&session.id).await.unwrap().is_none());
+            assert!(!storage.exists(&part.key).await.unwrap());
+        });
+    }
+}